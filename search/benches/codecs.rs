@@ -0,0 +1,102 @@
+// size and decode speed of the postings codecs, on blocks of doc ids
+// spread like the postings of rare, common and bursty terms
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use search::disk::{bits_reader::BitsReader, bits_writer::BitsWriter, codecs};
+use search::Result;
+use std::hint::black_box;
+use tempdir::TempDir;
+
+const BLOCK_SIZE: usize = 128;
+const NUM_BLOCKS: usize = 1000;
+
+type Write = fn(&mut BitsWriter, &[u32]) -> u64;
+type Read = fn(&mut BitsReader, usize, &mut Vec<u32>) -> Result<()>;
+
+fn write_gamma(writer: &mut BitsWriter, values: &[u32]) -> u64 {
+    values.iter().map(|v| writer.write_gamma(*v)).sum()
+}
+
+fn read_gamma(reader: &mut BitsReader, n: usize, values: &mut Vec<u32>) -> Result<()> {
+    for _ in 0..n {
+        values.push(reader.read_gamma()?);
+    }
+    Ok(())
+}
+
+// gap codecs take the gaps between ids, Elias-Fano the ids from the start of their block
+fn get_blocks(max_gap: u32, burst: bool, cumulative: bool) -> Vec<Vec<u32>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..NUM_BLOCKS)
+        .map(|_| {
+            let mut id = 0;
+            (0..BLOCK_SIZE)
+                .map(|i| {
+                    let gap = match burst && i % 32 != 0 {
+                        true => 1,
+                        false => rng.gen_range(1..=max_gap),
+                    };
+                    id += gap;
+                    match cumulative {
+                        true => id,
+                        false => gap,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let dir = TempDir::new("codecs").unwrap();
+    let codecs: [(&str, Write, Read, bool); 4] = [
+        ("gamma", write_gamma, read_gamma, false),
+        ("bitpack", codecs::write_packed, codecs::read_packed, false),
+        ("pfor", codecs::write_pfor, codecs::read_pfor, false),
+        (
+            "elias_fano",
+            codecs::write_elias_fano,
+            codecs::read_elias_fano,
+            true,
+        ),
+    ];
+
+    for (postings, max_gap, burst) in [
+        ("dense", 4, false),
+        ("sparse", 1000, false),
+        ("bursty", 1000, true),
+    ] {
+        let mut group = c.benchmark_group(format!("decode_{postings}"));
+        group.throughput(Throughput::Elements((NUM_BLOCKS * BLOCK_SIZE) as u64));
+
+        for (name, write, read, cumulative) in codecs {
+            let blocks = get_blocks(max_gap, burst, cumulative);
+            let path = dir.path().join(format!("{postings}_{name}"));
+            let path = path.to_string_lossy();
+
+            let mut writer = BitsWriter::new(&path).unwrap();
+            let len: u64 = blocks.iter().map(|b| write(&mut writer, b)).sum();
+            writer.flush().unwrap();
+            println!(
+                "{postings}/{name}: {:.2} bits per id",
+                len as f64 / (NUM_BLOCKS * BLOCK_SIZE) as f64
+            );
+
+            let mut values = Vec::with_capacity(BLOCK_SIZE);
+            group.bench_function(BenchmarkId::from_parameter(name), |b| {
+                b.iter(|| {
+                    let mut reader = BitsReader::new(&path).unwrap();
+                    for _ in 0..NUM_BLOCKS {
+                        values.clear();
+                        read(&mut reader, BLOCK_SIZE, &mut values).unwrap();
+                        black_box(&values);
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_codecs);
+criterion_main!(benches);
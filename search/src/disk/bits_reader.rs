@@ -1,51 +1,88 @@
-use std::{
-    fs::File,
-    io::{BufReader, Read, Seek, SeekFrom},
-};
+use crate::{Error, Result};
+use memmap2::Mmap;
+use std::{fs::File, sync::Arc};
 
 const BUFFER_SIZE: u32 = 128;
+const BUFFER_BYTES: usize = 16;
+// a valid gamma code never has more than 32 significant bits
+const MAX_GAMMA_LEN: u32 = 32;
+const MAX_VBYTE_BYTES: u32 = 5;
+
+// memory mapped file, shared by readers that decode it concurrently
+pub type BitsSource = Arc<Mmap>;
 
 pub struct BitsReader {
-    file: BufReader<File>,
+    source: BitsSource,
+    position: usize,
     buffer: u128,
-    byte_buffer: [u8; 16],
     read: u32,
 }
 
 impl BitsReader {
-    pub fn new(filename: &str) -> BitsReader {
+    pub fn new(filename: &str) -> Result<BitsReader> {
+        BitsReader::from_source(BitsReader::map_file(filename)?, 0)
+    }
+
+    pub fn map_file(filename: &str) -> Result<BitsSource> {
+        let file = File::open(filename).map_err(|e| Error::io(filename, e))?;
+
+        // SAFETY: index files are never modified in place, writers
+        // replace them with a rename, leaving existing mappings valid
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| Error::io(filename, e))?;
+        Ok(Arc::new(mmap))
+    }
+
+    pub fn from_source(source: BitsSource, bit_offset: u64) -> Result<BitsReader> {
         let mut r = BitsReader {
-            file: BufReader::new(File::open(filename).expect("can not open input file")),
+            source,
+            position: 0,
             buffer: 0,
-            byte_buffer: [0; 16],
             read: 0,
         };
-        r.fill_buffer();
-        r
+        r.seek(bit_offset)?;
+        Ok(r)
     }
 
-    pub fn read_gamma(&mut self) -> u32 {
-        let len = self.read_unary() - 1;
-        (self.read_internal(len) as u32 | (1 << len)) - 1
+    pub fn read_gamma(&mut self) -> Result<u32> {
+        let len = self.read_unary_within(MAX_GAMMA_LEN)? - 1;
+        if len >= MAX_GAMMA_LEN {
+            return Err(Error::corrupted("gamma code out of range"));
+        }
+
+        Ok((self.read_internal(len)? as u32 | (1 << len)) - 1)
     }
 
-    fn read_unary(&mut self) -> u32 {
-        let remaining = BUFFER_SIZE - self.read;
+    pub fn read_bits(&mut self, len: u32) -> Result<u32> {
+        Ok(self.read_internal(len)? as u32)
+    }
+
+    // the number of zeros before the next one
+    pub fn read_unary(&mut self) -> Result<u32> {
+        Ok(self.read_unary_within(u32::MAX)? - 1)
+    }
 
-        let zeros = self.buffer.trailing_zeros();
+    fn read_unary_within(&mut self, max_len: u32) -> Result<u32> {
+        let mut len = 0;
 
-        if zeros >= remaining {
-            self.fill_buffer();
-            return remaining + self.read_unary();
-        }
+        loop {
+            let remaining = BUFFER_SIZE - self.read;
+            let zeros = self.buffer.trailing_zeros();
 
-        self.buffer >>= zeros + 1;
-        self.read += zeros + 1;
+            if zeros < remaining {
+                self.buffer >>= zeros + 1;
+                self.read += zeros + 1;
+                return Ok(len + zeros + 1);
+            }
 
-        zeros + 1
+            len += remaining;
+            if len > max_len {
+                return Err(Error::corrupted("gamma code out of range"));
+            }
+            self.fill_buffer()?;
+        }
     }
 
-    pub fn read_vbyte(&mut self) -> u32 {
+    pub fn read_vbyte(&mut self) -> Result<u32> {
         let mut res = 0;
 
         let mask = (1 << 7) - 1;
@@ -53,36 +90,47 @@ impl BitsReader {
 
         let mut exit = false;
         while !exit {
-            let byte = self.read_internal(8);
+            if byte_num == MAX_VBYTE_BYTES {
+                return Err(Error::corrupted("vbyte code out of range"));
+            }
+
+            let byte = self.read_internal(8)?;
             res |= (byte & mask) << (7 * byte_num);
 
             byte_num += 1;
             exit = byte & (1 << 7) != 0;
         }
-        res as u32 - 1
+
+        u32::try_from(res)
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .ok_or_else(|| Error::corrupted("vbyte code out of range"))
     }
 
-    pub fn read_vbyte_gamma_gap_vector(&mut self) -> Vec<u32> {
-        let mut prefix = 0;
-        (0..self.read_vbyte())
+    pub fn read_vbyte_gamma_gap_vector(&mut self) -> Result<Vec<u32>> {
+        let mut prefix: u32 = 0;
+        (0..self.read_vbyte()?)
             .map(|_| {
-                let gap = self.read_gamma();
-                prefix += gap;
-                prefix
+                let gap = self.read_gamma()?;
+                prefix = prefix
+                    .checked_add(gap)
+                    .ok_or_else(|| Error::corrupted("gap vector out of range"))?;
+                Ok(prefix)
             })
             .collect()
     }
 
-    pub fn read_str(&mut self) -> String {
-        String::from_utf8(
-            (0..self.read_gamma())
-                .map(|_| self.read_internal(8) as u8)
-                .collect(),
-        )
-        .unwrap()
+    pub fn read_str(&mut self) -> Result<String> {
+        String::from_utf8(self.read_bytes()?).map_err(|_| Error::corrupted("invalid UTF-8 string"))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        (0..self.read_gamma()?)
+            .map(|_| Ok(self.read_internal(8)? as u8))
+            .collect()
     }
 
-    fn read_internal(&mut self, len: u32) -> u128 {
+    fn read_internal(&mut self, len: u32) -> Result<u128> {
         let mask = (1 << len) - 1;
 
         let remaining = BUFFER_SIZE - self.read;
@@ -91,39 +139,48 @@ impl BitsReader {
         self.buffer >>= len;
 
         if remaining <= len {
-            self.fill_buffer();
+            self.fill_buffer()?;
 
             let delta = len - remaining;
-            res |= self.read_internal(delta) << remaining;
+            res |= self.read_internal(delta)? << remaining;
 
-            return res;
+            return Ok(res);
         }
 
         self.read += len;
-        res
+        Ok(res)
     }
 
-    fn fill_buffer(&mut self) {
-        self.file
-            .read_exact(&mut self.byte_buffer)
-            .expect("error while filling byte buffer");
+    fn fill_buffer(&mut self) -> Result<()> {
+        // writers pad files to whole buffers, a partial one means a truncated file
+        let bytes: [u8; BUFFER_BYTES] = self
+            .source
+            .get(self.position..self.position + BUFFER_BYTES)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| Error::corrupted("unexpected end of file"))?;
 
-        self.buffer = u128::from_le_bytes(self.byte_buffer);
+        self.buffer = u128::from_le_bytes(bytes);
+        self.position += BUFFER_BYTES;
         self.read = 0;
+        Ok(())
     }
 
-    pub fn seek(&mut self, bit_offset: u64) {
+    pub fn get_position(&self) -> u64 {
+        // the buffer holds the last bytes read from the source
+        self.position as u64 * 8 - (BUFFER_SIZE - self.read) as u64
+    }
+
+    pub fn seek(&mut self, bit_offset: u64) -> Result<()> {
         let byte_seek = bit_offset / 8;
         let remainder_seek = bit_offset % 8;
 
-        self.file
-            .seek(SeekFrom::Start(byte_seek))
-            .expect("error while seeking reader");
+        self.position = byte_seek as usize;
 
-        self.fill_buffer();
+        self.fill_buffer()?;
         if remainder_seek > 0 {
-            self.read_internal(remainder_seek as u32);
+            self.read_internal(remainder_seek as u32)?;
         }
+        Ok(())
     }
 }
 
@@ -136,7 +193,7 @@ mod tests {
     fn test_read() {
         let test_output_path = create_temporary_file_path("test_read");
 
-        let mut w = BitsWriter::new(&test_output_path);
+        let mut w = BitsWriter::new(&test_output_path).unwrap();
 
         (1..100).for_each(|i| {
             w.write_vbyte(i);
@@ -153,34 +210,111 @@ mod tests {
 
         w.write_str("hello");
         w.write_str("");
+        w.write_bytes(&[0, 255, 7]);
+
+        w.write_bits(5, 3);
+        w.write_bits(u32::MAX, 32);
+        w.write_bits(0, 0);
+        w.write_unary(0);
+        w.write_unary(100);
+        w.write_bits(1, 1);
 
-        w.flush();
+        w.flush().unwrap();
 
-        let mut r = BitsReader::new(&test_output_path);
+        let mut r = BitsReader::new(&test_output_path).unwrap();
 
-        (1..100).for_each(|i| assert_eq!(i, r.read_vbyte()));
-        (1..100).for_each(|i| assert_eq!(i, r.read_gamma()));
+        (1..100).for_each(|i| assert_eq!(i, r.read_vbyte().unwrap()));
+        (1..100).for_each(|i| assert_eq!(i, r.read_gamma().unwrap()));
 
-        assert_eq!(r.read_vbyte_gamma_gap_vector(), [1, 3, 6]);
+        assert_eq!(r.read_vbyte_gamma_gap_vector().unwrap(), [1, 3, 6]);
 
-        assert_eq!(r.read_str(), "hello");
-        assert_eq!(r.read_str(), "");
+        assert_eq!(r.read_str().unwrap(), "hello");
+        assert_eq!(r.read_str().unwrap(), "");
+        assert_eq!(r.read_bytes().unwrap(), [0, 255, 7]);
+
+        assert_eq!(r.read_bits(3).unwrap(), 5);
+        assert_eq!(r.read_bits(32).unwrap(), u32::MAX);
+        assert_eq!(r.read_bits(0).unwrap(), 0);
+        assert_eq!(r.read_unary().unwrap(), 0);
+        assert_eq!(r.read_unary().unwrap(), 100);
+        assert_eq!(r.read_bits(1).unwrap(), 1);
     }
 
     #[test]
     fn test_seek() {
         let test_output_path = create_temporary_file_path("test_seek");
 
-        let mut w = BitsWriter::new(&test_output_path);
+        let mut w = BitsWriter::new(&test_output_path).unwrap();
 
         let offset = (0..1000).map(|i| w.write_gamma(i)).sum();
         w.write_gamma(10);
+        w.write_vbyte(300);
+
+        w.flush().unwrap();
+
+        // code lengths are known without writing them
+        assert_eq!(
+            (0..1000).map(BitsWriter::get_gamma_len).sum::<u64>(),
+            offset
+        );
+
+        let mut r = BitsReader::new(&test_output_path).unwrap();
+
+        r.seek(offset).unwrap();
+        assert_eq!(r.get_position(), offset);
+        assert_eq!(r.read_gamma().unwrap(), 10);
+        assert_eq!(r.get_position(), offset + BitsWriter::get_gamma_len(10));
+        assert_eq!(r.read_vbyte().unwrap(), 300);
+        assert_eq!(
+            r.get_position(),
+            offset + BitsWriter::get_gamma_len(10) + BitsWriter::get_vbyte_len(300)
+        );
+    }
 
-        w.flush();
+    #[test]
+    fn test_shared_source() {
+        let test_output_path = create_temporary_file_path("test_shared_source");
 
-        let mut r = BitsReader::new(&test_output_path);
+        let mut w = BitsWriter::new(&test_output_path).unwrap();
+        let offset = (0..100).map(|i| w.write_gamma(i)).sum();
+        w.write_gamma(10);
+        w.flush().unwrap();
 
-        r.seek(offset);
-        assert_eq!(r.read_gamma(), 10);
+        let source = BitsReader::map_file(&test_output_path).unwrap();
+
+        let mut r1 = BitsReader::from_source(source.clone(), 0).unwrap();
+        let mut r2 = BitsReader::from_source(source, offset).unwrap();
+
+        assert_eq!(r2.read_gamma().unwrap(), 10);
+        (0..100).for_each(|i| assert_eq!(i, r1.read_gamma().unwrap()));
+    }
+
+    #[test]
+    fn test_damaged_input() {
+        let test_output_path = create_temporary_file_path("test_damaged_input");
+
+        assert!(BitsReader::new(&test_output_path).is_err());
+
+        let mut w = BitsWriter::new(&test_output_path).unwrap();
+        (0..100).for_each(|i| {
+            w.write_str(&format!("string {i}"));
+        });
+        w.flush().unwrap();
+
+        // truncated files end with an error instead of a panic
+        let bytes = std::fs::read(&test_output_path).unwrap();
+        std::fs::write(&test_output_path, &bytes[..bytes.len() / 2 + 3]).unwrap();
+        let mut r = BitsReader::new(&test_output_path).unwrap();
+        let strings: Result<Vec<String>> = (0..100).map(|_| r.read_str()).collect();
+        assert!(strings.is_err());
+
+        // as do codes that no writer produces
+        std::fs::write(&test_output_path, [0; 64]).unwrap();
+        let mut r = BitsReader::new(&test_output_path).unwrap();
+        assert!(r.read_gamma().is_err());
+
+        std::fs::write(&test_output_path, [0; 16]).unwrap();
+        let mut r = BitsReader::new(&test_output_path).unwrap();
+        assert!(r.read_vbyte().is_err());
     }
 }
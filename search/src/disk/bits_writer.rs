@@ -1,23 +1,37 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
 };
 
 use super::file_utils;
+use crate::{Error, Result};
 
 pub struct BitsWriter {
     file: BufWriter<File>,
+    path: String,
     buffer: u128,
     written: u32,
+    // the first write error, returned by flush
+    error: Option<io::Error>,
 }
 
 impl BitsWriter {
-    pub fn new(path: &str) -> BitsWriter {
-        BitsWriter {
-            file: BufWriter::new(file_utils::create_and_open_file(path)),
+    pub fn new(path: &str) -> Result<BitsWriter> {
+        // write to a temporary file, renamed on flush, so that readers
+        // mapping the previous version of the file are not affected
+        Ok(BitsWriter {
+            file: BufWriter::new(file_utils::create_and_open_file(
+                &BitsWriter::get_temporary_path(path),
+            )?),
+            path: path.to_string(),
             buffer: 0,
             written: 0,
-        }
+            error: None,
+        })
+    }
+
+    fn get_temporary_path(path: &str) -> String {
+        format!("{path}.tmp")
     }
 
     pub fn write_gamma(&mut self, n: u32) -> u64 {
@@ -25,6 +39,15 @@ impl BitsWriter {
         self.write_internal(gamma, len)
     }
 
+    // lengths in bits of the codes, known before writing them
+    pub fn get_gamma_len(n: u32) -> u64 {
+        BitsWriter::int_to_gamma(n + 1).1 as u64
+    }
+
+    pub fn get_vbyte_len(n: u32) -> u64 {
+        BitsWriter::int_to_vbyte(n + 1).1 as u64
+    }
+
     fn int_to_gamma(n: u32) -> (u128, u32) {
         let msb = 31 - n.leading_zeros();
         let unary: u32 = 1 << msb;
@@ -54,9 +77,28 @@ impl BitsWriter {
         (vbyte, 8 * byte_num)
     }
 
+    // the lowest len bits of n, for fixed width codes
+    pub fn write_bits(&mut self, n: u32, len: u32) -> u64 {
+        self.write_internal(n as u128 & ((1 << len) - 1), len)
+    }
+
+    // n zeros followed by a one
+    pub fn write_unary(&mut self, mut n: u32) -> u64 {
+        let mut written = 0;
+        while n >= 32 {
+            written += self.write_internal(0, 32);
+            n -= 32;
+        }
+        written + self.write_internal(1 << n, n + 1)
+    }
+
     pub fn write_str(&mut self, s: &str) -> u64 {
-        self.write_gamma(s.len() as u32)
-            + s.as_bytes()
+        self.write_bytes(s.as_bytes())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> u64 {
+        self.write_gamma(bytes.len() as u32)
+            + bytes
                 .iter()
                 .map(|b| self.write_internal(*b as u128, 8))
                 .sum::<u64>()
@@ -80,23 +122,30 @@ impl BitsWriter {
     }
 
     fn update_buffer(&mut self) {
-        self.file
-            .write_all(&self.buffer.to_le_bytes())
-            .expect("error while writing buffer to BufWriter");
+        if self.error.is_none() {
+            self.error = self.file.write_all(&self.buffer.to_le_bytes()).err();
+        }
 
         self.buffer = 0;
         self.written = 0;
     }
 
-    pub fn flush(&mut self) {
+    pub fn flush(&mut self) -> Result<()> {
         if self.written != 0 {
             self.update_buffer();
         }
 
         self.update_buffer();
+
+        let temporary_path = BitsWriter::get_temporary_path(&self.path);
+        if let Some(err) = self.error.take() {
+            return Err(Error::io(&temporary_path, err));
+        }
         self.file
             .flush()
-            .expect("error while flushing BufWriter buffer");
+            .map_err(|e| Error::io(&temporary_path, e))?;
+
+        fs::rename(&temporary_path, &self.path).map_err(|e| Error::io(&self.path, e))
     }
 }
 
@@ -132,7 +181,7 @@ mod test {
         let word = (1 << 10) - 1;
         let len = 10;
 
-        let mut w = BitsWriter::new(&create_temporary_file_path("overflow"));
+        let mut w = BitsWriter::new(&create_temporary_file_path("overflow")).unwrap();
         w.written = 125;
 
         w.write_internal(word, len);
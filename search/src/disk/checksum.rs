@@ -0,0 +1,121 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+};
+
+// CRC-32 with the IEEE polynomial, in its reflected form
+const POLYNOMIAL: u32 = 0xedb8_8320;
+const READ_BUFFER_SIZE: usize = 1 << 16;
+
+// lookup table of the checksum of every byte, built at compile time
+const TABLE: [u32; 256] = build_table();
+
+pub struct Crc32 {
+    value: u32,
+}
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                POLYNOMIAL ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+
+    table
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { value: u32::MAX }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.value = TABLE[((self.value ^ *b as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+pub fn checksum_file(path: &str) -> io::Result<(u64, u32)> {
+    // files are streamed, index files can be larger than memory
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    let mut crc = Crc32::new();
+    let mut len = 0;
+
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok((len, crc.finish()));
+        }
+        crc.update(&buffer[..n]);
+        len += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disk::file_utils, test_utils::utils::create_temporary_file_path};
+    use std::io::Write;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            checksum(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+
+        // checksums can be computed a piece at a time
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_checksum_file() {
+        let path = create_temporary_file_path("checksum_unit");
+        let content: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        file_utils::create_and_open_file(&path)
+            .unwrap()
+            .write_all(&content)
+            .unwrap();
+
+        assert_eq!(
+            checksum_file(&path).unwrap(),
+            (content.len() as u64, checksum(&content))
+        );
+        assert!(checksum_file(&(path + ".missing")).is_err());
+    }
+}
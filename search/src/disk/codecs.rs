@@ -0,0 +1,262 @@
+// codecs for blocks of integers, bit aligned so that they can be
+// written next to gamma and vbyte codes in the same stream
+use super::{bits_reader::BitsReader, bits_writer::BitsWriter};
+use crate::{Error, Result};
+
+// widths go from 0 to 32 bits
+const WIDTH_BITS: u32 = 6;
+
+// every value takes the width of the largest one, bit aligned and read value by
+// value, rather than word aligned and unpacked a word at a time as in SIMD-BP128
+pub fn write_packed(writer: &mut BitsWriter, values: &[u32]) -> u64 {
+    let width = get_max_width(values);
+    writer.write_bits(width, WIDTH_BITS)
+        + values
+            .iter()
+            .map(|v| writer.write_bits(*v, width))
+            .sum::<u64>()
+}
+
+pub fn get_packed_len(values: &[u32]) -> u64 {
+    WIDTH_BITS as u64 + values.len() as u64 * get_max_width(values) as u64
+}
+
+pub fn read_packed(reader: &mut BitsReader, n: usize, values: &mut Vec<u32>) -> Result<()> {
+    let width = reader.read_bits(WIDTH_BITS)?;
+    for _ in 0..n {
+        values.push(reader.read_bits(width)?);
+    }
+    Ok(())
+}
+
+// PForDelta: values take the width that makes the block the smallest, and
+// the few that do not fit are patched afterwards with their high bits
+pub fn write_pfor(writer: &mut BitsWriter, values: &[u32]) -> u64 {
+    let width = get_pfor_width(values);
+    let mut written = writer.write_bits(width, WIDTH_BITS);
+    written += values
+        .iter()
+        .map(|v| writer.write_bits(*v, width))
+        .sum::<u64>();
+
+    let exceptions = get_exceptions(values, width);
+    written += writer.write_gamma(exceptions.len() as u32);
+    for (gap, high) in exceptions {
+        written += writer.write_gamma(gap) + writer.write_gamma(high);
+    }
+    written
+}
+
+pub fn get_pfor_len(values: &[u32]) -> u64 {
+    get_pfor_width_len(values, get_pfor_width(values))
+}
+
+pub fn read_pfor(reader: &mut BitsReader, n: usize, values: &mut Vec<u32>) -> Result<()> {
+    let width = reader.read_bits(WIDTH_BITS)?;
+    let start = values.len();
+    for _ in 0..n {
+        values.push(reader.read_bits(width)?);
+    }
+
+    let mut i = start;
+    for _ in 0..reader.read_gamma()? {
+        i += reader.read_gamma()? as usize;
+        let high = reader.read_gamma()? + 1;
+        let value = values.get_mut(i).ok_or_else(|| corrupted("PForDelta"))?;
+        *value |= high
+            .checked_shl(width)
+            .ok_or_else(|| corrupted("PForDelta"))?;
+        i += 1;
+    }
+    Ok(())
+}
+
+fn get_pfor_width(values: &[u32]) -> u32 {
+    (0..=get_max_width(values))
+        .min_by_key(|w| get_pfor_width_len(values, *w))
+        .unwrap_or_default()
+}
+
+fn get_pfor_width_len(values: &[u32], width: u32) -> u64 {
+    let exceptions = get_exceptions(values, width);
+    WIDTH_BITS as u64
+        + values.len() as u64 * width as u64
+        + BitsWriter::get_gamma_len(exceptions.len() as u32)
+        + exceptions
+            .iter()
+            .map(|(gap, high)| BitsWriter::get_gamma_len(*gap) + BitsWriter::get_gamma_len(*high))
+            .sum::<u64>()
+}
+
+fn get_exceptions(values: &[u32], width: u32) -> Vec<(u32, u32)> {
+    // the distance from the previous exception, and the bits over the width less one
+    let mut next = 0;
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| get_width(**v) > width)
+        .map(|(i, v)| {
+            let gap = (i - next) as u32;
+            next = i + 1;
+            (gap, (v >> width) - 1)
+        })
+        .collect()
+}
+
+// Elias-Fano, for non decreasing values: the low bits of every value are
+// written as they are, the high ones in unary as gaps from the previous value
+pub fn write_elias_fano(writer: &mut BitsWriter, values: &[u32]) -> u64 {
+    let max = values.last().copied().unwrap_or_default();
+    let low_width = get_low_width(max, values.len());
+
+    let mut written = writer.write_gamma(max);
+    written += values
+        .iter()
+        .map(|v| writer.write_bits(*v, low_width))
+        .sum::<u64>();
+
+    let mut prev_high = 0;
+    for v in values {
+        let high = v >> low_width;
+        written += writer.write_unary(high - prev_high);
+        prev_high = high;
+    }
+    written
+}
+
+pub fn get_elias_fano_len(values: &[u32]) -> u64 {
+    let max = values.last().copied().unwrap_or_default();
+    let low_width = get_low_width(max, values.len());
+
+    // one bit per value, plus a zero each time the high bits grow
+    BitsWriter::get_gamma_len(max)
+        + values.len() as u64 * (low_width as u64 + 1)
+        + (max >> low_width) as u64
+}
+
+pub fn read_elias_fano(reader: &mut BitsReader, n: usize, values: &mut Vec<u32>) -> Result<()> {
+    let max = reader.read_gamma()?;
+    let low_width = get_low_width(max, n);
+
+    let start = values.len();
+    for _ in 0..n {
+        values.push(reader.read_bits(low_width)?);
+    }
+
+    let mut high: u32 = 0;
+    for value in &mut values[start..] {
+        high = high
+            .checked_add(reader.read_unary()?)
+            .filter(|h| *h <= max >> low_width)
+            .ok_or_else(|| corrupted("Elias-Fano"))?;
+        *value |= high << low_width;
+    }
+    Ok(())
+}
+
+fn get_low_width(max: u32, n: usize) -> u32 {
+    match max.checked_div(n as u32) {
+        Some(ratio) if ratio > 0 => ratio.ilog2(),
+        _ => 0,
+    }
+}
+
+fn get_width(n: u32) -> u32 {
+    32 - n.leading_zeros()
+}
+
+fn get_max_width(values: &[u32]) -> u32 {
+    values
+        .iter()
+        .map(|v| get_width(*v))
+        .max()
+        .unwrap_or_default()
+}
+
+fn corrupted(codec: &str) -> Error {
+    Error::corrupted(&format!("{codec} block out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    type Write = fn(&mut BitsWriter, &[u32]) -> u64;
+    type Len = fn(&[u32]) -> u64;
+    type Read = fn(&mut BitsReader, usize, &mut Vec<u32>) -> Result<()>;
+
+    fn check_codec(name: &str, write: Write, get_len: Len, read: Read, sequences: &[Vec<u32>]) {
+        let path = create_temporary_file_path(name);
+        let mut writer = BitsWriter::new(&path).unwrap();
+
+        // lengths are known before writing, and blocks end where the next one starts
+        for values in sequences {
+            assert_eq!(write(&mut writer, values), get_len(values));
+        }
+        writer.write_gamma(42);
+        writer.flush().unwrap();
+
+        let mut reader = BitsReader::new(&path).unwrap();
+        for values in sequences {
+            let mut decoded = vec![7];
+            read(&mut reader, values.len(), &mut decoded).unwrap();
+            assert_eq!(decoded[1..], values[..]);
+        }
+        assert_eq!(reader.read_gamma().unwrap(), 42);
+    }
+
+    fn get_sequences() -> Vec<Vec<u32>> {
+        vec![
+            vec![],
+            vec![0],
+            vec![5, 5, 5],
+            (0..128).map(|i| i * 3).collect(),
+            (0..128).map(|i| i * i).collect(),
+            vec![0, 1, 2, 1000, 1001, 70000, 70002, u32::MAX / 2],
+            vec![u32::MAX - 1],
+        ]
+    }
+
+    #[test]
+    fn test_packed() {
+        check_codec(
+            "codec_packed",
+            write_packed,
+            get_packed_len,
+            read_packed,
+            &get_sequences(),
+        );
+    }
+
+    #[test]
+    fn test_pfor() {
+        check_codec(
+            "codec_pfor",
+            write_pfor,
+            get_pfor_len,
+            read_pfor,
+            &get_sequences(),
+        );
+
+        // a single large value is patched instead of widening every other one
+        let mut values = vec![1; 127];
+        values.push(1 << 20);
+        assert!(get_pfor_len(&values) < get_packed_len(&values) / 4);
+    }
+
+    #[test]
+    fn test_elias_fano() {
+        check_codec(
+            "codec_elias_fano",
+            write_elias_fano,
+            get_elias_fano_len,
+            read_elias_fano,
+            &get_sequences(),
+        );
+
+        // ids take two bits over the log of their average gap
+        let values: Vec<u32> = (0..128).map(|i| i * 4).collect();
+        assert!(get_elias_fano_len(&values) < 128 * 5);
+    }
+}
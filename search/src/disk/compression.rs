@@ -0,0 +1,161 @@
+// LZ77 compression in the style of LZ4: a sequence of literals followed by a
+// back reference to a match in the previous 64 KiB, byte aligned and fast to decode
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 14;
+const NIBBLE_MAX: usize = 15;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    // last position of each hashed 4 bytes sequence
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+
+    let mut anchor = 0;
+    let mut i = 0;
+
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..i + MIN_MATCH]);
+        let candidate = table[h];
+        table[h] = i;
+
+        if candidate != usize::MAX
+            && i - candidate <= MAX_OFFSET
+            && input[candidate..candidate + MIN_MATCH] == input[i..i + MIN_MATCH]
+        {
+            let mut len = MIN_MATCH;
+            while i + len < input.len() && input[candidate + len] == input[i + len] {
+                len += 1;
+            }
+
+            write_sequence(&mut output, &input[anchor..i], Some((i - candidate, len)));
+            i += len;
+            anchor = i;
+        } else {
+            i += 1;
+        }
+    }
+
+    // the last sequence only holds literals
+    write_sequence(&mut output, &input[anchor..], None);
+    output
+}
+
+pub fn decompress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() * 2);
+    let mut i = 0;
+
+    while i < input.len() {
+        let token = input[i] as usize;
+        i += 1;
+
+        let literals_len = read_length(input, &mut i, token >> 4);
+        output.extend_from_slice(&input[i..i + literals_len]);
+        i += literals_len;
+
+        if i >= input.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([input[i], input[i + 1]]) as usize;
+        i += 2;
+        let match_len = read_length(input, &mut i, token & NIBBLE_MAX) + MIN_MATCH;
+
+        // matches can overlap the bytes they produce, so they are copied one at a time
+        let start = output.len() - offset;
+        for j in 0..match_len {
+            output.push(output[start + j]);
+        }
+    }
+
+    output
+}
+
+fn hash(sequence: &[u8]) -> usize {
+    let v = u32::from_le_bytes([sequence[0], sequence[1], sequence[2], sequence[3]]);
+    (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+
+    let token = (literals.len().min(NIBBLE_MAX) << 4) | match_len.min(NIBBLE_MAX);
+    output.push(token as u8);
+    write_length(output, literals.len());
+    output.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(output, match_len);
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, len: usize) {
+    // lengths not fitting the token nibble continue in bytes, until one below 255
+    if len < NIBBLE_MAX {
+        return;
+    }
+
+    let mut remaining = len - NIBBLE_MAX;
+    while remaining >= 255 {
+        output.push(255);
+        remaining -= 255;
+    }
+    output.push(remaining as u8);
+}
+
+fn read_length(input: &[u8], i: &mut usize, nibble: usize) -> usize {
+    let mut len = nibble;
+
+    if nibble == NIBBLE_MAX {
+        loop {
+            let b = input[*i];
+            *i += 1;
+            len += b as usize;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_compress_text() {
+        let text = "the quick brown fox jumps over the lazy dog, ".repeat(50);
+
+        let compressed = compress(text.as_bytes());
+        assert!(compressed.len() < text.len() / 10);
+        assert_eq!(decompress(&compressed), text.as_bytes());
+    }
+
+    #[test]
+    fn test_compress_edge_cases() {
+        for input in [
+            &b""[..],
+            b"a",
+            b"abc",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        ] {
+            assert_eq!(decompress(&compress(input)), input);
+        }
+
+        // long literal runs and long matches use extended lengths
+        let mut input: Vec<u8> = (0..=255).cycle().take(300).collect();
+        input.extend(vec![7; 1000]);
+        assert_eq!(decompress(&compress(&input)), input);
+    }
+
+    #[test]
+    fn test_compress_random() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let input: Vec<u8> = (0..100_000).map(|_| rng.gen_range(0..8)).collect();
+
+        assert_eq!(decompress(&compress(&input)), input);
+    }
+}
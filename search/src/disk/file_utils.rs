@@ -4,11 +4,15 @@ use std::{
 };
 use walkdir::{DirEntry, WalkDir};
 
-pub fn create_and_open_file(file_path: &str) -> File {
+use crate::{Error, Result};
+
+pub fn create_and_open_file(file_path: &str) -> Result<File> {
     let path = Path::new(file_path);
-    path.parent().map(create_dir_all);
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| Error::io(file_path, e))?;
+    }
 
-    File::create(path).expect("error while creating file")
+    File::create(path).map_err(|e| Error::io(file_path, e))
 }
 
 pub fn walk_dir(input_dir: &str) -> Vec<DirEntry> {
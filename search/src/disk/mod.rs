@@ -1,3 +1,6 @@
 pub mod bits_reader;
 pub mod bits_writer;
+pub mod checksum;
+pub mod codecs;
+pub mod compression;
 pub mod file_utils;
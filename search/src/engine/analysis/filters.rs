@@ -0,0 +1,320 @@
+use super::{Language, Token, TokenFilter};
+use fxhash::{FxHashMap, FxHashSet};
+use rust_stemmers::Stemmer;
+
+pub const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+pub struct LowercaseFilter;
+
+pub struct AsciiFoldingFilter;
+
+pub struct StopWordsFilter {
+    stop_words: FxHashSet<String>,
+}
+
+pub struct StemmerFilter {
+    stemmer: Stemmer,
+}
+
+pub struct NGramFilter {
+    min_len: usize,
+    max_len: usize,
+}
+
+pub struct SynonymsFilter {
+    groups: Vec<Vec<String>>,
+    group_index_map: FxHashMap<String, usize>,
+}
+
+impl TokenFilter for LowercaseFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|mut t| {
+                t.text = t.text.to_lowercase();
+                t
+            })
+            .collect()
+    }
+
+    fn normalize(&self, text: String) -> String {
+        text.to_lowercase()
+    }
+}
+
+impl AsciiFoldingFilter {
+    fn fold_char(c: char) -> Option<&'static str> {
+        let folded = match c {
+            'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+            'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+            'Æ' => "AE",
+            'æ' => "ae",
+            'Ç' | 'Ć' | 'Č' => "C",
+            'ç' | 'ć' | 'č' => "c",
+            'Ð' | 'Ď' | 'Đ' => "D",
+            'ð' | 'ď' | 'đ' => "d",
+            'È'..='Ë' | 'Ē' | 'Ė' | 'Ę' | 'Ě' => "E",
+            'è'..='ë' | 'ē' | 'ė' | 'ę' | 'ě' => "e",
+            'Ğ' => "G",
+            'ğ' => "g",
+            'Ì'..='Ï' | 'Ī' | 'İ' => "I",
+            'ì'..='ï' | 'ī' | 'ı' => "i",
+            'Ł' => "L",
+            'ł' => "l",
+            'Ñ' | 'Ń' | 'Ň' => "N",
+            'ñ' | 'ń' | 'ň' => "n",
+            'Ò'..='Ö' | 'Ø' | 'Ō' | 'Ő' => "O",
+            'ò'..='ö' | 'ø' | 'ō' | 'ő' => "o",
+            'Œ' => "OE",
+            'œ' => "oe",
+            'Ř' => "R",
+            'ř' => "r",
+            'Ś' | 'Ş' | 'Š' => "S",
+            'ś' | 'ş' | 'š' => "s",
+            'ß' => "ss",
+            'Ť' => "T",
+            'ť' => "t",
+            'Þ' => "TH",
+            'þ' => "th",
+            'Ù'..='Ü' | 'Ū' | 'Ů' | 'Ű' => "U",
+            'ù'..='ü' | 'ū' | 'ů' | 'ű' => "u",
+            'Ý' | 'Ÿ' => "Y",
+            'ý' | 'ÿ' => "y",
+            'Ź' | 'Ż' | 'Ž' => "Z",
+            'ź' | 'ż' | 'ž' => "z",
+            _ => return None,
+        };
+
+        Some(folded)
+    }
+
+    fn fold(text: String) -> String {
+        if text.is_ascii() {
+            return text;
+        }
+
+        let mut folded = String::with_capacity(text.len());
+        for c in text.chars() {
+            match Self::fold_char(c) {
+                Some(f) => folded.push_str(f),
+                None => folded.push(c),
+            }
+        }
+        folded
+    }
+}
+
+impl TokenFilter for AsciiFoldingFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|mut t| {
+                t.text = Self::fold(t.text);
+                t
+            })
+            .collect()
+    }
+
+    fn normalize(&self, text: String) -> String {
+        Self::fold(text)
+    }
+}
+
+impl StopWordsFilter {
+    pub fn new(stop_words: &[String]) -> StopWordsFilter {
+        StopWordsFilter {
+            stop_words: stop_words.iter().cloned().collect(),
+        }
+    }
+}
+
+impl TokenFilter for StopWordsFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        // removed tokens leave a gap in positions, so phrases do not match across them
+        tokens
+            .into_iter()
+            .filter(|t| !self.stop_words.contains(&t.text))
+            .collect()
+    }
+}
+
+impl StemmerFilter {
+    pub fn new(language: Language) -> StemmerFilter {
+        StemmerFilter {
+            stemmer: Stemmer::create(language.get_algorithm()),
+        }
+    }
+}
+
+impl TokenFilter for StemmerFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .map(|mut t| {
+                t.text = self.stemmer.stem(&t.text).to_string();
+                t
+            })
+            .collect()
+    }
+}
+
+impl NGramFilter {
+    pub fn new(min_len: u32, max_len: u32) -> NGramFilter {
+        NGramFilter {
+            min_len: min_len.max(1) as usize,
+            max_len: max_len.max(min_len).max(1) as usize,
+        }
+    }
+}
+
+impl TokenFilter for NGramFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut res = Vec::new();
+
+        for t in tokens {
+            let chars: Vec<char> = t.text.chars().collect();
+
+            // tokens too short to be split are kept whole
+            if chars.len() < self.min_len {
+                res.push(t);
+                continue;
+            }
+
+            // every gram shares the position and offsets of its token
+            for n in self.min_len..=self.max_len.min(chars.len()) {
+                for gram in chars.windows(n) {
+                    res.push(Token {
+                        text: gram.iter().collect(),
+                        ..t.clone()
+                    });
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl SynonymsFilter {
+    pub fn new(groups: &[Vec<String>]) -> SynonymsFilter {
+        let mut group_index_map = FxHashMap::default();
+        for (i, group) in groups.iter().enumerate() {
+            for term in group {
+                group_index_map.insert(term.clone(), i);
+            }
+        }
+
+        SynonymsFilter {
+            groups: groups.to_vec(),
+            group_index_map,
+        }
+    }
+}
+
+impl TokenFilter for SynonymsFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut res = Vec::with_capacity(tokens.len());
+
+        for t in tokens {
+            let synonyms = self
+                .group_index_map
+                .get(&t.text)
+                .map(|i| &self.groups[*i][..])
+                .unwrap_or_default();
+
+            // the original token comes first, synonyms are stacked on its position
+            let others: Vec<Token> = synonyms
+                .iter()
+                .filter(|s| **s != t.text)
+                .map(|s| Token {
+                    text: s.clone(),
+                    ..t.clone()
+                })
+                .collect();
+
+            res.push(t);
+            res.extend(others);
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        text.split(' ')
+            .enumerate()
+            .map(|(i, t)| Token {
+                text: t.to_string(),
+                start: 0,
+                end: 0,
+                position: i as u32,
+            })
+            .collect()
+    }
+
+    fn texts(tokens: &[Token]) -> Vec<(&str, u32)> {
+        tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.position))
+            .collect()
+    }
+
+    #[test]
+    fn test_ascii_folding_filter() {
+        let result = AsciiFoldingFilter.filter(tokens("Crème brûlée Łódź straße"));
+        assert_eq!(
+            texts(&result),
+            [("Creme", 0), ("brulee", 1), ("Lodz", 2), ("strasse", 3)]
+        );
+    }
+
+    #[test]
+    fn test_stop_words_filter() {
+        let filter = StopWordsFilter::new(&["the".to_string(), "of".to_string()]);
+        let result = filter.filter(tokens("the end of the world"));
+        assert_eq!(texts(&result), [("end", 1), ("world", 4)]);
+    }
+
+    #[test]
+    fn test_stemmer_filter() {
+        let result = StemmerFilter::new(Language::English).filter(tokens("jumps over lazy dogs"));
+        assert_eq!(
+            texts(&result),
+            [("jump", 0), ("over", 1), ("lazi", 2), ("dog", 3)]
+        );
+
+        let result = StemmerFilter::new(Language::French).filter(tokens("les maisons"));
+        assert_eq!(texts(&result), [("le", 0), ("maison", 1)]);
+    }
+
+    #[test]
+    fn test_ngram_filter() {
+        let result = NGramFilter::new(2, 3).filter(tokens("a rust"));
+        assert_eq!(
+            texts(&result),
+            [
+                ("a", 0),
+                ("ru", 1),
+                ("us", 1),
+                ("st", 1),
+                ("rus", 1),
+                ("ust", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_synonyms_filter() {
+        let filter = SynonymsFilter::new(&[vec!["car".to_string(), "automobile".to_string()]]);
+        let result = filter.filter(tokens("red automobile"));
+        assert_eq!(texts(&result), [("red", 0), ("automobile", 1), ("car", 1)]);
+    }
+}
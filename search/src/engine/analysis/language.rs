@@ -0,0 +1,130 @@
+use rust_stemmers::Algorithm;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Language {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+// languages with their name and ISO 639-1 code, the position is the on-disk id
+pub const LANGUAGES: [(Language, &str, &str); 18] = [
+    (Language::Arabic, "arabic", "ar"),
+    (Language::Danish, "danish", "da"),
+    (Language::Dutch, "dutch", "nl"),
+    (Language::English, "english", "en"),
+    (Language::Finnish, "finnish", "fi"),
+    (Language::French, "french", "fr"),
+    (Language::German, "german", "de"),
+    (Language::Greek, "greek", "el"),
+    (Language::Hungarian, "hungarian", "hu"),
+    (Language::Italian, "italian", "it"),
+    (Language::Norwegian, "norwegian", "no"),
+    (Language::Portuguese, "portuguese", "pt"),
+    (Language::Romanian, "romanian", "ro"),
+    (Language::Russian, "russian", "ru"),
+    (Language::Spanish, "spanish", "es"),
+    (Language::Swedish, "swedish", "sv"),
+    (Language::Tamil, "tamil", "ta"),
+    (Language::Turkish, "turkish", "tr"),
+];
+
+impl Language {
+    pub fn from_name(name: &str) -> Option<Language> {
+        // both names and codes are accepted
+        let name = name.to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(_, n, c)| *n == name || *c == name)
+            .map(|(l, _, _)| *l)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Language> {
+        // the code is the last extension of the file stem, as in `article.fr.txt`
+        path.file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .and_then(|c| c.to_str())
+            .and_then(|c| LANGUAGES.iter().find(|(_, _, code)| *code == c))
+            .map(|(l, _, _)| *l)
+    }
+
+    pub fn from_id(id: u32) -> Option<Language> {
+        LANGUAGES.get(id as usize).map(|(l, _, _)| *l)
+    }
+
+    pub fn get_id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn get_name(self) -> &'static str {
+        LANGUAGES[self as usize].1
+    }
+
+    pub fn get_algorithm(self) -> Algorithm {
+        match self {
+            Language::Arabic => Algorithm::Arabic,
+            Language::Danish => Algorithm::Danish,
+            Language::Dutch => Algorithm::Dutch,
+            Language::English => Algorithm::English,
+            Language::Finnish => Algorithm::Finnish,
+            Language::French => Algorithm::French,
+            Language::German => Algorithm::German,
+            Language::Greek => Algorithm::Greek,
+            Language::Hungarian => Algorithm::Hungarian,
+            Language::Italian => Algorithm::Italian,
+            Language::Norwegian => Algorithm::Norwegian,
+            Language::Portuguese => Algorithm::Portuguese,
+            Language::Romanian => Algorithm::Romanian,
+            Language::Russian => Algorithm::Russian,
+            Language::Spanish => Algorithm::Spanish,
+            Language::Swedish => Algorithm::Swedish,
+            Language::Tamil => Algorithm::Tamil,
+            Language::Turkish => Algorithm::Turkish,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_names() {
+        assert_eq!(Language::from_name("French"), Some(Language::French));
+        assert_eq!(Language::from_name("de"), Some(Language::German));
+        assert_eq!(Language::from_name("klingon"), None);
+
+        for (i, (l, name, _)) in LANGUAGES.iter().enumerate() {
+            assert_eq!(l.get_id(), i as u32);
+            assert_eq!(Language::from_id(i as u32), Some(*l));
+            assert_eq!(l.get_name(), *name);
+        }
+    }
+
+    #[test]
+    fn test_language_from_path() {
+        let language = |p: &str| Language::from_path(Path::new(p));
+
+        assert_eq!(language("docs/article.it.txt"), Some(Language::Italian));
+        assert_eq!(language("docs/article.txt"), None);
+        assert_eq!(language("docs/v1.2.txt"), None);
+        assert_eq!(language("docs/notes"), None);
+    }
+}
@@ -0,0 +1,487 @@
+mod filters;
+mod language;
+mod tokenizers;
+
+pub use self::language::Language;
+use self::language::LANGUAGES;
+
+use self::filters::{
+    AsciiFoldingFilter, LowercaseFilter, NGramFilter, StemmerFilter, StopWordsFilter,
+    SynonymsFilter, ENGLISH_STOP_WORDS,
+};
+use self::tokenizers::{StandardTokenizer, UnicodeTokenizer, WhitespaceTokenizer};
+use super::ANALYZER_EXTENSION;
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Error, Result,
+};
+use std::{fs, path::Path, sync::OnceLock};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub position: u32,
+}
+
+pub trait Tokenizer: Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<Token>;
+}
+
+pub trait TokenFilter: Send + Sync {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token>;
+
+    // only filters mapping the chars of a term are applied to patterns,
+    // the others, such as stemmers, would not keep the pattern whole
+    fn normalize(&self, text: String) -> String {
+        text
+    }
+}
+
+pub trait Analyzer: Send + Sync {
+    fn analyze(&self, text: &str) -> Vec<Token>;
+
+    fn analyze_terms(&self, text: &str) -> Vec<String> {
+        self.analyze(text).into_iter().map(|t| t.text).collect()
+    }
+
+    // patterns, such as wildcards, are neither split into tokens nor stemmed
+    fn normalize(&self, text: &str) -> String {
+        text.to_string()
+    }
+}
+
+// a tokenizer followed by a chain of filters, applied in order
+pub struct Pipeline {
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenizerConfig {
+    Standard,
+    Whitespace,
+    Unicode,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterConfig {
+    Lowercase,
+    AsciiFolding,
+    StopWords(Vec<String>),
+    Stemmer(Language),
+    NGram(u32, u32),
+    Synonyms(Vec<Vec<String>>),
+}
+
+// serializable description of a pipeline, recorded in the index
+// so that queries are analyzed like the documents were
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalyzerConfig {
+    pub tokenizer: TokenizerConfig,
+    pub filters: Vec<FilterConfig>,
+}
+
+// the analyzer of an index, along with its variants for the other
+// languages, built on first use
+pub struct Analyzers {
+    config: AnalyzerConfig,
+    analyzers: Vec<OnceLock<Box<dyn Analyzer>>>,
+}
+
+impl Pipeline {
+    pub fn new(tokenizer: Box<dyn Tokenizer>) -> Pipeline {
+        Pipeline {
+            tokenizer,
+            filters: Vec::new(),
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Box<dyn TokenFilter>) -> Pipeline {
+        self.filters.push(filter);
+        self
+    }
+}
+
+impl Analyzer for Pipeline {
+    fn analyze(&self, text: &str) -> Vec<Token> {
+        self.filters
+            .iter()
+            .fold(self.tokenizer.tokenize(text), |tokens, f| f.filter(tokens))
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        self.filters
+            .iter()
+            .fold(text.to_string(), |text, f| f.normalize(text))
+    }
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> AnalyzerConfig {
+        AnalyzerConfig {
+            tokenizer: TokenizerConfig::Unicode,
+            filters: vec![
+                FilterConfig::Lowercase,
+                FilterConfig::Stemmer(Language::English),
+            ],
+        }
+    }
+}
+
+impl Analyzers {
+    pub fn new(config: AnalyzerConfig) -> Analyzers {
+        Analyzers {
+            config,
+            analyzers: (0..LANGUAGES.len()).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    pub fn get_analyzer(&self, language: Option<Language>) -> &dyn Analyzer {
+        let language = language
+            .or(self.config.get_language())
+            .unwrap_or(Language::English);
+
+        self.analyzers[language.get_id() as usize]
+            .get_or_init(|| self.config.with_language(language).build_analyzer())
+            .as_ref()
+    }
+}
+
+impl AnalyzerConfig {
+    pub fn build_analyzer(&self) -> Box<dyn Analyzer> {
+        let tokenizer: Box<dyn Tokenizer> = match self.tokenizer {
+            TokenizerConfig::Standard => Box::new(StandardTokenizer::new()),
+            TokenizerConfig::Whitespace => Box::new(WhitespaceTokenizer),
+            TokenizerConfig::Unicode => Box::new(UnicodeTokenizer),
+        };
+
+        let pipeline = self
+            .filters
+            .iter()
+            .fold(Pipeline::new(tokenizer), |pipeline, f| {
+                let filter: Box<dyn TokenFilter> = match f {
+                    FilterConfig::Lowercase => Box::new(LowercaseFilter),
+                    FilterConfig::AsciiFolding => Box::new(AsciiFoldingFilter),
+                    FilterConfig::StopWords(words) => Box::new(StopWordsFilter::new(words)),
+                    FilterConfig::Stemmer(language) => Box::new(StemmerFilter::new(*language)),
+                    FilterConfig::NGram(min_len, max_len) => {
+                        Box::new(NGramFilter::new(*min_len, *max_len))
+                    }
+                    FilterConfig::Synonyms(groups) => Box::new(SynonymsFilter::new(groups)),
+                };
+                pipeline.with_filter(filter)
+            });
+
+        Box::new(pipeline)
+    }
+
+    pub fn get_language(&self) -> Option<Language> {
+        self.filters.iter().find_map(|f| match f {
+            FilterConfig::Stemmer(language) => Some(*language),
+            _ => None,
+        })
+    }
+
+    pub fn with_language(&self, language: Language) -> AnalyzerConfig {
+        let filters = self
+            .filters
+            .iter()
+            .map(|f| match f {
+                FilterConfig::Stemmer(_) => FilterConfig::Stemmer(language),
+                f => f.clone(),
+            })
+            .collect();
+
+        AnalyzerConfig {
+            tokenizer: self.tokenizer.clone(),
+            filters,
+        }
+    }
+
+    pub fn get_description(&self) -> String {
+        // same syntax as parsed specs, without the word lists
+        let tokenizer = match self.tokenizer {
+            TokenizerConfig::Standard => "standard".to_string(),
+            TokenizerConfig::Whitespace => "whitespace".to_string(),
+            TokenizerConfig::Unicode => "unicode".to_string(),
+        };
+
+        let filters = self.filters.iter().map(|f| match f {
+            FilterConfig::Lowercase => "lowercase".to_string(),
+            FilterConfig::AsciiFolding => "ascii_folding".to_string(),
+            FilterConfig::StopWords(_) => "stop_words".to_string(),
+            FilterConfig::Stemmer(language) => format!("stemmer:{}", language.get_name()),
+            FilterConfig::NGram(min_len, max_len) => format!("ngram:{min_len}:{max_len}"),
+            FilterConfig::Synonyms(_) => "synonyms".to_string(),
+        });
+
+        std::iter::once(tokenizer)
+            .chain(filters)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn parse(spec: &str) -> Result<AnalyzerConfig, String> {
+        // a comma separated tokenizer and filters list, filter
+        // arguments follow their name, separated by colons
+        let mut parts = spec.split(',').map(str::trim);
+
+        let tokenizer = match parts.next().unwrap_or_default() {
+            "standard" => TokenizerConfig::Standard,
+            "whitespace" => TokenizerConfig::Whitespace,
+            "unicode" => TokenizerConfig::Unicode,
+            t => return Err(format!("unknown tokenizer `{t}`")),
+        };
+
+        let filters = parts
+            .map(|p| {
+                let args: Vec<&str> = p.split(':').collect();
+                match args[..] {
+                    ["lowercase"] => Ok(FilterConfig::Lowercase),
+                    ["ascii_folding"] => Ok(FilterConfig::AsciiFolding),
+                    ["stemmer"] => Ok(FilterConfig::Stemmer(Language::English)),
+                    ["stemmer", language] => Language::from_name(language)
+                        .map(FilterConfig::Stemmer)
+                        .ok_or_else(|| format!("unknown language `{language}`")),
+                    ["stop_words"] => Ok(FilterConfig::StopWords(
+                        ENGLISH_STOP_WORDS.iter().map(|w| w.to_string()).collect(),
+                    )),
+                    ["stop_words", path] => {
+                        Self::read_lines(path).map(|lines| FilterConfig::StopWords(lines.concat()))
+                    }
+                    ["ngram", min_len, max_len] => match (min_len.parse(), max_len.parse()) {
+                        (Ok(min_len), Ok(max_len)) if min_len > 0 && min_len <= max_len => {
+                            Ok(FilterConfig::NGram(min_len, max_len))
+                        }
+                        _ => Err(format!("invalid ngram lengths in `{p}`")),
+                    },
+                    ["synonyms", path] => Self::read_lines(path).map(FilterConfig::Synonyms),
+                    _ => Err(format!("unknown filter `{p}`")),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AnalyzerConfig { tokenizer, filters })
+    }
+
+    pub fn write_analyzer(&self, output_path: &str) -> Result<()> {
+        let path = output_path.to_string() + ANALYZER_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(match self.tokenizer {
+            TokenizerConfig::Standard => 0,
+            TokenizerConfig::Whitespace => 1,
+            TokenizerConfig::Unicode => 2,
+        });
+
+        writer.write_vbyte(self.filters.len() as u32);
+        for f in &self.filters {
+            match f {
+                FilterConfig::Lowercase => {
+                    writer.write_vbyte(0);
+                }
+                FilterConfig::AsciiFolding => {
+                    writer.write_vbyte(1);
+                }
+                FilterConfig::StopWords(words) => {
+                    writer.write_vbyte(2);
+                    Self::write_words(&mut writer, words);
+                }
+                FilterConfig::Stemmer(language) => {
+                    writer.write_vbyte(3);
+                    writer.write_vbyte(language.get_id());
+                }
+                FilterConfig::NGram(min_len, max_len) => {
+                    writer.write_vbyte(4);
+                    writer.write_vbyte(*min_len);
+                    writer.write_vbyte(*max_len);
+                }
+                FilterConfig::Synonyms(groups) => {
+                    writer.write_vbyte(5);
+                    writer.write_vbyte(groups.len() as u32);
+                    groups
+                        .iter()
+                        .for_each(|g| Self::write_words(&mut writer, g));
+                }
+            }
+        }
+
+        writer.flush()
+    }
+
+    pub fn load_analyzer(input_path: &str) -> Result<AnalyzerConfig> {
+        let path = input_path.to_string() + ANALYZER_EXTENSION;
+
+        // indexes built before analyzers were recorded used the ascii
+        // tokenizer and the english stemmer
+        if !Path::new(&path).exists() {
+            return Ok(AnalyzerConfig {
+                tokenizer: TokenizerConfig::Standard,
+                filters: vec![
+                    FilterConfig::Lowercase,
+                    FilterConfig::Stemmer(Language::English),
+                ],
+            });
+        }
+
+        let mut reader = BitsReader::new(&path)?;
+
+        let tokenizer = match reader.read_vbyte()? {
+            0 => TokenizerConfig::Standard,
+            1 => TokenizerConfig::Whitespace,
+            2 => TokenizerConfig::Unicode,
+            t => return Err(Error::Corrupted(format!("unknown analyzer tokenizer {t}"))),
+        };
+
+        let filters = (0..reader.read_vbyte()?)
+            .map(|_| {
+                Ok(match reader.read_vbyte()? {
+                    0 => FilterConfig::Lowercase,
+                    1 => FilterConfig::AsciiFolding,
+                    2 => FilterConfig::StopWords(Self::read_words(&mut reader)?),
+                    3 => FilterConfig::Stemmer(
+                        Language::from_id(reader.read_vbyte()?)
+                            .ok_or_else(|| Error::corrupted("unknown stemmer language"))?,
+                    ),
+                    4 => FilterConfig::NGram(reader.read_vbyte()?, reader.read_vbyte()?),
+                    5 => FilterConfig::Synonyms(
+                        (0..reader.read_vbyte()?)
+                            .map(|_| Self::read_words(&mut reader))
+                            .collect::<Result<_>>()?,
+                    ),
+                    f => return Err(Error::Corrupted(format!("unknown analyzer filter {f}"))),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(AnalyzerConfig { tokenizer, filters })
+    }
+
+    fn write_words(writer: &mut BitsWriter, words: &[String]) {
+        writer.write_vbyte(words.len() as u32);
+        words.iter().for_each(|w| {
+            writer.write_str(w);
+        });
+    }
+
+    fn read_words(reader: &mut BitsReader) -> Result<Vec<String>> {
+        (0..reader.read_vbyte()?)
+            .map(|_| reader.read_str())
+            .collect()
+    }
+
+    fn read_lines(path: &str) -> Result<Vec<Vec<String>>, String> {
+        // one entry per line, words on the same line are comma separated
+        let content = fs::read_to_string(path).map_err(|e| format!("cannot read `{path}`: {e}"))?;
+
+        Ok(content
+            .lines()
+            .map(|l| {
+                l.split(',')
+                    .map(|w| w.trim().to_string())
+                    .filter(|w| !w.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|words| !words.is_empty())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    #[test]
+    fn test_default_analyzer() {
+        let analyzer = AnalyzerConfig::default().build_analyzer();
+
+        let result = analyzer.analyze_terms("The quick brown, fox jumps over the lazy dog!!!");
+        assert_eq!(
+            result,
+            vec!["the", "quick", "brown", "fox", "jump", "over", "the", "lazi", "dog"]
+        );
+    }
+
+    #[test]
+    fn test_parse_and_build() {
+        let config =
+            AnalyzerConfig::parse("whitespace, lowercase, ascii_folding, stop_words, stemmer")
+                .unwrap();
+        assert_eq!(config.tokenizer, TokenizerConfig::Whitespace);
+        assert_eq!(config.filters.len(), 4);
+
+        let tokens = config.build_analyzer().analyze("The Crème of the CRÈMES");
+        let result: Vec<(&str, u32)> = tokens
+            .iter()
+            .map(|t| (t.text.as_str(), t.position))
+            .collect();
+        assert_eq!(result, [("creme", 1), ("creme", 4)]);
+
+        // patterns are only mapped char by char, stop words and stems are left
+        let analyzer = config.build_analyzer();
+        assert_eq!(analyzer.normalize("The CRÈM*s?"), "the crem*s?");
+
+        let config = AnalyzerConfig::parse("unicode,lowercase,stemmer:fr").unwrap();
+        assert_eq!(config.get_language(), Some(Language::French));
+        assert_eq!(
+            config.build_analyzer().analyze_terms("Les élèves français"),
+            ["le", "élev", "franc"]
+        );
+        assert_eq!(config.get_description(), "unicode,lowercase,stemmer:french");
+
+        assert!(AnalyzerConfig::parse("letters").is_err());
+        assert!(AnalyzerConfig::parse("unicode,stemmer:klingon").is_err());
+        assert!(AnalyzerConfig::parse("standard,ngram:3:2").is_err());
+        assert!(AnalyzerConfig::parse("standard,unknown").is_err());
+    }
+
+    #[test]
+    fn test_write_and_load_analyzer() {
+        let path = create_temporary_file_path("analyzer_unit");
+
+        assert_eq!(
+            AnalyzerConfig::load_analyzer(&path).unwrap().tokenizer,
+            TokenizerConfig::Standard
+        );
+
+        let config = AnalyzerConfig {
+            tokenizer: TokenizerConfig::Unicode,
+            filters: vec![
+                FilterConfig::Lowercase,
+                FilterConfig::StopWords(vec!["a".to_string(), "the".to_string()]),
+                FilterConfig::Synonyms(vec![vec!["car".to_string(), "auto".to_string()]]),
+                FilterConfig::NGram(2, 4),
+                FilterConfig::AsciiFolding,
+                FilterConfig::Stemmer(Language::German),
+            ],
+        };
+        config.write_analyzer(&path).unwrap();
+
+        assert_eq!(AnalyzerConfig::load_analyzer(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn test_language_analyzers() {
+        let analyzers = Analyzers::new(AnalyzerConfig::default());
+
+        let terms = |language| {
+            analyzers
+                .get_analyzer(language)
+                .analyze_terms("Häuser gebaut")
+        };
+        assert_eq!(terms(None), ["häuser", "gebaut"]);
+        assert_eq!(terms(Some(Language::German)), ["haus", "gebaut"]);
+
+        // analyzers without a stemmer are the same in every language
+        let analyzers = Analyzers::new(AnalyzerConfig::parse("unicode,lowercase").unwrap());
+        assert_eq!(
+            analyzers
+                .get_analyzer(Some(Language::German))
+                .analyze_terms("Häuser"),
+            ["häuser"]
+        );
+    }
+}
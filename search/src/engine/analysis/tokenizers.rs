@@ -0,0 +1,134 @@
+use super::{Token, Tokenizer};
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub struct StandardTokenizer {
+    regex: Regex,
+}
+
+pub struct WhitespaceTokenizer;
+
+pub struct UnicodeTokenizer;
+
+impl StandardTokenizer {
+    pub fn new() -> StandardTokenizer {
+        StandardTokenizer {
+            regex: Regex::new(r"[a-zA-Z0-9]+").expect("error while building regex"),
+        }
+    }
+}
+
+impl Tokenizer for StandardTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        // tokens are kept with their byte offsets in the original text
+        self.regex
+            .find_iter(text)
+            .enumerate()
+            .map(|(i, m)| Token {
+                text: m.as_str().to_string(),
+                start: m.start(),
+                end: m.end(),
+                position: i as u32,
+            })
+            .collect()
+    }
+}
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+
+        for word in text.split_whitespace() {
+            let start = offset + text[offset..].find(word).unwrap();
+            offset = start + word.len();
+
+            // punctuation is only stripped at the edges of a word
+            let trimmed = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+            let start = start + word.len() - trimmed.len();
+            let trimmed = trimmed.trim_end_matches(|c: char| !c.is_alphanumeric());
+
+            if !trimmed.is_empty() {
+                tokens.push(Token {
+                    text: trimmed.to_string(),
+                    start,
+                    end: start + trimmed.len(),
+                    position: tokens.len() as u32,
+                });
+            }
+        }
+
+        tokens
+    }
+}
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        // words are split on the unicode word boundaries (UAX #29)
+        text.unicode_word_indices()
+            .enumerate()
+            .map(|(i, (start, word))| Token {
+                text: word.to_string(),
+                start,
+                end: start + word.len(),
+                position: i as u32,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|t| t.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_standard_tokenizer() {
+        let text = "Café, dogs!";
+        let result = StandardTokenizer::new().tokenize(text);
+        assert_eq!(
+            result,
+            vec![
+                Token {
+                    text: "Caf".to_string(),
+                    start: 0,
+                    end: 3,
+                    position: 0
+                },
+                Token {
+                    text: "dogs".to_string(),
+                    start: 7,
+                    end: 11,
+                    position: 1
+                }
+            ]
+        );
+        assert_eq!(&text[result[1].start..result[1].end], "dogs");
+    }
+
+    #[test]
+    fn test_whitespace_tokenizer() {
+        let text = "\"Crème brûlée\" -- rock'n'roll!";
+        let result = WhitespaceTokenizer.tokenize(text);
+
+        assert_eq!(texts(&result), ["Crème", "brûlée", "rock'n'roll"]);
+        assert_eq!(&text[result[1].start..result[1].end], "brûlée");
+        assert_eq!(result[2].position, 2);
+    }
+
+    #[test]
+    fn test_unicode_tokenizer() {
+        let text = "Les élèves, naïfs; Straße 42 — Ελληνικά!";
+        let result = UnicodeTokenizer.tokenize(text);
+
+        assert_eq!(
+            texts(&result),
+            ["Les", "élèves", "naïfs", "Straße", "42", "Ελληνικά"]
+        );
+        assert_eq!(&text[result[1].start..result[1].end], "élèves");
+        assert_eq!(result[5].position, 5);
+    }
+}
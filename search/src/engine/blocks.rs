@@ -0,0 +1,268 @@
+use super::{
+    analysis::Token,
+    postings::{Posting, Postings, PostingsCodec, PostingsList, PostingsWriter},
+    vocabulary::Vocabulary,
+};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Result,
+};
+use fxhash::FxHashMap;
+use std::{cmp::Reverse, collections::BinaryHeap, mem::size_of};
+
+// rough per-term cost of the hash map entry and postings vector
+const TERM_OVERHEAD_BYTES: usize = 64;
+
+pub struct Block {
+    term_index_map: FxHashMap<String, usize>,
+    postings: Vec<PostingsList>,
+    estimated_bytes: usize,
+}
+
+struct RunReader {
+    reader: BitsReader,
+    remaining: u32,
+}
+
+impl Block {
+    pub fn new() -> Block {
+        Block {
+            term_index_map: FxHashMap::default(),
+            postings: Vec::new(),
+            estimated_bytes: 0,
+        }
+    }
+
+    pub fn add_document(&mut self, doc_id: u32, tokens: &[Token]) {
+        for t in tokens {
+            let term_index = match self.term_index_map.get(&t.text) {
+                Some(i) => *i,
+                None => {
+                    self.estimated_bytes += t.text.len() + TERM_OVERHEAD_BYTES;
+                    self.term_index_map
+                        .insert(t.text.clone(), self.postings.len());
+                    self.postings.push(PostingsList::new());
+                    self.postings.len() - 1
+                }
+            };
+
+            // documents are added in id order, so the entry can only be the last one
+            let postings_list = &mut self.postings[term_index];
+            if postings_list.last().map(|p| p.document_id) != Some(doc_id) {
+                self.estimated_bytes += size_of::<Posting>();
+                postings_list.push(Posting {
+                    document_id: doc_id,
+                    ..Posting::default()
+                });
+            }
+
+            let posting_entry = postings_list.last_mut().unwrap();
+            // stacked tokens of the same term share a single position
+            if posting_entry.positions.last() == Some(&t.position) {
+                continue;
+            }
+            posting_entry.document_frequency += 1;
+            posting_entry.positions.push(t.position);
+            self.estimated_bytes += size_of::<u32>();
+        }
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        self.estimated_bytes
+    }
+
+    pub fn write_run(self, path: &str) -> Result<()> {
+        let mut writer = BitsWriter::new(path)?;
+
+        let mut terms: Vec<(String, usize)> = self.term_index_map.into_iter().collect();
+        terms.sort_unstable();
+
+        writer.write_vbyte(terms.len() as u32);
+        // runs are only read back whole, their blocks need no length bounds,
+        // and are always written with gamma codes
+        for (term, idx) in terms {
+            writer.write_str(&term);
+            Postings::write_postings_list(
+                &mut writer,
+                &self.postings[idx],
+                &[],
+                PostingsCodec::Gamma,
+            );
+        }
+
+        writer.flush()
+    }
+}
+
+impl RunReader {
+    fn new(path: &str) -> Result<RunReader> {
+        let mut reader = BitsReader::new(path)?;
+        let remaining = reader.read_vbyte()?;
+        Ok(RunReader { reader, remaining })
+    }
+
+    fn next_term(&mut self) -> Result<Option<(String, PostingsList)>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        let term = self.reader.read_str()?;
+        Ok(Some((
+            term,
+            Postings::read_postings_list(&mut self.reader, PostingsCodec::Gamma)?,
+        )))
+    }
+}
+
+pub fn merge_runs(
+    run_paths: &[String],
+    output_path: &str,
+    lengths: &[u32],
+    codec: PostingsCodec,
+    num_documents: u32,
+    max_freq_percentage_threshold: f64,
+    min_freq_threshold: u32,
+) -> Result<()> {
+    let frequency_threshold = (num_documents as f64 * max_freq_percentage_threshold) as u32;
+
+    let mut runs: Vec<RunReader> = run_paths
+        .iter()
+        .map(|p| RunReader::new(p))
+        .collect::<Result<_>>()?;
+    let mut heads: Vec<Option<PostingsList>> = vec![None; runs.len()];
+
+    // min heap on (term, run), runs hold increasing doc ids,
+    // so equal terms are popped in the right order
+    let mut heap = BinaryHeap::new();
+    let mut advance = |i: usize, heap: &mut BinaryHeap<_>, heads: &mut Vec<_>| -> Result<()> {
+        if let Some((term, postings)) = runs[i].next_term()? {
+            heads[i] = Some(postings);
+            heap.push(Reverse((term, i)));
+        }
+        Ok(())
+    };
+
+    for i in 0..heads.len() {
+        advance(i, &mut heap, &mut heads)?;
+    }
+
+    let mut writer = PostingsWriter::new(output_path, codec)?;
+    let mut terms = Vec::new();
+
+    while let Some(Reverse((term, i))) = heap.pop() {
+        let mut postings = heads[i].take().unwrap();
+        advance(i, &mut heap, &mut heads)?;
+
+        while heap.peek().is_some_and(|Reverse((t, _))| *t == term) {
+            let Reverse((_, j)) = heap.pop().unwrap();
+            postings.extend(heads[j].take().unwrap());
+            advance(j, &mut heap, &mut heads)?;
+        }
+
+        let f = postings.len() as u32;
+        if f <= frequency_threshold && f > min_freq_threshold {
+            writer.write_postings_list(&postings, lengths);
+            terms.push((term, f));
+        }
+    }
+
+    writer.flush()?;
+    Vocabulary::write_terms(&terms, output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_dir_path;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(i, t)| Token {
+                text: t.to_string(),
+                start: 0,
+                end: 0,
+                position: i as u32,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_document() {
+        let mut block = Block::new();
+        block.add_document(0, &tokens("a b a"));
+        block.add_document(3, &tokens("b"));
+
+        let a = &block.postings[block.term_index_map["a"]];
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].document_frequency, 2);
+        assert_eq!(a[0].positions, [0, 2]);
+
+        let b = &block.postings[block.term_index_map["b"]];
+        assert_eq!(b.iter().map(|p| p.document_id).collect::<Vec<_>>(), [0, 3]);
+
+        assert!(block.get_estimated_bytes() > 0);
+    }
+
+    #[test]
+    fn test_write_and_merge_runs() {
+        let dir = create_temporary_dir_path();
+        let output_path = format!("{dir}/idx");
+
+        let run_paths: Vec<String> = [vec!["a b", "b c"], vec!["c d", "a"]]
+            .iter()
+            .enumerate()
+            .map(|(i, docs)| {
+                let mut block = Block::new();
+                for (j, d) in docs.iter().enumerate() {
+                    block.add_document((2 * i + j) as u32, &tokens(d));
+                }
+
+                let path = format!("{dir}/run{i}");
+                block.write_run(&path).unwrap();
+                path
+            })
+            .collect();
+
+        merge_runs(
+            &run_paths,
+            &output_path,
+            &[2, 2, 2, 1],
+            PostingsCodec::Gamma,
+            4,
+            1.0,
+            0,
+        )
+        .unwrap();
+
+        let vocabulary = Vocabulary::load_vocabulary(&output_path).unwrap();
+        let postings = Postings::load_postings_reader(&output_path, PostingsCodec::Gamma).unwrap();
+
+        assert_eq!(vocabulary.get_terms(), ["a", "b", "c", "d"]);
+
+        let doc_ids =
+            |list: PostingsList| -> Vec<u32> { list.iter().map(|p| p.document_id).collect() };
+        assert_eq!(doc_ids(postings.load_postings_list(0).unwrap()), [0, 3]);
+        assert_eq!(doc_ids(postings.load_postings_list(2).unwrap()), [1, 2]);
+        assert_eq!(doc_ids(postings.load_postings_list(3).unwrap()), [2]);
+
+        // blocks know the shortest document they hold
+        let cursor = postings.get_cursor(0).unwrap();
+        assert_eq!(cursor.get_block_header(0).unwrap().min_length, 1);
+
+        // terms appearing in a single document are filtered out
+        merge_runs(
+            &run_paths,
+            &output_path,
+            &[2, 2, 2, 1],
+            PostingsCodec::Gamma,
+            4,
+            1.0,
+            1,
+        )
+        .unwrap();
+        let vocabulary = Vocabulary::load_vocabulary(&output_path).unwrap();
+        assert_eq!(vocabulary.get_terms(), ["a", "b", "c"]);
+    }
+}
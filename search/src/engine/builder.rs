@@ -1,148 +1,794 @@
-use crate::disk::file_utils::walk_dir;
+use crate::{
+    disk::{bits_reader::BitsReader, file_utils::walk_dir},
+    Result,
+};
 
 use super::{
-    documents::{Document, Documents},
-    postings::{Posting, Postings, PostingsList},
-    preprocessor::Preprocessor,
+    analysis::{AnalyzerConfig, Analyzers, Language, Token},
+    blocks::{merge_runs, Block},
+    documents::{Document, Documents, DocumentsWriter},
+    fields::{FieldIndex, Fields, Record, DEFAULT_FIELD},
+    manifest::{IndexManifest, FORMAT_VERSION},
+    postings::{PostingsCodec, PostingsList, PostingsWriter},
+    segments::{Segment, SegmentInfo},
+    sources::SourceConfig,
+    store::{DocumentStoreWriter, StoreConfig},
     vocabulary::Vocabulary,
-    InMemory,
+    IndexConfig, CODEC_EXTENSION, DOCUMENTS_EXTENSION, EXTERNAL_IDS_EXTENSION, LENGTHS_EXTENSION,
+    OFFSETS_EXTENSION, POSTINGS_EXTENSION, SOURCE_EXTENSION, STORED_FIELDS_EXTENSION,
+    STORE_EXTENSION, STORE_OFFSETS_EXTENSION, TOMBSTONES_EXTENSION, VOCABULARY_ALPHA_EXTENSION,
 };
-use fxhash::FxHashMap;
-use indicatif::{ParallelProgressIterator, ProgressIterator, ProgressStyle};
+use fxhash::{FxHashMap, FxHashSet};
+use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use std::{
-    collections::{hash_map::Entry, BTreeMap},
+    cmp::Reverse,
+    collections::BinaryHeap,
     fs::{self},
+    mem::size_of,
+    path::Path,
+    time::UNIX_EPOCH,
 };
 use walkdir::DirEntry;
 
 const PROGRESS_STYLE: &str =
     "Documents per second: {per_sec:<3}\n\n[{elapsed_precise}] [{bar:50}] {pos}/{len} [{eta_precise}]";
 const PROGRESS_CHARS: &str = "=> ";
+// records streamed out of dumps are not counted in advance
+const PROGRESS_STYLE_UNKNOWN_LEN: &str =
+    "Documents per second: {per_sec:<3}\n\n[{elapsed_precise}] {pos} documents";
+
+// documents are pre-processed in parallel, a chunk at a time
+const PROCESSING_CHUNK_SIZE: usize = 1024;
+// budget used by updates, whose delta segments are usually small
+const DEFAULT_MEMORY_BUDGET_BYTES: usize = 1 << 30;
+
+// a record after analysis, with the tokens of each of its fields and the stored ones
+struct AnalyzedRecord {
+    path: String,
+    id: Option<String>,
+    fields: Vec<(String, Vec<Token>)>,
+    stored: Vec<(String, String)>,
+}
+
+// how the records of a segment are analyzed and stored, as recorded in the index
+struct RecordProcessor {
+    analyzers: Analyzers,
+    store: StoreConfig,
+    codec: PostingsCodec,
+}
+
+// anything that can be turned into a record to index, read in parallel
+// items that can not be read are skipped, while errors stop the build
+pub trait IntoRecord: Send {
+    fn into_record(self) -> Result<Option<Record>>;
+}
+
+impl IntoRecord for Record {
+    fn into_record(self) -> Result<Option<Record>> {
+        Ok(Some(self))
+    }
+}
+
+impl IntoRecord for Result<Record> {
+    fn into_record(self) -> Result<Option<Record>> {
+        self.map(Some)
+    }
+}
+
+impl IntoRecord for DirEntry {
+    fn into_record(self) -> Result<Option<Record>> {
+        let file_path = self.path();
+
+        match fs::read_to_string(file_path) {
+            Ok(file_content) => Ok(Some(
+                Record::new(&file_path.to_string_lossy()).with_field(DEFAULT_FIELD, &file_content),
+            )),
+            Err(err) => {
+                // Print an error message including the file path
+                eprintln!("Error reading file {:?}: {}", file_path, err);
+                Ok(None)
+            }
+        }
+    }
+}
 
 pub fn build_engine(
     input_path: &str,
     output_path: &str,
-    preprocessor: &Preprocessor,
+    config: &IndexConfig,
+    max_freq_percentage_threshold: f64,
+    min_freq_threshold: u32,
+    memory_budget_bytes: usize,
+) -> Result<()> {
+    build_engine_from_records(
+        walk_dir(input_path).into_iter(),
+        output_path,
+        config,
+        max_freq_percentage_threshold,
+        min_freq_threshold,
+        memory_budget_bytes,
+    )
+}
+
+pub fn build_engine_from_source(
+    input_path: &str,
+    output_path: &str,
+    source: &SourceConfig,
+    config: &IndexConfig,
+    max_freq_percentage_threshold: f64,
+    min_freq_threshold: u32,
+    memory_budget_bytes: usize,
+) -> Result<()> {
+    // records are streamed out of the dumps, never holding a whole file in memory
+    let records = walk_dir(input_path)
+        .into_iter()
+        .filter(|e| source.is_source_file(e.path()))
+        .flat_map(|e| source.read_records(&e.path().to_string_lossy()));
+
+    build_index(
+        records,
+        output_path,
+        config,
+        Some(source),
+        max_freq_percentage_threshold,
+        min_freq_threshold,
+        memory_budget_bytes,
+    )
+}
+
+pub fn build_engine_from_records<T: IntoRecord>(
+    records: impl Iterator<Item = T>,
+    output_path: &str,
+    config: &IndexConfig,
+    max_freq_percentage_threshold: f64,
+    min_freq_threshold: u32,
+    memory_budget_bytes: usize,
+) -> Result<()> {
+    build_index(
+        records,
+        output_path,
+        config,
+        None,
+        max_freq_percentage_threshold,
+        min_freq_threshold,
+        memory_budget_bytes,
+    )
+}
+
+fn build_index<T: IntoRecord>(
+    records: impl Iterator<Item = T>,
+    output_path: &str,
+    config: &IndexConfig,
+    source: Option<&SourceConfig>,
     max_freq_percentage_threshold: f64,
     min_freq_threshold: u32,
-) {
-    let index: InMemory = build_in_memory(
-        input_path,
-        preprocessor,
+    memory_budget_bytes: usize,
+) -> Result<()> {
+    // a full build replaces every previous segment, as long as it can be read
+    let old_fields = Fields::load_fields(output_path).unwrap_or_else(|_| Fields::new());
+    Segment::load_manifest(output_path)
+        .unwrap_or_default()
+        .iter()
+        .for_each(|s| remove_segment(s, output_path, old_fields.get_num_fields()));
+    for extension in [SOURCE_EXTENSION, STORED_FIELDS_EXTENSION, CODEC_EXTENSION] {
+        let _ = fs::remove_file(output_path.to_string() + extension);
+    }
+
+    // taken before reading any file, so that the ones changed while building are seen by updates
+    let info = SegmentInfo::new(0);
+
+    // the default field always comes first, so that plain documents keep their lengths
+    let mut fields = Fields::new();
+    fields.get_or_insert_field_id(DEFAULT_FIELD);
+
+    config.analyzer.write_analyzer(output_path)?;
+    if config.store.is_enabled() {
+        config.store.write_store_config(output_path)?;
+    }
+    if config.codec != PostingsCodec::default() {
+        config.codec.write_codec(output_path)?;
+    }
+
+    build_segment(
+        records,
+        output_path,
+        &RecordProcessor::new(config.analyzer.clone(), config.store.clone(), config.codec),
+        &mut fields,
+        max_freq_percentage_threshold,
+        min_freq_threshold,
+        memory_budget_bytes,
+    )?;
+    fields.write_fields(output_path)?;
+    if let Some(source) = source {
+        source.write_source(output_path)?;
+    }
+    Segment::write_manifest(&[info], output_path)?;
+
+    write_index_manifest(
+        output_path,
+        &config.analyzer,
+        config.codec,
         max_freq_percentage_threshold,
         min_freq_threshold,
+    )
+}
+
+pub fn update_engine(input_path: &str, index_path: &str) -> Result<()> {
+    let mut segments = Segment::load_manifest(index_path)?;
+    // taken before walking the input folder, so that files changed while
+    // updating are seen by the next update
+    let info = SegmentInfo::new(segments.iter().map(|s| s.id).max().unwrap_or_default() + 1);
+    // new documents must be analyzed and stored like the indexed ones
+    let processor = RecordProcessor::new(
+        AnalyzerConfig::load_analyzer(index_path)?,
+        StoreConfig::load_store_config(index_path)?,
+        PostingsCodec::load_codec(index_path)?,
+    );
+    let mut fields = Fields::load_fields(index_path)?;
+    // indexes built from dumps are updated from the same kind of dumps
+    let source = SourceConfig::load_source(index_path)?;
+    let get_file_path = |doc_path: String| match source {
+        Some(_) => SourceConfig::get_file_path(&doc_path).to_string(),
+        None => doc_path,
+    };
+
+    // live indexed files, mapped to the segment position and local doc id of their documents
+    let mut indexed: FxHashMap<String, Vec<(usize, u32)>> = FxHashMap::default();
+    let mut deleted: Vec<FxHashSet<u32>> = Vec::new();
+
+    for (i, info) in segments.iter().enumerate() {
+        let path = info.get_path(index_path);
+        let documents = Documents::load_documents(&path)?;
+        let tombstones: FxHashSet<u32> = Segment::load_tombstones(&path)?.into_iter().collect();
+
+        for doc_id in 0..documents.get_num_documents() {
+            if !tombstones.contains(&doc_id) {
+                indexed
+                    .entry(get_file_path(documents.get_doc_path(doc_id)))
+                    .or_default()
+                    .push((i, doc_id));
+            }
+        }
+
+        deleted.push(tombstones);
+    }
+
+    let mut to_index = Vec::new();
+
+    for entry in walk_dir(input_path) {
+        if source
+            .as_ref()
+            .is_some_and(|s| !s.is_source_file(entry.path()))
+        {
+            continue;
+        }
+
+        let path = entry.path().to_string_lossy().to_string();
+
+        match indexed.remove(&path) {
+            None => to_index.push(entry),
+            Some(docs) => {
+                // files modified after their segment was started are re-indexed, timestamps
+                // are in whole seconds, so the ones of the same second are too
+                let timestamp = get_modified_timestamp(&entry);
+                if docs
+                    .iter()
+                    .any(|(i, _)| timestamp >= segments[*i].timestamp)
+                {
+                    docs.into_iter().for_each(|(i, doc_id)| {
+                        deleted[i].insert(doc_id);
+                    });
+                    to_index.push(entry);
+                }
+            }
+        }
+    }
+
+    // whatever is left was removed from the input folder
+    let num_removed = indexed.len();
+    for (i, doc_id) in indexed.into_values().flatten() {
+        deleted[i].insert(doc_id);
+    }
+
+    println!(
+        "- Found {} new or modified and {} removed files",
+        to_index.len(),
+        num_removed
     );
-    println!("- Writing postings");
-    Postings::write_postings(&index, output_path);
-    println!("- Writing vocabulary");
-    Vocabulary::write_vocabulary(&index, output_path);
+
+    if !to_index.is_empty() {
+        // frequency filters are left to the full build, as
+        // they are meaningless on small delta segments
+        let segment_path = info.get_path(index_path);
+
+        match &source {
+            Some(source) => build_segment(
+                to_index
+                    .iter()
+                    .flat_map(|e| source.read_records(&e.path().to_string_lossy())),
+                &segment_path,
+                &processor,
+                &mut fields,
+                1.0,
+                0,
+                DEFAULT_MEMORY_BUDGET_BYTES,
+            )?,
+            None => build_segment(
+                to_index.into_iter(),
+                &segment_path,
+                &processor,
+                &mut fields,
+                1.0,
+                0,
+                DEFAULT_MEMORY_BUDGET_BYTES,
+            )?,
+        }
+        segments.push(info);
+        deleted.push(FxHashSet::default());
+        fields.write_fields(index_path)?;
+    }
+
+    println!("- Writing tombstones");
+    for (info, tombstones) in segments.iter().zip(deleted) {
+        Segment::write_tombstones(&tombstones, &info.get_path(index_path))?;
+    }
+
+    Segment::write_manifest(&segments, index_path)?;
+    rewrite_index_manifest(index_path)
+}
+
+pub fn compact_engine(index_path: &str) -> Result<()> {
+    let segments = Segment::load_manifest(index_path)?;
+    let num_fields = Fields::load_fields(index_path)?.get_num_fields();
+
+    // the compacted segment is written next to the current ones, which are
+    // only removed once the manifest points to it, so that a failed
+    // compaction leaves the index as it was. The newest timestamp is kept,
+    // as files changed before it were re-indexed by the update that wrote it
+    let info = SegmentInfo {
+        id: segments.iter().map(|s| s.id).max().unwrap_or_default() + 1,
+        timestamp: segments
+            .iter()
+            .map(|s| s.timestamp)
+            .max()
+            .unwrap_or_default(),
+    };
+
+    let written = write_compacted_segment(index_path, &segments, &info.get_path(index_path))
+        .and_then(|_| Segment::write_manifest(&[info], index_path));
+    if let Err(err) = written {
+        remove_segment(&info, index_path, num_fields);
+        return Err(err);
+    }
+
+    segments
+        .iter()
+        .for_each(|s| remove_segment(s, index_path, num_fields));
+    rewrite_index_manifest(index_path)
+}
+
+fn write_compacted_segment(
+    index_path: &str,
+    infos: &[SegmentInfo],
+    output_path: &str,
+) -> Result<()> {
+    let fields = Fields::load_fields(index_path)?;
+    let num_fields = fields.get_num_fields();
+    let codec = PostingsCodec::load_codec(index_path)?;
+    let mut store = match StoreConfig::load_store_config(index_path)?.is_enabled() {
+        true => Some(DocumentStoreWriter::new(output_path)?),
+        false => None,
+    };
+
+    println!("- Merging {} segments", infos.len());
+
+    // postings are memory mapped, and read a term at a time while merging
+    let segments: Vec<Segment> = infos
+        .iter()
+        .map(|info| Segment::load_segment(index_path, *info, 0, num_fields, codec))
+        .collect::<Result<_>>()?;
+
+    let mut documents = DocumentsWriter::new(output_path)?;
+    let mut lengths: Vec<Vec<u32>> = vec![Vec::new(); num_fields];
+    // live documents get consecutive ids in the compacted segment
+    let mut new_doc_ids: Vec<Vec<Option<u32>>> = Vec::new();
+
+    for segment in &segments {
+        let mut segment_doc_ids = Vec::new();
+        for doc_id in 0..segment.documents.get_num_documents() {
+            if !segment.is_live(doc_id) {
+                segment_doc_ids.push(None);
+                continue;
+            }
+
+            segment_doc_ids.push(Some(documents.get_num_documents()));
+            documents.add_document(&Document {
+                path: segment.documents.get_doc_path(doc_id),
+                length: segment.documents.get_doc_len(doc_id),
+                external_id: segment.documents.get_external_id(doc_id),
+            });
+            if let Some(store) = &mut store {
+                store.add_document(&segment.get_stored_fields(doc_id)?);
+            }
+            (0..num_fields).for_each(|f| lengths[f].push(segment.get_field_len(f, doc_id)));
+        }
+        new_doc_ids.push(segment_doc_ids);
+    }
+
+    for (field_id, lengths) in lengths.iter().enumerate() {
+        println!("- Merging field {}", fields.get_name(field_id));
+        let field_path = FieldIndex::get_path(output_path, field_id);
+        merge_segment_postings(
+            &segments,
+            field_id,
+            &new_doc_ids,
+            lengths,
+            &field_path,
+            codec,
+        )?;
+
+        // the lengths of the first field are kept in documents
+        if field_id > 0 {
+            FieldIndex::write_lengths(lengths, &field_path)?;
+        }
+    }
+
     println!("- Writing documents");
-    Documents::write_documents(&index.documents, output_path);
+    documents.flush()?;
+
+    if let Some(store) = store {
+        println!("- Writing document store");
+        store.flush()?;
+    }
+    Ok(())
+}
+
+fn merge_segment_postings(
+    segments: &[Segment],
+    field_id: usize,
+    new_doc_ids: &[Vec<Option<u32>>],
+    lengths: &[u32],
+    output_path: &str,
+    codec: PostingsCodec,
+) -> Result<()> {
+    let vocabularies: Vec<&[String]> = segments
+        .iter()
+        .map(|s| {
+            s.get_field(field_id)
+                .map_or(&[][..], |f| f.vocabulary.get_terms())
+        })
+        .collect();
+    let mut next_terms = vec![0; segments.len()];
+
+    // min heap on (term, segment), segments hold increasing doc ids,
+    // so equal terms are popped in the right order
+    let mut heap: BinaryHeap<Reverse<(&str, usize)>> = vocabularies
+        .iter()
+        .enumerate()
+        .filter_map(|(i, terms)| terms.first().map(|t| Reverse((t.as_str(), i))))
+        .collect();
+
+    let mut writer = PostingsWriter::new(output_path, codec)?;
+    let mut terms = Vec::new();
+
+    while let Some(&Reverse((term, _))) = heap.peek() {
+        let mut postings = PostingsList::new();
+
+        while heap.peek().is_some_and(|Reverse((t, _))| *t == term) {
+            let Reverse((_, i)) = heap.pop().unwrap();
+            let field = segments[i].get_field(field_id).unwrap();
+            postings.extend(
+                field
+                    .postings
+                    .load_postings_list(next_terms[i])?
+                    .into_iter()
+                    .filter_map(|mut p| {
+                        new_doc_ids[i][p.document_id as usize].map(|id| {
+                            p.document_id = id;
+                            p
+                        })
+                    }),
+            );
+
+            next_terms[i] += 1;
+            if let Some(t) = vocabularies[i].get(next_terms[i]) {
+                heap.push(Reverse((t.as_str(), i)));
+            }
+        }
+
+        // terms only held by deleted documents are dropped
+        if !postings.is_empty() {
+            writer.write_postings_list(&postings, lengths);
+            terms.push((term.to_string(), postings.len() as u32));
+        }
+    }
+
+    writer.flush()?;
+    Vocabulary::write_terms(&terms, output_path)
+}
+
+fn rewrite_index_manifest(index_path: &str) -> Result<()> {
+    // updates and compactions keep the build parameters of the full build
+    let (max_freq_percentage_threshold, min_freq_threshold) =
+        match IndexManifest::read_index_manifest(index_path) {
+            Ok(m) => (m.max_freq_percentage_threshold, m.min_freq_threshold),
+            Err(_) => (1.0, 0),
+        };
+
+    write_index_manifest(
+        index_path,
+        &AnalyzerConfig::load_analyzer(index_path)?,
+        PostingsCodec::load_codec(index_path)?,
+        max_freq_percentage_threshold,
+        min_freq_threshold,
+    )
 }
 
-fn build_in_memory(
-    input_dir: &str,
-    preprocessor: &Preprocessor,
+fn write_index_manifest(
+    index_path: &str,
+    analyzer: &AnalyzerConfig,
+    codec: PostingsCodec,
     max_freq_percentage_threshold: f64,
     min_freq_threshold: u32,
-) -> InMemory {
-    let iterator_style = ProgressStyle::with_template(PROGRESS_STYLE)
-        .unwrap()
-        .progress_chars(PROGRESS_CHARS);
-
-    let files = walk_dir(input_dir);
-
-    println!("- Pre-processing phase");
-    let processed_documents: Vec<(String, Vec<String>)> = files
-        .into_par_iter()
-        .progress_with_style(iterator_style.clone())
-        .filter_map(|d| process_document(d, preprocessor))
-        .collect();
+) -> Result<()> {
+    println!("- Writing manifest");
+    let num_fields = Fields::load_fields(index_path)?.get_num_fields();
+
+    // counts are read from the headers of the files, without loading them
+    let mut num_documents = 0;
+    let mut num_terms = 0;
+    for info in Segment::load_manifest(index_path)? {
+        let path = info.get_path(index_path);
+
+        let segment_documents =
+            BitsReader::new(&(path.clone() + DOCUMENTS_EXTENSION))?.read_vbyte()? as u64;
+        num_documents += segment_documents - Segment::load_tombstones(&path)?.len() as u64;
+
+        for field_id in 0..num_fields {
+            let alphas_path = FieldIndex::get_path(&path, field_id) + VOCABULARY_ALPHA_EXTENSION;
+            if Path::new(&alphas_path).exists() {
+                num_terms += BitsReader::new(&alphas_path)?.read_vbyte()? as u64;
+            }
+        }
+    }
+
+    IndexManifest {
+        version: FORMAT_VERSION,
+        analyzer: analyzer.get_description(),
+        codec: codec.get_name().to_string(),
+        min_freq_threshold,
+        max_freq_percentage_threshold,
+        num_documents,
+        num_terms,
+        files: IndexManifest::get_index_files(index_path)?,
+    }
+    .write_index_manifest(index_path)
+}
+
+fn remove_segment(info: &SegmentInfo, index_path: &str, num_fields: usize) {
+    let path = info.get_path(index_path);
+
+    for extension in [
+        DOCUMENTS_EXTENSION,
+        EXTERNAL_IDS_EXTENSION,
+        STORE_EXTENSION,
+        STORE_OFFSETS_EXTENSION,
+        TOMBSTONES_EXTENSION,
+    ] {
+        let _ = fs::remove_file(path.clone() + extension);
+    }
+
+    for field_id in 0..num_fields {
+        let field_path = FieldIndex::get_path(&path, field_id);
+
+        for extension in [
+            POSTINGS_EXTENSION,
+            OFFSETS_EXTENSION,
+            VOCABULARY_ALPHA_EXTENSION,
+            LENGTHS_EXTENSION,
+        ] {
+            let _ = fs::remove_file(field_path.clone() + extension);
+        }
+    }
+}
+
+fn get_modified_timestamp(dir_entry: &DirEntry) -> u32 {
+    dir_entry
+        .metadata()
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(u32::MAX)
+}
+
+fn build_segment<T: IntoRecord>(
+    items: impl Iterator<Item = T>,
+    output_path: &str,
+    processor: &RecordProcessor,
+    fields: &mut Fields,
+    max_freq_percentage_threshold: f64,
+    min_freq_threshold: u32,
+    memory_budget_bytes: usize,
+) -> Result<()> {
+    let mut items = items.peekable();
+
+    let progress = match items.size_hint() {
+        (len, Some(max_len)) if len == max_len => ProgressBar::new(len as u64).with_style(
+            ProgressStyle::with_template(PROGRESS_STYLE)
+                .unwrap()
+                .progress_chars(PROGRESS_CHARS),
+        ),
+        _ => ProgressBar::no_length()
+            .with_style(ProgressStyle::with_template(PROGRESS_STYLE_UNKNOWN_LEN).unwrap()),
+    };
 
     println!("- Indexing phase");
 
-    // document counter
-    let mut doc_id = 0;
-    // postings list
-    let mut postings = Vec::new();
-    // word to postings index
-    let mut term_index_map = FxHashMap::default();
-    // per-word doc id to posting list index
-    let mut term_doc_map: Vec<FxHashMap<u32, usize>> = Vec::new();
-    // documents data
-    let mut documents = Vec::new();
-
-    let processed_docs_iterator = processed_documents
-        .into_iter()
-        .progress_with_style(iterator_style);
+    // documents data, spilled to disk as they come
+    let mut documents = DocumentsWriter::new(output_path)?;
+    // current partial index of each field, flushed to sorted runs when over budget
+    let mut blocks = vec![Block::new()];
+    // lengths of each field, the first one is kept in documents
+    let mut lengths: Vec<Vec<u32>> = vec![Vec::new()];
+    // runs written so far for each field
+    let mut run_paths: Vec<Vec<String>> = vec![Vec::new()];
+    // stored fields are written as documents come
+    let mut store = match processor.store.is_enabled() {
+        true => Some(DocumentStoreWriter::new(output_path)?),
+        false => None,
+    };
 
-    for (path, tokens) in processed_docs_iterator {
-        // update documents array
-        documents.push(Document {
-            path,
-            length: tokens.len() as u32,
-        });
+    while items.peek().is_some() {
+        let chunk: Vec<T> = items.by_ref().take(PROCESSING_CHUNK_SIZE).collect();
+        let chunk_len = chunk.len() as u64;
+
+        let processed_documents: Vec<AnalyzedRecord> = chunk
+            .into_par_iter()
+            .filter_map(|item| item.into_record().transpose())
+            .map(|record| record.map(|r| processor.process_record(r)))
+            .collect::<Result<_>>()?;
+
+        for record in processed_documents {
+            let doc_id = documents.get_num_documents();
+
+            for (name, tokens) in record.fields {
+                let field_id = fields.get_or_insert_field_id(&name);
+                if field_id >= blocks.len() {
+                    blocks.resize_with(field_id + 1, Block::new);
+                    lengths.resize_with(field_id + 1, Vec::new);
+                    run_paths.resize_with(field_id + 1, Vec::new);
+                }
 
-        for (word_pos, t) in tokens.iter().enumerate() {
-            // obtain postings for this word and increment collection frequency
-            if !term_index_map.contains_key(t) {
-                let idx = term_index_map.len();
-                term_index_map.insert(t.clone(), idx);
-                postings.push(PostingsList::new());
-                term_doc_map.push(FxHashMap::default());
+                blocks[field_id].add_document(doc_id, &tokens);
+                lengths[field_id].resize(doc_id as usize, 0);
+                lengths[field_id].push(tokens.len() as u32);
             }
-            let term_index = *term_index_map.get(t).unwrap();
-
-            // obtain document entry for this word and update it
-            let postings_list = &mut postings[term_index];
-            if let Entry::Vacant(e) = term_doc_map[term_index].entry(doc_id) {
-                let idx = postings_list.len();
-                e.insert(idx);
-                postings_list.push(Posting::default());
+
+            documents.add_document(&Document {
+                path: record.path,
+                length: lengths[0].get(doc_id as usize).copied().unwrap_or_default(),
+                external_id: record.id,
+            });
+
+            if let Some(store) = &mut store {
+                store.add_document(&record.stored);
+            }
+
+            // field lengths stay in memory until the end, and leave less room to postings
+            let lengths_bytes = lengths.iter().map(|l| l.len()).sum::<usize>() * size_of::<u32>();
+            let estimated_bytes: usize = blocks.iter().map(Block::get_estimated_bytes).sum();
+            if estimated_bytes + lengths_bytes > memory_budget_bytes {
+                for (field_id, block) in blocks.iter_mut().enumerate() {
+                    let field_path = FieldIndex::get_path(output_path, field_id);
+                    let run_path = format!("{field_path}.run{}", run_paths[field_id].len());
+                    std::mem::replace(block, Block::new()).write_run(&run_path)?;
+                    run_paths[field_id].push(run_path);
+                }
             }
-            let posting_entry_index = *term_doc_map[term_index].get(&doc_id).unwrap();
+        }
 
-            let posting_entry = &mut postings_list[posting_entry_index];
+        progress.inc(chunk_len);
+    }
 
-            posting_entry.document_frequency += 1;
-            posting_entry.document_id = doc_id;
-            posting_entry.positions.push(word_pos as u32);
+    progress.finish();
+
+    let num_documents = documents.get_num_documents();
+
+    for (field_id, block) in blocks.into_iter().enumerate() {
+        let field_path = FieldIndex::get_path(output_path, field_id);
+        let run_path = format!("{field_path}.run{}", run_paths[field_id].len());
+        block.write_run(&run_path)?;
+        run_paths[field_id].push(run_path);
+
+        println!(
+            "- Merging {} runs of field {}",
+            run_paths[field_id].len(),
+            fields.get_name(field_id)
+        );
+        merge_runs(
+            &run_paths[field_id],
+            &field_path,
+            &lengths[field_id],
+            processor.codec,
+            num_documents,
+            max_freq_percentage_threshold,
+            min_freq_threshold,
+        )?;
+
+        run_paths[field_id].iter().for_each(|p| {
+            let _ = fs::remove_file(p);
+        });
+
+        if field_id > 0 {
+            lengths[field_id].resize(num_documents as usize, 0);
+            FieldIndex::write_lengths(&lengths[field_id], &field_path)?;
         }
-        doc_id += 1;
     }
 
-    let frequency_threshold = (doc_id as f64 * max_freq_percentage_threshold) as u32;
+    println!("- Writing documents");
+    documents.flush()?;
 
-    let term_index_map: BTreeMap<String, usize> = term_index_map
-        .into_iter()
-        .filter(|(_, v)| {
-            let f = postings[*v].len() as u32;
-            f <= frequency_threshold && f > min_freq_threshold
-        })
-        .collect();
+    if let Some(store) = store {
+        println!("- Writing document store");
+        store.flush()?;
+    }
+    Ok(())
+}
+
+impl RecordProcessor {
+    fn new(analyzer: AnalyzerConfig, store: StoreConfig, codec: PostingsCodec) -> RecordProcessor {
+        RecordProcessor {
+            analyzers: Analyzers::new(analyzer),
+            store,
+            codec,
+        }
+    }
+
+    fn process_record(&self, record: Record) -> AnalyzedRecord {
+        // a language code in the path overrides the index language
+        let analyzer = self
+            .analyzers
+            .get_analyzer(Language::from_path(Path::new(&record.path)));
+
+        // values of repeated fields are concatenated, keeping the first seen order
+        let mut texts: Vec<(String, String)> = Vec::new();
+        for (name, text) in record.fields {
+            if !Fields::is_valid_name(&name) {
+                eprintln!(
+                    "Skipping field {:?} of {:?}, invalid name",
+                    name, record.path
+                );
+                continue;
+            }
+
+            match texts.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, t)) => {
+                    t.push('\n');
+                    t.push_str(&text);
+                }
+                None => texts.push((name, text)),
+            }
+        }
+
+        // fields are stored as indexed, so that snippets line up with their positions
+        let stored = texts
+            .iter()
+            .filter(|(name, _)| self.store.is_stored(name))
+            .cloned()
+            .collect();
+
+        let fields = texts
+            .into_iter()
+            .map(|(name, text)| (name, analyzer.analyze(&text)))
+            .collect();
 
-    InMemory {
-        term_index_map,
-        postings,
-        documents,
-    }
-}
-
-fn process_document(
-    dir_entry: DirEntry,
-    preprocessor: &Preprocessor,
-) -> Option<(String, Vec<String>)> {
-    let file_path = dir_entry.path();
-    match fs::read_to_string(file_path) {
-        Ok(file_content) => Some((
-            dir_entry.path().to_str().unwrap().to_string(),
-            preprocessor.tokenize_and_stem(&file_content),
-        )),
-        Err(err) => {
-            // Print an error message including the file path
-            eprintln!("Error reading file {:?}: {}", file_path, err);
-            None
+        AnalyzedRecord {
+            path: record.path,
+            id: record.id,
+            fields,
+            stored,
         }
     }
 }
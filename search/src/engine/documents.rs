@@ -1,78 +1,159 @@
-use super::{utils, DOCUMENTS_EXTENSION};
-use crate::disk::{bits_reader::BitsReader, bits_writer::BitsWriter};
+use super::{utils, DOCUMENTS_EXTENSION, EXTERNAL_IDS_EXTENSION};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Result,
+};
+use std::{fs, path::Path};
 
 #[derive(Clone)]
 pub struct Document {
     pub path: String,
     pub length: u32,
+    // id of the record in the dump it was read from
+    pub external_id: Option<String>,
 }
 
 pub struct Documents {
     docs: Vec<Document>,
-    avg_len: f64,
+}
+
+// writes documents as they come, spilling them to disk until their number is known
+pub struct DocumentsWriter {
+    writer: BitsWriter,
+    output_path: String,
+    spill_path: String,
+    num_documents: u32,
+    has_external_ids: bool,
 }
 
 impl Documents {
-    pub fn load_documents(input_path: &str) -> Documents {
-        let mut reader = BitsReader::new(&(input_path.to_string() + DOCUMENTS_EXTENSION));
+    pub fn load_documents(input_path: &str) -> Result<Documents> {
+        let mut reader = BitsReader::new(&(input_path.to_string() + DOCUMENTS_EXTENSION))?;
 
         let mut prev = String::new();
 
-        let mut length_sum = 0;
-
-        let docs: Vec<Document> = (0..reader.read_vbyte())
+        let mut docs: Vec<Document> = (0..reader.read_vbyte()?)
             .map(|_| {
-                let p_len = reader.read_gamma();
+                let p_len = reader.read_gamma()?;
                 let prefix: String = prev.chars().take(p_len as usize).collect();
-                let path = prefix + &reader.read_str();
+                let path = prefix + &reader.read_str()?;
                 prev = path.clone();
 
-                let length = reader.read_vbyte();
-                length_sum += length;
+                let length = reader.read_vbyte()?;
 
-                Document { path, length }
+                Ok(Document {
+                    path,
+                    length,
+                    external_id: None,
+                })
             })
-            .collect();
+            .collect::<Result<_>>()?;
+
+        // external ids are only written for documents read from dumps
+        let ids_path = input_path.to_string() + EXTERNAL_IDS_EXTENSION;
+        if Path::new(&ids_path).exists() {
+            let mut reader = BitsReader::new(&ids_path)?;
+            for doc in docs.iter_mut().take(reader.read_vbyte()? as usize) {
+                let id = reader.read_str()?;
+                doc.external_id = (!id.is_empty()).then_some(id);
+            }
+        }
+
+        Ok(Documents { docs })
+    }
 
-        let avg_len = length_sum as f64 / docs.len() as f64;
+    pub fn get_num_documents(&self) -> u32 {
+        self.docs.len() as u32
+    }
 
-        Documents { docs, avg_len }
+    pub fn get_doc_len(&self, doc_id: u32) -> u32 {
+        self.docs[doc_id as usize].length
     }
 
-    pub fn write_documents(documents: &Vec<Document>, output_path: &str) {
-        let path = output_path.to_string() + DOCUMENTS_EXTENSION;
-        let mut writer = BitsWriter::new(&path);
+    pub fn get_doc_path(&self, doc_id: u32) -> String {
+        self.docs[doc_id as usize].path.clone()
+    }
 
-        let mut prev = "";
+    pub fn get_external_id(&self, doc_id: u32) -> Option<String> {
+        self.docs[doc_id as usize].external_id.clone()
+    }
+}
 
-        writer.write_vbyte(documents.len() as u32);
-        for l in documents {
-            let p_len = utils::get_matching_prefix_len(prev, &l.path);
-            writer.write_gamma(p_len as u32);
-            let remaining: String = l.path.chars().skip(p_len).collect();
-            prev = &l.path;
+impl DocumentsWriter {
+    pub fn new(output_path: &str) -> Result<DocumentsWriter> {
+        let spill_path = format!("{output_path}{DOCUMENTS_EXTENSION}.run");
+
+        Ok(DocumentsWriter {
+            writer: BitsWriter::new(&spill_path)?,
+            output_path: output_path.to_string(),
+            spill_path,
+            num_documents: 0,
+            has_external_ids: false,
+        })
+    }
 
-            writer.write_str(&remaining);
-            writer.write_vbyte(l.length);
-        }
+    pub fn add_document(&mut self, document: &Document) {
+        self.writer.write_str(&document.path);
+        self.writer.write_vbyte(document.length);
+        self.writer
+            .write_str(document.external_id.as_deref().unwrap_or_default());
 
-        writer.flush();
+        self.num_documents += 1;
+        self.has_external_ids |= document.external_id.is_some();
     }
 
     pub fn get_num_documents(&self) -> u32 {
-        self.docs.len() as u32
+        self.num_documents
     }
 
-    pub fn get_doc_len(&self, doc_id: u32) -> u32 {
-        self.docs[doc_id as usize].length
-    }
+    pub fn flush(mut self) -> Result<()> {
+        self.writer.flush()?;
+        let mut reader = BitsReader::new(&self.spill_path)?;
+
+        let path = self.output_path.clone() + DOCUMENTS_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+        writer.write_vbyte(self.num_documents);
+
+        // external ids are only written for documents read from dumps,
+        // the ones without an id get an empty one
+        let mut ids_writer = match self.has_external_ids {
+            true => Some(BitsWriter::new(
+                &(self.output_path.clone() + EXTERNAL_IDS_EXTENSION),
+            )?),
+            false => None,
+        };
+        if let Some(ids_writer) = &mut ids_writer {
+            ids_writer.write_vbyte(self.num_documents);
+        }
 
-    pub fn get_avg_doc_len(&self) -> f64 {
-        self.avg_len
-    }
+        let mut prev = String::new();
 
-    pub fn get_doc_path(&self, doc_id: u32) -> String {
-        self.docs[doc_id as usize].path.clone()
+        for _ in 0..self.num_documents {
+            let doc_path = reader.read_str()?;
+            let length = reader.read_vbyte()?;
+            let external_id = reader.read_str()?;
+
+            let p_len = utils::get_matching_prefix_len(&prev, &doc_path);
+            writer.write_gamma(p_len as u32);
+            let remaining: String = doc_path.chars().skip(p_len).collect();
+            prev = doc_path;
+
+            writer.write_str(&remaining);
+            writer.write_vbyte(length);
+
+            if let Some(ids_writer) = &mut ids_writer {
+                ids_writer.write_str(&external_id);
+            }
+        }
+
+        writer.flush()?;
+        if let Some(mut ids_writer) = ids_writer {
+            ids_writer.flush()?;
+        }
+
+        drop(reader);
+        let _ = fs::remove_file(&self.spill_path);
+        Ok(())
     }
 }
 
@@ -86,25 +167,30 @@ mod tests {
     fn test_write_and_load() {
         let dir = create_temporary_file_path("docs_unit");
 
-        let documents = vec![
+        let documents = [
             Document {
                 path: "document1.txt".to_string(),
                 length: 100,
+                external_id: None,
             },
             Document {
                 path: "document2.txt".to_string(),
                 length: 150,
+                external_id: Some("b2".to_string()),
             },
         ];
 
-        Documents::write_documents(&documents, &dir);
-        let loaded_documents = Documents::load_documents(&dir);
+        let mut writer = DocumentsWriter::new(&dir).unwrap();
+        documents.iter().for_each(|d| writer.add_document(d));
+        writer.flush().unwrap();
+        let loaded_documents = Documents::load_documents(&dir).unwrap();
 
         assert_eq!(loaded_documents.get_num_documents(), documents.len() as u32);
 
         for (i, d) in documents.iter().enumerate() {
             assert_eq!(loaded_documents.get_doc_path(i as u32), d.path);
             assert_eq!(loaded_documents.get_doc_len(i as u32), d.length);
+            assert_eq!(loaded_documents.get_external_id(i as u32), d.external_id);
         }
     }
 
@@ -114,16 +200,17 @@ mod tests {
             Document {
                 path: "document1.txt".to_string(),
                 length: 100,
+                external_id: None,
             },
             Document {
                 path: "document2.txt".to_string(),
                 length: 150,
+                external_id: Some("b2".to_string()),
             },
         ];
 
         let doc_collection = Documents {
             docs: documents.clone(),
-            avg_len: 125.0,
         };
 
         assert_eq!(doc_collection.get_num_documents(), documents.len() as u32);
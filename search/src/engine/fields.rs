@@ -0,0 +1,230 @@
+use super::{
+    documents::Documents,
+    postings::{Postings, PostingsCodec},
+    vocabulary::Vocabulary,
+    FIELDS_EXTENSION, LENGTHS_EXTENSION, VOCABULARY_ALPHA_EXTENSION,
+};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Result,
+};
+use std::path::Path;
+
+// the only field of documents read from plain files
+pub const DEFAULT_FIELD: &str = "body";
+// positions of a field are shifted by its id times the gap when fields
+// are searched together, so that phrases and windows never span two fields
+pub const FIELD_POSITION_GAP: u32 = 1 << 24;
+
+// a document made of named text fields, values of repeated fields are concatenated
+pub struct Record {
+    pub path: String,
+    pub id: Option<String>,
+    pub fields: Vec<(String, String)>,
+}
+
+// names of the fields of an index, the position is the field id
+pub struct Fields {
+    names: Vec<String>,
+}
+
+// the vocabulary, postings and lengths of a single field of a segment
+pub struct FieldIndex {
+    pub vocabulary: Vocabulary,
+    pub postings: Postings,
+    lengths: Vec<u32>,
+    lengths_sum: u64,
+}
+
+impl Record {
+    pub fn new(path: &str) -> Record {
+        Record {
+            path: path.to_string(),
+            id: None,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with_id(mut self, id: &str) -> Record {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn with_field(mut self, name: &str, text: &str) -> Record {
+        self.fields.push((name.to_string(), text.to_string()));
+        self
+    }
+}
+
+impl Fields {
+    pub fn new() -> Fields {
+        Fields { names: Vec::new() }
+    }
+
+    pub fn write_fields(&self, index_path: &str) -> Result<()> {
+        let path = index_path.to_string() + FIELDS_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(self.names.len() as u32);
+        self.names.iter().for_each(|n| {
+            writer.write_str(n);
+        });
+
+        writer.flush()
+    }
+
+    pub fn load_fields(index_path: &str) -> Result<Fields> {
+        let path = index_path.to_string() + FIELDS_EXTENSION;
+
+        // indexes built before fields existed only have the default one
+        if !Path::new(&path).exists() {
+            return Ok(Fields {
+                names: vec![DEFAULT_FIELD.to_string()],
+            });
+        }
+
+        let mut reader = BitsReader::new(&path)?;
+        let names = (0..reader.read_vbyte()?)
+            .map(|_| reader.read_str())
+            .collect::<Result<_>>()?;
+
+        Ok(Fields { names })
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    }
+
+    pub fn get_field_id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn get_or_insert_field_id(&mut self, name: &str) -> usize {
+        self.get_field_id(name).unwrap_or_else(|| {
+            self.names.push(name.to_string());
+            self.names.len() - 1
+        })
+    }
+
+    pub fn get_name(&self, field_id: usize) -> &str {
+        &self.names[field_id]
+    }
+
+    pub fn get_num_fields(&self) -> usize {
+        self.names.len()
+    }
+}
+
+impl FieldIndex {
+    pub fn get_path(segment_path: &str, field_id: usize) -> String {
+        // the first field keeps the plain segment path, like single field indexes
+        match field_id {
+            0 => segment_path.to_string(),
+            id => format!("{segment_path}.f{id}"),
+        }
+    }
+
+    pub fn load_field_index(
+        segment_path: &str,
+        field_id: usize,
+        documents: &Documents,
+        codec: PostingsCodec,
+    ) -> Result<Option<FieldIndex>> {
+        let path = Self::get_path(segment_path, field_id);
+
+        // segments written before a field was added do not hold it
+        if field_id > 0 && !Path::new(&(path.clone() + VOCABULARY_ALPHA_EXTENSION)).exists() {
+            return Ok(None);
+        }
+
+        // lengths of the first field are the document lengths
+        let lengths: Vec<u32> = match field_id {
+            0 => (0..documents.get_num_documents())
+                .map(|doc_id| documents.get_doc_len(doc_id))
+                .collect(),
+            _ => Self::load_lengths(&path)?,
+        };
+
+        Ok(Some(FieldIndex {
+            vocabulary: Vocabulary::load_vocabulary(&path)?,
+            postings: Postings::load_postings_reader(&path, codec)?,
+            lengths_sum: lengths.iter().map(|l| *l as u64).sum(),
+            lengths,
+        }))
+    }
+
+    pub fn write_lengths(lengths: &[u32], field_path: &str) -> Result<()> {
+        let path = field_path.to_string() + LENGTHS_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(lengths.len() as u32);
+        lengths.iter().for_each(|l| {
+            writer.write_vbyte(*l);
+        });
+
+        writer.flush()
+    }
+
+    pub fn load_lengths(field_path: &str) -> Result<Vec<u32>> {
+        let path = field_path.to_string() + LENGTHS_EXTENSION;
+        let mut reader = BitsReader::new(&path)?;
+
+        (0..reader.read_vbyte()?)
+            .map(|_| reader.read_vbyte())
+            .collect()
+    }
+
+    pub fn get_len(&self, doc_id: u32) -> u32 {
+        self.lengths
+            .get(doc_id as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn get_lengths_sum(&self) -> u64 {
+        self.lengths_sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    #[test]
+    fn test_write_and_load_fields() {
+        let path = create_temporary_file_path("fields_unit");
+
+        let fields = Fields::load_fields(&path).unwrap();
+        assert_eq!(fields.get_num_fields(), 1);
+        assert_eq!(fields.get_name(0), DEFAULT_FIELD);
+
+        let mut fields = Fields::new();
+        assert_eq!(fields.get_or_insert_field_id("title"), 0);
+        assert_eq!(fields.get_or_insert_field_id("body"), 1);
+        assert_eq!(fields.get_or_insert_field_id("title"), 0);
+        fields.write_fields(&path).unwrap();
+
+        let fields = Fields::load_fields(&path).unwrap();
+        assert_eq!(fields.get_field_id("body"), Some(1));
+        assert_eq!(fields.get_field_id("author"), None);
+
+        assert!(Fields::is_valid_name("page_title2"));
+        assert!(!Fields::is_valid_name("Title"));
+        assert!(!Fields::is_valid_name("a:b"));
+    }
+
+    #[test]
+    fn test_write_and_load_lengths() {
+        let path = create_temporary_file_path("lengths_unit");
+
+        FieldIndex::write_lengths(&[3, 0, 12], &path).unwrap();
+        assert_eq!(FieldIndex::load_lengths(&path).unwrap(), [3, 0, 12]);
+
+        assert_eq!(FieldIndex::get_path("idx", 0), "idx");
+        assert_eq!(FieldIndex::get_path("idx.2", 3), "idx.2.f3");
+    }
+}
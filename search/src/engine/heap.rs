@@ -8,7 +8,7 @@ pub struct Entry {
 
 impl PartialEq for Entry {
     fn eq(&self, other: &Self) -> bool {
-        self.priority == other.priority
+        self.priority == other.priority && self.id == other.id
     }
 }
 
@@ -26,6 +26,8 @@ impl Ord for Entry {
             .priority
             .partial_cmp(&self.priority)
             .unwrap_or(Ordering::Equal)
+            // on equal scores the lowest id ranks first, whatever the push order
+            .then(self.id.cmp(&other.id))
     }
 }
 
@@ -53,9 +55,17 @@ impl FixedMinHeap {
         }
     }
 
+    // the score to beat to enter the top k, none until it is full
+    pub fn get_min_priority(&self) -> Option<f64> {
+        match self.heap.len() < self.capacity {
+            true => None,
+            false => self.heap.peek().map(|e| e.priority),
+        }
+    }
+
     pub fn get_sorted_id_priority_pairs(&mut self) -> Vec<(u32, f64)> {
-        let mut res: Vec<(u32, f64)> = (0..self.capacity)
-            .filter_map(|_| self.heap.pop().map(|e| (e.id, e.priority)))
+        let mut res: Vec<(u32, f64)> = std::iter::from_fn(|| self.heap.pop())
+            .map(|e| (e.id, e.priority))
             .collect();
 
         res.reverse();
@@ -82,6 +92,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_ties_and_min_priority() {
+        let mut selector = FixedMinHeap::new(2);
+
+        selector.push(5, 0.4);
+        assert_eq!(selector.get_min_priority(), None);
+        selector.push(3, 0.4);
+        assert_eq!(selector.get_min_priority(), Some(0.4));
+        selector.push(1, 0.4);
+
+        assert_eq!(
+            selector.get_sorted_id_priority_pairs(),
+            [(1, 0.4), (3, 0.4)]
+        );
+    }
+
     #[test]
     fn test_top_less_than_k() {
         let mut selector = FixedMinHeap::new(3);
@@ -0,0 +1,124 @@
+// accepts the words within a number of edits of a term, read one char at a time,
+// so that words sharing a prefix share the states reached on it
+pub struct LevenshteinAutomaton {
+    term: Vec<char>,
+    max_distance: usize,
+    // swapping two adjacent chars is one edit (restricted Damerau) instead of two
+    transpositions: bool,
+}
+
+// the distances of the prefix read so far to every prefix of the term,
+// and the ones of the prefix before, needed for transpositions
+#[derive(Clone, Debug)]
+pub struct AutomatonState {
+    row: Vec<usize>,
+    prev_row: Vec<usize>,
+    last: Option<char>,
+}
+
+impl LevenshteinAutomaton {
+    pub fn new(term: &str, max_distance: u32, transpositions: bool) -> LevenshteinAutomaton {
+        LevenshteinAutomaton {
+            term: term.chars().collect(),
+            max_distance: max_distance as usize,
+            transpositions,
+        }
+    }
+
+    pub fn start(&self) -> AutomatonState {
+        AutomatonState {
+            row: (0..=self.term.len()).map(|d| self.cap(d)).collect(),
+            prev_row: Vec::new(),
+            last: None,
+        }
+    }
+
+    pub fn step(&self, state: &AutomatonState, c: char) -> AutomatonState {
+        let row = &state.row;
+        let mut next = Vec::with_capacity(row.len());
+        next.push(self.cap(row[0] + 1));
+
+        for (j, t) in self.term.iter().enumerate() {
+            let substitution = row[j] + usize::from(*t != c);
+            let mut distance = substitution.min(row[j + 1] + 1).min(next[j] + 1);
+
+            if self.transpositions && j > 0 && state.last == Some(*t) && self.term[j - 1] == c {
+                distance = distance.min(state.prev_row[j - 1] + 1);
+            }
+            next.push(self.cap(distance));
+        }
+
+        AutomatonState {
+            row: next,
+            prev_row: state.row.clone(),
+            last: Some(c),
+        }
+    }
+
+    // whether any word starting with the chars read so far can be accepted
+    pub fn can_match(&self, state: &AutomatonState) -> bool {
+        state.row.iter().any(|d| *d <= self.max_distance)
+    }
+
+    pub fn get_distance(&self, state: &AutomatonState) -> Option<u32> {
+        let distance = state.row[self.term.len()];
+        (distance <= self.max_distance).then_some(distance as u32)
+    }
+
+    fn cap(&self, distance: usize) -> usize {
+        // distances above the maximum are all the same, which keeps states small
+        distance.min(self.max_distance + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_distance(automaton: &LevenshteinAutomaton, word: &str) -> Option<u32> {
+        let state = word
+            .chars()
+            .fold(automaton.start(), |s, c| automaton.step(&s, c));
+        automaton.get_distance(&state)
+    }
+
+    #[test]
+    fn test_levenshtein_automaton() {
+        let automaton = LevenshteinAutomaton::new("color", 2, false);
+        assert_eq!(get_distance(&automaton, "color"), Some(0));
+        assert_eq!(get_distance(&automaton, "colour"), Some(1));
+        assert_eq!(get_distance(&automaton, "colr"), Some(1));
+        assert_eq!(get_distance(&automaton, "cloor"), Some(2));
+        assert_eq!(get_distance(&automaton, "dolours"), None);
+        assert_eq!(get_distance(&automaton, ""), None);
+
+        // prefixes too far from any prefix of the term end the search
+        let state = "xyz"
+            .chars()
+            .fold(automaton.start(), |s, c| automaton.step(&s, c));
+        assert!(!automaton.can_match(&state));
+        let state = automaton.step(&automaton.start(), 'x');
+        assert!(automaton.can_match(&state));
+
+        let automaton = LevenshteinAutomaton::new("rivière", 1, false);
+        assert_eq!(get_distance(&automaton, "riviere"), Some(1));
+        assert_eq!(get_distance(&automaton, "rivire"), Some(1));
+    }
+
+    #[test]
+    fn test_damerau_automaton() {
+        let levenshtein = LevenshteinAutomaton::new("color", 1, false);
+        let damerau = LevenshteinAutomaton::new("color", 1, true);
+        assert_eq!(get_distance(&levenshtein, "colro"), None);
+        assert_eq!(get_distance(&damerau, "colro"), Some(1));
+        assert_eq!(get_distance(&damerau, "oclor"), Some(1));
+        assert_eq!(get_distance(&damerau, "cloor"), Some(1));
+        assert_eq!(get_distance(&damerau, "color"), Some(0));
+        assert_eq!(get_distance(&damerau, "ocolr"), None);
+
+        let damerau = LevenshteinAutomaton::new("ab", 2, true);
+        assert_eq!(get_distance(&damerau, "ba"), Some(1));
+        // swapped chars are not edited again, as with the optimal string alignment
+        assert_eq!(get_distance(&damerau, "bca"), None);
+    }
+}
@@ -0,0 +1,382 @@
+use super::{
+    utils::{read_varint, write_varint},
+    MANIFEST_EXTENSION,
+};
+use crate::{disk::checksum, Error, Result};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+// bumped whenever the layout of any index file changes
+pub const FORMAT_VERSION: u32 = 4;
+const MAGIC: &[u8; 8] = b"SRCHIDX\0";
+const CHECKSUM_BYTES: usize = 4;
+
+// describes a whole index: how it was built, what it holds and the
+// files it is made of, written last by builds, updates and compactions
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexManifest {
+    pub version: u32,
+    pub analyzer: String,
+    pub codec: String,
+    pub min_freq_threshold: u32,
+    pub max_freq_percentage_threshold: f64,
+    // live documents, and terms summed over the vocabularies of segments and fields
+    pub num_documents: u64,
+    pub num_terms: u64,
+    pub files: Vec<IndexFile>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexFile {
+    // relative to the index folder, so that indexes can be moved
+    pub name: String,
+    pub len: u64,
+    pub checksum: u32,
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    MissingManifest(String),
+    InvalidManifest(String),
+    UnsupportedVersion(u32),
+    MissingFile(String),
+    SizeMismatch {
+        file: String,
+        expected: u64,
+        found: u64,
+    },
+    ChecksumMismatch(String),
+    Io(String, io::Error),
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::MissingManifest(path) => {
+                write!(
+                    f,
+                    "no index manifest at {path}, the index must be (re)built"
+                )
+            }
+            IndexError::InvalidManifest(path) => write!(f, "corrupted index manifest {path}"),
+            IndexError::UnsupportedVersion(version) => write!(
+                f,
+                "index format version {version} is not supported, expected {FORMAT_VERSION}"
+            ),
+            IndexError::MissingFile(file) => write!(f, "missing index file {file}"),
+            IndexError::SizeMismatch {
+                file,
+                expected,
+                found,
+            } => write!(
+                f,
+                "index file {file} is {found} bytes long, expected {expected}"
+            ),
+            IndexError::ChecksumMismatch(file) => write!(f, "corrupted index file {file}"),
+            IndexError::Io(path, err) => write!(f, "can not read {path}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IndexError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl IndexManifest {
+    pub fn write_index_manifest(&self, index_path: &str) -> Result<()> {
+        // plain bytes rather than a bit stream, so that the manifest
+        // can be checked before anything is decoded from it
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+
+        write_bytes(&mut bytes, self.analyzer.as_bytes());
+        write_bytes(&mut bytes, self.codec.as_bytes());
+        write_varint(&mut bytes, self.min_freq_threshold as usize);
+        bytes.extend_from_slice(&self.max_freq_percentage_threshold.to_le_bytes());
+        write_varint(&mut bytes, self.num_documents as usize);
+        write_varint(&mut bytes, self.num_terms as usize);
+
+        write_varint(&mut bytes, self.files.len());
+        for file in &self.files {
+            write_bytes(&mut bytes, file.name.as_bytes());
+            write_varint(&mut bytes, file.len as usize);
+            bytes.extend_from_slice(&file.checksum.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&checksum::checksum(&bytes).to_le_bytes());
+
+        // replaced with a rename, as the other index files
+        let path = index_path.to_string() + MANIFEST_EXTENSION;
+        let tmp_path = path.clone() + ".tmp";
+        fs::write(&tmp_path, bytes).map_err(|e| Error::io(&tmp_path, e))?;
+        fs::rename(&tmp_path, &path).map_err(|e| Error::io(&path, e))
+    }
+
+    pub fn read_index_manifest(index_path: &str) -> Result<IndexManifest, IndexError> {
+        let path = index_path.to_string() + MANIFEST_EXTENSION;
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(IndexError::MissingManifest(path))
+            }
+            Err(err) => return Err(IndexError::Io(path, err)),
+        };
+
+        // the trailing checksum covers everything before it
+        let header_len = MAGIC.len() + 4;
+        if bytes.len() < header_len + CHECKSUM_BYTES || !bytes.starts_with(MAGIC) {
+            return Err(IndexError::InvalidManifest(path));
+        }
+        let (content, expected) = bytes.split_at(bytes.len() - CHECKSUM_BYTES);
+        if checksum::checksum(content).to_le_bytes() != expected {
+            return Err(IndexError::InvalidManifest(path));
+        }
+
+        let version = u32::from_le_bytes(content[MAGIC.len()..header_len].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+
+        let mut i = header_len;
+        let analyzer = read_string(content, &mut i);
+        let codec = read_string(content, &mut i);
+        let min_freq_threshold = read_varint(content, &mut i) as u32;
+        let max_freq_percentage_threshold =
+            f64::from_le_bytes(content[i..i + 8].try_into().unwrap());
+        i += 8;
+        let num_documents = read_varint(content, &mut i) as u64;
+        let num_terms = read_varint(content, &mut i) as u64;
+
+        let files = (0..read_varint(content, &mut i))
+            .map(|_| {
+                let name = read_string(content, &mut i);
+                let len = read_varint(content, &mut i) as u64;
+                let checksum = u32::from_le_bytes(content[i..i + 4].try_into().unwrap());
+                i += 4;
+                IndexFile {
+                    name,
+                    len,
+                    checksum,
+                }
+            })
+            .collect();
+
+        Ok(IndexManifest {
+            version,
+            analyzer,
+            codec,
+            min_freq_threshold,
+            max_freq_percentage_threshold,
+            num_documents,
+            num_terms,
+            files,
+        })
+    }
+
+    pub fn load_index_manifest(index_path: &str) -> Result<IndexManifest, IndexError> {
+        let manifest = Self::read_index_manifest(index_path)?;
+        manifest.verify_files(index_path)?;
+        Ok(manifest)
+    }
+
+    pub fn verify_files(&self, index_path: &str) -> Result<(), IndexError> {
+        let folder = get_index_folder(index_path);
+
+        for file in &self.files {
+            let path = folder.join(&file.name);
+            let path_str = path.to_string_lossy().to_string();
+
+            // sizes are checked first, catching truncated files without reading them
+            let len = match fs::metadata(&path) {
+                Ok(metadata) => metadata.len(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    return Err(IndexError::MissingFile(path_str))
+                }
+                Err(err) => return Err(IndexError::Io(path_str, err)),
+            };
+            if len != file.len {
+                return Err(IndexError::SizeMismatch {
+                    file: path_str,
+                    expected: file.len,
+                    found: len,
+                });
+            }
+
+            let (_, checksum) = checksum::checksum_file(&path_str)
+                .map_err(|e| IndexError::Io(path_str.clone(), e))?;
+            if checksum != file.checksum {
+                return Err(IndexError::ChecksumMismatch(path_str));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_index_files(index_path: &str) -> Result<Vec<IndexFile>> {
+        // every file of the index shares its name, followed by an extension
+        let folder = get_index_folder(index_path);
+        let name = Path::new(index_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let prefix = format!("{name}.");
+        let manifest_name = name.to_string() + MANIFEST_EXTENSION;
+
+        let mut names: Vec<String> = fs::read_dir(&folder)
+            .map_err(|e| Error::io(&folder.to_string_lossy(), e))?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| e.file_name().to_str().map(str::to_string))
+            .filter(|n| n.starts_with(&prefix) && *n != manifest_name && !n.ends_with(".tmp"))
+            .collect();
+        names.sort_unstable();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let path = folder.join(&name).to_string_lossy().to_string();
+                let (len, checksum) =
+                    checksum::checksum_file(&path).map_err(|e| Error::io(&path, e))?;
+                Ok(IndexFile {
+                    name,
+                    len,
+                    checksum,
+                })
+            })
+            .collect()
+    }
+}
+
+fn get_index_folder(index_path: &str) -> PathBuf {
+    match Path::new(index_path).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn write_bytes(output: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(output, bytes.len());
+    output.extend_from_slice(bytes);
+}
+
+fn read_string(input: &[u8], i: &mut usize) -> String {
+    let len = read_varint(input, i);
+    let s = String::from_utf8_lossy(&input[*i..*i + len]).into_owned();
+    *i += len;
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_dir_path;
+
+    fn write_test_index(folder: &str) -> (String, IndexManifest) {
+        let index_path = format!("{folder}/idx");
+        fs::create_dir_all(folder).unwrap();
+        fs::write(format!("{index_path}.docs"), "documents").unwrap();
+        fs::write(format!("{index_path}.postings"), "postings").unwrap();
+        // neither temporary files nor other indexes belong to the manifest
+        fs::write(format!("{index_path}.postings.tmp"), "partial").unwrap();
+        fs::write(format!("{folder}/other.docs"), "other").unwrap();
+
+        let manifest = IndexManifest {
+            version: FORMAT_VERSION,
+            analyzer: "standard,lowercase".to_string(),
+            codec: "bitpack".to_string(),
+            min_freq_threshold: 1,
+            max_freq_percentage_threshold: 0.99,
+            num_documents: 3,
+            num_terms: 42,
+            files: IndexManifest::get_index_files(&index_path).unwrap(),
+        };
+        manifest.write_index_manifest(&index_path).unwrap();
+
+        (index_path, manifest)
+    }
+
+    #[test]
+    fn test_write_and_load_manifest() {
+        let (index_path, manifest) = write_test_index(&create_temporary_dir_path());
+
+        let names: Vec<&str> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["idx.docs", "idx.postings"]);
+        assert_eq!(manifest.files[0].len, 9);
+
+        assert_eq!(
+            IndexManifest::load_index_manifest(&index_path).unwrap(),
+            manifest
+        );
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let folder = create_temporary_dir_path();
+        let (index_path, _) = write_test_index(&folder);
+        let manifest_path = index_path.clone() + MANIFEST_EXTENSION;
+
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&format!("{folder}/missing")),
+            Err(IndexError::MissingManifest(_))
+        ));
+
+        let mut bytes = fs::read(&manifest_path).unwrap();
+        bytes[20] ^= 1;
+        fs::write(&manifest_path, &bytes).unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::InvalidManifest(_))
+        ));
+
+        fs::write(&manifest_path, "garbage").unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::InvalidManifest(_))
+        ));
+
+        // a newer version, with a valid checksum
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        bytes.extend_from_slice(&checksum::checksum(&bytes).to_le_bytes());
+        fs::write(&manifest_path, &bytes).unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_damaged_files() {
+        let (index_path, _) = write_test_index(&create_temporary_dir_path());
+
+        fs::write(format!("{index_path}.docs"), "documenta").unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::ChecksumMismatch(_))
+        ));
+
+        fs::write(format!("{index_path}.docs"), "docu").unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::SizeMismatch {
+                expected: 9,
+                found: 4,
+                ..
+            })
+        ));
+
+        fs::remove_file(format!("{index_path}.docs")).unwrap();
+        assert!(matches!(
+            IndexManifest::load_index_manifest(&index_path),
+            Err(IndexError::MissingFile(_))
+        ));
+    }
+}
@@ -1,352 +1,2767 @@
+mod analysis;
+mod blocks;
 mod builder;
 mod documents;
+mod fields;
 mod heap;
+mod levenshtein;
+mod manifest;
 mod postings;
-mod preprocessor;
+mod query;
+mod scoring;
+mod segments;
+mod snippets;
+mod sources;
+mod spelling;
+mod store;
 mod utils;
 mod vocabulary;
+mod wand;
 
-use self::documents::{Document, Documents};
-use self::heap::FixedMinHeap;
-use self::postings::{DocumentIdsList, Postings, PostingsList};
-use self::preprocessor::Preprocessor;
-use self::vocabulary::Vocabulary;
-use phf::phf_map;
+use self::analysis::Analyzers;
+pub use self::analysis::{
+    Analyzer, AnalyzerConfig, FilterConfig, Language, Pipeline, Token, TokenFilter, Tokenizer,
+    TokenizerConfig,
+};
+use self::fields::{Fields, FIELD_POSITION_GAP};
+pub use self::fields::{Record, DEFAULT_FIELD};
+pub use self::manifest::{IndexError, IndexFile, IndexManifest};
+pub use self::postings::PostingsCodec;
+use self::postings::{DocumentIdsList, Posting, Postings, PostingsList};
+use self::query::{is_free_wildcard, split_fuzzy, MAX_FUZZY_DISTANCE};
+pub use self::query::{QueryError, QueryNode};
+pub use self::scoring::{parse_scorer, Bm25, Bm25Plus, Dfr, Dirichlet, Scorer, TermStats, TfIdf};
+use self::segments::Segment;
+pub use self::snippets::Snippet;
+pub use self::sources::{SourceConfig, SourceFormat};
+use self::spelling::{rank_corrections, Correction, TermCandidates};
+pub use self::store::StoreConfig;
+use self::wand::{get_top_documents, TermCursor, TermWeight, TopDocuments};
 use std::cmp::min;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::{Error, Result};
+
 pub const POSTINGS_EXTENSION: &str = ".postings";
 pub const OFFSETS_EXTENSION: &str = ".offsets";
 pub const DOCUMENTS_EXTENSION: &str = ".docs";
 pub const VOCABULARY_ALPHA_EXTENSION: &str = ".alphas";
+pub const SEGMENTS_EXTENSION: &str = ".segments";
+pub const TOMBSTONES_EXTENSION: &str = ".deleted";
+pub const ANALYZER_EXTENSION: &str = ".analyzer";
+pub const FIELDS_EXTENSION: &str = ".fields";
+pub const LENGTHS_EXTENSION: &str = ".lengths";
+pub const EXTERNAL_IDS_EXTENSION: &str = ".ids";
+pub const SOURCE_EXTENSION: &str = ".source";
+pub const STORE_EXTENSION: &str = ".store";
+pub const STORE_OFFSETS_EXTENSION: &str = ".store_offsets";
+pub const STORED_FIELDS_EXTENSION: &str = ".stored";
+pub const MANIFEST_EXTENSION: &str = ".manifest";
+pub const CODEC_EXTENSION: &str = ".codec";
 
-const WINDOW_SCORE_MULTIPLIER: f64 = 3.0;
-const BM25_SCORE_MULTIPLIER: f64 = 1.0;
+const DEFAULT_PROXIMITY_WEIGHT: f64 = 3.0;
 
-const BM25_KL: f64 = 1.2;
-const BM25_B: f64 = 0.75;
+// the closest and most frequent terms matching a wildcard or a fuzzy term are kept
+const MAX_EXPANDED_TERMS: usize = 64;
 
-static BOOLEAN_PRECEDENCE: phf::Map<&'static str, &u8> = phf_map! {
-    "NOT" => &3,
-    "AND" => &2,
-    "OR" => &1,
-    "(" => &0,
-    ")" => &0,
-};
+// terms that may have been meant instead of a query one, and queries suggested
+const MAX_TERM_CANDIDATES: usize = 5;
+const MAX_SUGGESTIONS: usize = 3;
+// valid terms are only suggested the ones found in that many times more documents
+const RARE_TERM_RATIO: usize = 10;
+
+const SNIPPET_WINDOW_LEN: usize = 30;
+const SNIPPET_MAX_FRAGMENTS: usize = 2;
 
 pub struct Engine {
-    vocabulary: Vocabulary,
-    postings: Postings,
-    documents: Documents,
-    preprocessor: Preprocessor,
+    segments: Vec<Segment>,
+    fields: Fields,
+    analyzers: Analyzers,
+    manifest: IndexManifest,
 }
 
-pub struct InMemory {
-    term_index_map: BTreeMap<String, usize>,
-    postings: Vec<PostingsList>,
-    documents: Vec<Document>,
+// how documents are analyzed and stored, recorded in the index at build time
+#[derive(Clone, Default)]
+pub struct IndexConfig {
+    pub analyzer: AnalyzerConfig,
+    pub store: StoreConfig,
+    pub codec: PostingsCodec,
+}
+
+pub struct QueryOptions {
+    // analyze the query in this language instead of the index one
+    pub language: Option<Language>,
+    // weights of fields, the ones not listed weigh 1
+    pub field_weights: Vec<(String, f64)>,
+    // relevance of documents to the terms of free queries
+    pub scorer: Arc<dyn Scorer>,
+    // added to the relevance, times the number of terms over the smallest
+    // window holding all of them, zero ranks on relevance alone
+    pub proximity_weight: f64,
+    // boolean matches are ranked on their positive terms, or all returned in doc id order
+    pub ranked: bool,
+    // fuzzy terms count swapping two adjacent chars as one edit instead of two
+    pub transpositions: bool,
+    // terms missing from the index are replaced by their most likely correction,
+    // or searched as they are, suggestions are made either way
+    pub spellcheck: bool,
 }
 
 pub struct QueryResult {
+    // the analyzed terms of the query as typed, and the ones searched
+    pub original_query: Vec<String>,
     pub query: Vec<String>,
+    // whether misspelled terms were corrected to find the documents
+    pub corrected: bool,
+    // the queries most likely meant, the first one is the correction
+    pub suggestions: Vec<Suggestion>,
     pub documents: Vec<DocumentResult>,
+    pub total_hits: usize,
+    pub total_hits_exact: bool,
     pub time_ms: u128,
 }
 
+// the terms of a query close to the one typed, the number of documents
+// holding all of its terms that are not negated, and how likely it was meant
+pub struct Suggestion {
+    pub query: Vec<String>,
+    pub hits: usize,
+    pub score: f64,
+}
+
 pub struct DocumentResult {
     pub id: u32,
     pub path: String,
+    pub external_id: Option<String>,
     pub score: f64,
+    pub positions: Vec<u32>,
+}
+
+// the top k documents of a query, along with what is needed to report them
+struct RankedDocuments {
+    top: TopDocuments,
+    // of each distinct term
+    document_counts: Vec<usize>,
 }
 
 #[derive(Default)]
 struct DocumentScore {
-    tf_idf: f64,
+    relevance: f64,
     term_positions: HashMap<u32, Vec<u32>>,
 }
 
+// misspelled terms of a query mapped to the ones searched instead
+type Corrections = HashMap<(Option<usize>, String), String>;
+
+#[derive(Default)]
+struct Spelling {
+    corrections: Corrections,
+    suggestions: Vec<Suggestion>,
+}
+
+// NEAR needs term positions, while the other operators only need document ids,
+// single terms are only loaded once it is known which of the two is needed
+enum BooleanOperand {
+    Term(Option<usize>, String),
+    // the terms matching a wildcard or a fuzzy term, any of them matches
+    Terms(Option<usize>, Vec<String>),
+    Postings(PostingsList),
+    DocIds(DocumentIdsList),
+}
+
+impl Default for QueryOptions {
+    fn default() -> QueryOptions {
+        QueryOptions {
+            language: None,
+            field_weights: Vec::new(),
+            scorer: Arc::new(Bm25::default()),
+            proximity_weight: DEFAULT_PROXIMITY_WEIGHT,
+            ranked: true,
+            transpositions: true,
+            spellcheck: true,
+        }
+    }
+}
+
 impl Engine {
     pub fn build_engine(
         input_path: &str,
         output_path: &str,
         max_freq_percentage_threshold: f64,
         min_freq_threshold: u32,
-    ) {
+        memory_budget_bytes: usize,
+        config: &IndexConfig,
+    ) -> Result<()> {
         builder::build_engine(
             input_path,
             output_path,
-            &Preprocessor::new(),
+            config,
             max_freq_percentage_threshold,
             min_freq_threshold,
-        );
+            memory_budget_bytes,
+        )
+    }
+
+    pub fn build_engine_from_source(
+        input_path: &str,
+        output_path: &str,
+        source: &SourceConfig,
+        max_freq_percentage_threshold: f64,
+        min_freq_threshold: u32,
+        memory_budget_bytes: usize,
+        config: &IndexConfig,
+    ) -> Result<()> {
+        builder::build_engine_from_source(
+            input_path,
+            output_path,
+            source,
+            config,
+            max_freq_percentage_threshold,
+            min_freq_threshold,
+            memory_budget_bytes,
+        )
+    }
+
+    pub fn build_engine_from_records(
+        records: Vec<Record>,
+        output_path: &str,
+        max_freq_percentage_threshold: f64,
+        min_freq_threshold: u32,
+        memory_budget_bytes: usize,
+        config: &IndexConfig,
+    ) -> Result<()> {
+        builder::build_engine_from_records(
+            records.into_iter(),
+            output_path,
+            config,
+            max_freq_percentage_threshold,
+            min_freq_threshold,
+            memory_budget_bytes,
+        )
+    }
+
+    pub fn update_engine(input_path: &str, index_path: &str) -> Result<()> {
+        builder::update_engine(input_path, index_path)
     }
 
-    pub fn load_index(input_path: &str) -> Engine {
-        Engine {
-            vocabulary: Vocabulary::load_vocabulary(input_path),
-            postings: Postings::load_postings_reader(input_path),
-            documents: Documents::load_documents(input_path),
-            preprocessor: Preprocessor::new(),
+    pub fn compact_engine(index_path: &str) -> Result<()> {
+        builder::compact_engine(index_path)
+    }
+
+    pub fn load_index(input_path: &str) -> Result<Engine> {
+        // nothing is decoded before every file is known to be complete and intact
+        let manifest = IndexManifest::load_index_manifest(input_path)?;
+
+        // segments share a global doc id space, each one starting
+        // where the previous one ends
+        let fields = Fields::load_fields(input_path)?;
+        let codec = PostingsCodec::load_codec(input_path)?;
+        let mut base_doc_id = 0;
+        let mut segments = Vec::new();
+        for info in Segment::load_manifest(input_path)? {
+            let num_fields = fields.get_num_fields();
+            let segment = Segment::load_segment(input_path, info, base_doc_id, num_fields, codec)?;
+            base_doc_id += segment.documents.get_num_documents();
+            segments.push(segment);
         }
+
+        Ok(Engine {
+            segments,
+            fields,
+            analyzers: Analyzers::new(AnalyzerConfig::load_analyzer(input_path)?),
+            manifest,
+        })
     }
 
-    pub fn boolean_query(&mut self, query: &str) -> QueryResult {
+    pub fn get_manifest(&self) -> &IndexManifest {
+        &self.manifest
+    }
+
+    pub fn boolean_query(&self, query: &str, offset: usize, limit: usize) -> Result<QueryResult> {
+        self.boolean_query_with_options(query, offset, limit, &QueryOptions::default())
+    }
+
+    pub fn boolean_query_with_options(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+        options: &QueryOptions,
+    ) -> Result<QueryResult> {
         let start_time = Instant::now();
+        let analyzer = self.analyzers.get_analyzer(options.language);
+
+        let query = QueryNode::parse(query, &|name| self.fields.get_field_id(name))?;
+
+        let mut original_terms = Vec::new();
+        Self::get_boolean_terms(&query, analyzer, true, &mut original_terms);
+        let spelling = self.check_spelling(&original_terms, options)?;
+
+        // terms are told apart by whether they are negated, only positive ones are ranked
+        let mut terms = Vec::new();
+        let corrections = &spelling.corrections;
+        let result = self.evaluate_boolean(&query, analyzer, options, corrections, &mut terms)?;
+        let positive_terms: Vec<(Option<usize>, String)> = terms
+            .iter()
+            .filter(|(_, _, positive)| *positive)
+            .map(|(field, term, _)| (*field, term.clone()))
+            .collect();
+        let terms = terms.into_iter().map(|(f, t, _)| (f, t)).collect();
 
-        let mut stack = Vec::new();
-        let mut intermediate_result;
-        let num_docs = self.documents.get_num_documents();
-
-        let query = Self::infix_to_postfix_boolean(query);
-
-        for p in query.clone() {
-            intermediate_result = match p.as_str() {
-                "AND" => Postings::and_operator(stack.pop().unwrap(), stack.pop().unwrap()),
-                "OR" => Postings::or_operator(stack.pop().unwrap(), stack.pop().unwrap()),
-                "NOT" => Postings::not_operator(stack.pop().unwrap(), num_docs),
-                _ => self
-                    .vocabulary
-                    .spellcheck_term(&p)
-                    .and_then(|t| self.get_term_doc_ids(&t))
-                    .unwrap_or_default(),
+        if options.ranked {
+            let doc_ids: DocumentIdsList = match result {
+                None => DocumentIdsList::new(),
+                Some(operand) => self.get_operand_doc_ids(operand)?,
             };
+            let doc_ids = self.get_live_doc_ids(doc_ids)?;
+
+            let k = offset.saturating_add(limit);
+            let mut ranked =
+                self.rank_documents(&positive_terms, Some(&doc_ids), k, options, true)?;
+
+            // matches holding none of the positive terms, as with NOT, come last
+            let documents = &mut ranked.top.documents;
+            if documents.len() < k {
+                let scored: HashSet<u32> = documents.iter().map(|(id, _)| *id).collect();
+                let unscored = doc_ids.iter().filter(|id| !scored.contains(id));
+                let missing = k - documents.len();
+                documents.extend(unscored.take(missing).map(|id| (*id, 0.0)));
+            }
 
-            stack.push(intermediate_result);
+            return Ok(QueryResult {
+                original_query: self.get_original_query(original_terms),
+                query: self.get_query_terms(terms),
+                corrected: !spelling.corrections.is_empty(),
+                suggestions: spelling.suggestions,
+                documents: self.get_document_results(
+                    &ranked.top.documents,
+                    offset,
+                    &positive_terms,
+                )?,
+                total_hits: doc_ids.len(),
+                total_hits_exact: true,
+                time_ms: start_time.elapsed().as_millis(),
+            });
         }
 
-        let documents = stack
-            .pop()
-            .unwrap()
+        // positions are only known when the query ends with NEAR/k or a phrase,
+        // the postings of terms are not decoded past their doc ids
+        let postings: PostingsList = match result {
+            None => PostingsList::new(),
+            Some(BooleanOperand::Postings(postings)) => postings,
+            Some(operand) => self
+                .get_operand_doc_ids(operand)?
+                .into_iter()
+                .map(|document_id| Posting {
+                    document_id,
+                    ..Posting::default()
+                })
+                .collect(),
+        };
+
+        let mut live_postings = PostingsList::with_capacity(postings.len());
+        for p in postings {
+            if self.is_live(p.document_id)? {
+                live_postings.push(p);
+            }
+        }
+        let postings = live_postings;
+
+        let documents = postings
             .iter()
-            .map(|i| DocumentResult {
-                id: *i,
-                path: self.documents.get_doc_path(*i),
-                score: 1.0,
+            .skip(offset)
+            .take(limit)
+            .map(|p| {
+                Ok(DocumentResult {
+                    id: p.document_id,
+                    path: self.get_doc_path(p.document_id)?,
+                    external_id: self.get_external_id(p.document_id)?,
+                    score: 1.0,
+                    positions: p.positions.clone(),
+                })
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         let time_ms = start_time.elapsed().as_millis();
 
-        QueryResult {
-            query,
+        Ok(QueryResult {
+            original_query: self.get_original_query(original_terms),
+            query: self.get_query_terms(terms),
+            corrected: !spelling.corrections.is_empty(),
+            suggestions: spelling.suggestions,
             documents,
+            total_hits: postings.len(),
+            total_hits_exact: true,
             time_ms,
-        }
+        })
+    }
+
+    pub fn free_query(&self, query: &str, offset: usize, limit: usize) -> Result<QueryResult> {
+        self.free_query_with_options(query, offset, limit, &QueryOptions::default())
+    }
+
+    pub fn free_query_with_options(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+        options: &QueryOptions,
+    ) -> Result<QueryResult> {
+        self.run_free_query(query, None, offset, limit, options, true)
+    }
+
+    pub fn filtered_query(
+        &self,
+        query: &str,
+        filter: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<QueryResult> {
+        self.filtered_query_with_options(query, filter, offset, limit, &QueryOptions::default())
+    }
+
+    // ranks the documents matching the boolean filter on the free text query
+    pub fn filtered_query_with_options(
+        &self,
+        query: &str,
+        filter: &str,
+        offset: usize,
+        limit: usize,
+        options: &QueryOptions,
+    ) -> Result<QueryResult> {
+        self.run_free_query(query, Some(filter), offset, limit, options, true)
     }
 
-    pub fn free_query(&mut self, query: &str, num_results: usize) -> QueryResult {
+    fn run_free_query(
+        &self,
+        query: &str,
+        filter: Option<&str>,
+        offset: usize,
+        limit: usize,
+        options: &QueryOptions,
+        prune: bool,
+    ) -> Result<QueryResult> {
         let start_time = Instant::now();
+        let analyzer = self.analyzers.get_analyzer(options.language);
 
-        let tokens: Vec<String> = self
-            .preprocessor
-            .tokenize_and_stem(query)
-            .iter()
-            .filter_map(|t| self.vocabulary.spellcheck_term(t))
-            .collect();
+        // only documents matching the filter and every quoted phrase are scored
+        let mut candidates = match filter {
+            Some(filter) => self.get_filter_doc_ids(filter, analyzer, options)?,
+            None => None,
+        };
+
+        let clauses = QueryNode::parse_free(query, &|name| self.fields.get_field_id(name))?;
+        let original_terms = Self::get_free_query_terms(&clauses, analyzer);
+        let spelling = self.check_spelling(&original_terms, options)?;
+        let corrections = &spelling.corrections;
+
+        // a clause is either a quoted phrase or a word, both optionally prefixed by a field
+        let mut terms: Vec<(Option<usize>, String)> = Vec::new();
+
+        for clause in &clauses {
+            let (field, text) = match clause {
+                QueryNode::Term { field, text } | QueryNode::Phrase { field, text } => {
+                    (*field, text.as_str())
+                }
+                _ => continue,
+            };
+
+            // wildcards and fuzzy terms are expanded, but not inside phrases,
+            // and free queries are not rejected, fuzzy distances are capped instead
+            if matches!(clause, QueryNode::Phrase { .. }) {
+                // phrases without terms, such as empty or stop words only ones, match anything
+                if analyzer.analyze(text).is_empty() {
+                    continue;
+                }
+                let doc_ids = self.get_phrase_doc_ids(field, text, analyzer, corrections)?;
+                candidates = Some(match candidates {
+                    Some(ids) => Postings::and_operator(ids, doc_ids),
+                    None => doc_ids,
+                });
+            } else if let Some((text, distance)) = split_fuzzy(text) {
+                let distance = distance.min(MAX_FUZZY_DISTANCE);
+                let expanded = self.expand_fuzzy(field, text, distance, analyzer, options);
+                terms.extend(expanded.into_iter().map(|t| (field, t)));
+                continue;
+            } else if is_free_wildcard(text) {
+                let expanded = self.expand_wildcard(field, &analyzer.normalize(text));
+                terms.extend(expanded.into_iter().map(|t| (field, t)));
+                continue;
+            }
 
-        let mut scores: HashMap<u32, DocumentScore> = HashMap::new();
+            analyzer
+                .analyze_terms(text)
+                .iter()
+                .filter_map(|t| self.correct_term(field, t, corrections))
+                .for_each(|t| terms.push((field, t)));
+        }
 
-        let n = self.documents.get_num_documents() as f64;
-        let avgdl = self.documents.get_avg_doc_len();
+        let ranked = self.rank_documents(
+            &terms,
+            candidates.as_deref(),
+            offset.saturating_add(limit),
+            options,
+            prune,
+        )?;
+        let top = ranked.top;
 
-        for (id, token) in tokens.iter().enumerate() {
-            if let Some(postings) = self.get_term_postings(token) {
-                // compute idf where n is the number of documents and
-                // nq the number of documents containing query term
+        // when documents were skipped, their number is only estimated
+        let total_hits = match top.exhaustive {
+            true => top.num_matches,
+            false => self
+                .estimate_total_hits(&ranked.document_counts, candidates.as_deref())
+                .max(top.num_matches),
+        };
 
-                let nq = self.vocabulary.get_term_frequency(token).unwrap() as f64;
-                let idf = ((n - nq + 0.5) / (nq + 0.5) + 1.0).ln();
+        let documents = self.get_document_results(&top.documents, offset, &terms)?;
 
-                for doc_posting in &postings {
-                    // compute B25 score, where fq is the frequency of term in this documents
-                    // dl is the document len, and avgdl is the average document len accross the collection
+        let time_ms = start_time.elapsed().as_millis();
 
-                    let fq = doc_posting.document_frequency as f64;
-                    let dl = self.documents.get_doc_len(doc_posting.document_id) as f64;
+        Ok(QueryResult {
+            original_query: self.get_original_query(original_terms),
+            query: self.get_query_terms(terms),
+            corrected: !spelling.corrections.is_empty(),
+            suggestions: spelling.suggestions,
+            documents,
+            total_hits,
+            total_hits_exact: top.exhaustive,
+            time_ms,
+        })
+    }
 
-                    let bm_score = idf * (fq * (BM25_KL + 1.0))
-                        / (fq + BM25_KL * (1.0 - BM25_B + BM25_B * (dl / avgdl)));
+    fn rank_documents(
+        &self,
+        terms: &[(Option<usize>, String)],
+        candidates: Option<&[u32]>,
+        k: usize,
+        options: &QueryOptions,
+        prune: bool,
+    ) -> Result<RankedDocuments> {
+        // deleted documents are left out of the statistics, before compaction too
+        let n = self.get_num_live_documents() as f64;
+        let weights: Vec<f64> = (0..self.fields.get_num_fields())
+            .map(|f| {
+                options
+                    .field_weights
+                    .iter()
+                    .find(|(name, _)| name == self.fields.get_name(f))
+                    .map_or(1.0, |(_, w)| *w)
+            })
+            .collect();
+        let avg_lens: Vec<f64> = (0..self.fields.get_num_fields())
+            .map(|f| self.get_avg_field_len(f))
+            .collect();
+        let collection_len = avg_lens.iter().sum::<f64>() * n;
+        let scorer = &options.scorer;
 
-                    let doc_score = scores.entry(doc_posting.document_id).or_default();
-                    doc_score.tf_idf += bm_score;
-                    let positions = doc_score.term_positions.entry(id as u32).or_default();
+        let mut stats = Vec::new();
+        let mut document_counts = Vec::new();
+        let mut cursors = Vec::new();
+        for (id, (field, term)) in terms.iter().enumerate() {
+            let nq = self.get_term_document_count(*field, term)?;
+            let collection_frequency = match scorer.needs_collection_frequency() {
+                true => self.get_term_collection_frequency(*field, term)?,
+                false => 0,
+            };
+            let term_stats = TermStats {
+                num_documents: n,
+                document_count: nq as f64,
+                collection_frequency: collection_frequency as f64,
+                collection_len,
+            };
+            stats.push(term_stats);
+            if !terms[..id].contains(&terms[id]) {
+                document_counts.push(nq);
+            }
 
-                    doc_posting
-                        .positions
-                        .iter()
-                        .for_each(|p| positions.push(*p));
+            for field_id in self.get_field_ids(*field) {
+                let weight = TermWeight {
+                    scorer: scorer.clone(),
+                    stats: term_stats,
+                    field_weight: weights[field_id],
+                    avg_field_len: avg_lens[field_id],
+                };
+                for segment in &self.segments {
+                    if let Some(cursor) = segment.get_term_cursor(field_id, term)? {
+                        let base_doc_id = segment.base_doc_id;
+                        let weight = weight.clone();
+                        cursors.push(TermCursor::new(id, field_id, base_doc_id, cursor, weight));
+                    }
                 }
             }
         }
 
-        let mut selector = FixedMinHeap::new(num_results);
-        let num_tokens = tokens.len();
-        for (id, score) in &mut scores {
-            score.tf_idf /= self.documents.get_doc_len(*id) as f64;
-            selector.push(*id, Self::compute_score(score, num_tokens));
-        }
+        let num_tokens = terms.len();
+        let proximity_weight = options.proximity_weight;
+        let score_document = |doc_id: u32, cursors: &mut [TermCursor]| {
+            // documents must be among the candidates, such as the ones holding every phrase
+            let is_candidate = candidates.is_none_or(|ids| ids.binary_search(&doc_id).is_ok());
+            if !is_candidate || !self.is_live(doc_id)? {
+                return Ok(None);
+            }
+
+            // frequencies are normalized by the length of their field and weighted,
+            // before being summed and scored once for the whole document
+            let mut tfs = vec![0.0; num_tokens];
+            let mut score = DocumentScore::default();
+            for cursor in cursors {
+                let field_id = cursor.field_id;
+                let fq = cursor.get_frequency() as f64;
+                let field_len = self.get_field_len(field_id, doc_id)? as f64;
+                tfs[cursor.term_id] += weights[field_id]
+                    * scorer.get_field_frequency(fq, field_len, avg_lens[field_id]);
+
+                // positions are only decoded when they count in the score,
+                // and the ones of each field are kept apart
+                if proximity_weight > 0.0 {
+                    let shift = field_id as u32 * FIELD_POSITION_GAP;
+                    let positions = score
+                        .term_positions
+                        .entry(cursor.term_id as u32)
+                        .or_default();
+                    positions.extend(cursor.get_positions()?.iter().map(|p| p + shift));
+                }
+            }
+
+            let doc_len = self.get_doc_len(doc_id)? as f64;
+            score.relevance = scorer.get_document_score(doc_len, num_tokens);
+            for (tf, stats) in tfs.into_iter().zip(&stats) {
+                score.relevance += scorer.get_term_score(tf, doc_len, stats);
+            }
+
+            Ok(Some(Self::compute_score(
+                &score,
+                num_tokens,
+                proximity_weight,
+            )))
+        };
+        let top = get_top_documents(
+            cursors,
+            num_tokens,
+            k,
+            prune,
+            proximity_weight,
+            score_document,
+        )?;
+
+        Ok(RankedDocuments {
+            top,
+            document_counts,
+        })
+    }
 
-        let documents = selector
-            .get_sorted_id_priority_pairs()
+    fn get_document_results(
+        &self,
+        documents: &[(u32, f64)],
+        offset: usize,
+        terms: &[(Option<usize>, String)],
+    ) -> Result<Vec<DocumentResult>> {
+        documents
             .iter()
-            .map(|(id, score)| DocumentResult {
-                id: *id,
-                score: *score,
-                path: self.documents.get_doc_path(*id),
+            .skip(offset)
+            .map(|(id, score)| {
+                Ok(DocumentResult {
+                    id: *id,
+                    score: *score,
+                    path: self.get_doc_path(*id)?,
+                    external_id: self.get_external_id(*id)?,
+                    positions: self.get_document_positions(*id, terms)?,
+                })
             })
+            .collect()
+    }
+
+    fn get_document_positions(
+        &self,
+        doc_id: u32,
+        terms: &[(Option<usize>, String)],
+    ) -> Result<Vec<u32>> {
+        // positions are only decoded for the documents of the page, for snippets
+        let segment = self.get_segment(doc_id)?;
+        let local_doc_id = doc_id - segment.base_doc_id;
+
+        let mut positions = Vec::new();
+        for (field, term) in terms {
+            for field_id in self.get_field_ids(*field) {
+                let Some(mut cursor) = segment.get_term_cursor(field_id, term)? else {
+                    continue;
+                };
+                cursor.seek_document(local_doc_id)?;
+                if cursor.get_document_id() != Some(local_doc_id) {
+                    continue;
+                }
+
+                // positions of each field are kept apart
+                let shift = field_id as u32 * FIELD_POSITION_GAP;
+                positions.extend(cursor.get_positions()?.iter().map(|p| p + shift));
+            }
+        }
+        Ok(positions)
+    }
+
+    pub fn get_snippet(
+        &self,
+        text: &str,
+        query: &QueryResult,
+        document: &DocumentResult,
+    ) -> Snippet {
+        let analyzer = self.analyzers.get_analyzer(None);
+        let tokens = analyzer.analyze(text);
+
+        // fall back to matching query terms in the text, when
+        // the query could not provide their positions
+        let positions: Vec<u32> = if document.positions.is_empty() {
+            let terms: Vec<String> = query
+                .query
+                .iter()
+                .flat_map(|t| analyzer.analyze_terms(self.split_field(t).1))
+                .collect();
+
+            tokens
+                .iter()
+                .filter(|t| terms.contains(&t.text))
+                .map(|t| t.position)
+                .collect()
+        } else {
+            // snippets are built on the default field, the first one
+            document
+                .positions
+                .iter()
+                .copied()
+                .filter(|p| *p < FIELD_POSITION_GAP)
+                .collect()
+        };
+
+        // filters can stack several tokens on the same position, snippets
+        // work on the first one, and on indexes in the deduplicated list
+        let mut tokens = tokens;
+        tokens.dedup_by_key(|t| t.position);
+        let positions: Vec<u32> = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| positions.contains(&t.position))
+            .map(|(i, _)| i as u32)
             .collect();
 
-        let time_ms = start_time.elapsed().as_millis();
+        Snippet::build_snippet(
+            text,
+            &tokens,
+            &positions,
+            SNIPPET_WINDOW_LEN,
+            SNIPPET_MAX_FRAGMENTS,
+        )
+    }
 
-        QueryResult {
-            query: tokens,
-            documents,
-            time_ms,
+    pub fn get_stored_fields(&self, doc_id: u32) -> Result<Vec<(String, String)>> {
+        // unknown documents have no stored fields, as the ones of unstored indexes
+        match self.segments.iter().find(|s| s.contains_doc_id(doc_id)) {
+            Some(segment) => segment.get_stored_fields(doc_id - segment.base_doc_id),
+            None => Ok(Vec::new()),
         }
     }
 
-    fn get_term_doc_ids(&mut self, term: &str) -> Option<DocumentIdsList> {
-        self.vocabulary
-            .get_term_index(term)
-            .map(|i| self.postings.load_doc_ids_list(i))
+    pub fn get_stored_text(&self, doc_id: u32) -> Result<Option<String>> {
+        // the text of the default field, the one snippets are built on
+        Ok(self
+            .get_stored_fields(doc_id)?
+            .into_iter()
+            .find(|(name, _)| name == DEFAULT_FIELD)
+            .map(|(_, text)| text))
+    }
+
+    fn evaluate_boolean(
+        &self,
+        node: &QueryNode,
+        analyzer: &dyn Analyzer,
+        options: &QueryOptions,
+        corrections: &Corrections,
+        terms: &mut Vec<(Option<usize>, String, bool)>,
+    ) -> Result<Option<BooleanOperand>> {
+        // operands removed by the analyzer, such as stop words, are
+        // ignored by the operators instead of matching nothing
+        let operand = match node {
+            QueryNode::Term { field, text } | QueryNode::Phrase { field, text } => {
+                let mut tokens = analyzer.analyze(text);
+                tokens.dedup_by_key(|t| t.position);
+                tokens
+                    .iter()
+                    .filter_map(|t| self.correct_term(*field, &t.text, corrections))
+                    .for_each(|t| terms.push((*field, t, true)));
+
+                // words split by the analyzer, as `e-mail`, are searched as phrases
+                match tokens.as_slice() {
+                    [] => None,
+                    [token] if matches!(node, QueryNode::Term { .. }) => {
+                        Some(match self.correct_term(*field, &token.text, corrections) {
+                            Some(t) => BooleanOperand::Term(*field, t),
+                            None => BooleanOperand::DocIds(DocumentIdsList::new()),
+                        })
+                    }
+                    _ => Some(BooleanOperand::Postings(self.get_phrase_postings(
+                        *field,
+                        text,
+                        analyzer,
+                        corrections,
+                    )?)),
+                }
+            }
+            QueryNode::Wildcard { field, pattern } => {
+                let expanded = self.expand_wildcard(*field, &analyzer.normalize(pattern));
+                terms.extend(expanded.iter().map(|t| (*field, t.clone(), true)));
+                Some(BooleanOperand::Terms(*field, expanded))
+            }
+            QueryNode::Fuzzy {
+                field,
+                text,
+                distance,
+            } => {
+                // analyzed like terms, as the vocabulary may hold stems
+                let expanded = self.expand_fuzzy(*field, text, *distance, analyzer, options);
+                terms.extend(expanded.iter().map(|t| (*field, t.clone(), true)));
+                Some(BooleanOperand::Terms(*field, expanded))
+            }
+            QueryNode::And(nodes) => {
+                let mut operands = Vec::new();
+                for node in nodes {
+                    operands.extend(self.evaluate_boolean(
+                        node,
+                        analyzer,
+                        options,
+                        corrections,
+                        terms,
+                    )?);
+                }
+                match operands.len() {
+                    0 => None,
+                    1 => operands.pop(),
+                    _ => Some(BooleanOperand::DocIds(self.and_operands(operands)?)),
+                }
+            }
+            QueryNode::Or(nodes) => {
+                let mut result: Option<BooleanOperand> = None;
+                for node in nodes {
+                    let Some(operand) =
+                        self.evaluate_boolean(node, analyzer, options, corrections, terms)?
+                    else {
+                        continue;
+                    };
+                    result = Some(match result {
+                        None => operand,
+                        Some(r) => BooleanOperand::DocIds(Postings::or_operator(
+                            self.get_operand_doc_ids(r)?,
+                            self.get_operand_doc_ids(operand)?,
+                        )),
+                    });
+                }
+                result
+            }
+            QueryNode::Not(node) => {
+                let start = terms.len();
+                let operand = self.evaluate_boolean(node, analyzer, options, corrections, terms)?;
+                terms[start..].iter_mut().for_each(|t| t.2 = !t.2);
+
+                match operand {
+                    Some(o) => Some(BooleanOperand::DocIds(Postings::not_operator(
+                        self.get_operand_doc_ids(o)?,
+                        self.get_num_documents(),
+                    ))),
+                    None => None,
+                }
+            }
+            QueryNode::Near {
+                left,
+                right,
+                distance,
+            } => {
+                let left = self.evaluate_boolean(left, analyzer, options, corrections, terms)?;
+                let right = self.evaluate_boolean(right, analyzer, options, corrections, terms)?;
+                match (left, right) {
+                    (Some(l), Some(r)) => Some(BooleanOperand::Postings(Postings::near_operator(
+                        self.get_operand_postings(l)?,
+                        self.get_operand_postings(r)?,
+                        *distance,
+                    ))),
+                    (l, r) => l.or(r),
+                }
+            }
+        };
+
+        Ok(operand)
+    }
+
+    fn get_filter_doc_ids(
+        &self,
+        filter: &str,
+        analyzer: &dyn Analyzer,
+        options: &QueryOptions,
+    ) -> Result<Option<DocumentIdsList>> {
+        // terms of the filter only select documents, they are neither ranked, reported nor
+        // corrected, and a filter left without operands by the analyzer does not filter anything
+        let filter = QueryNode::parse(filter, &|name| self.fields.get_field_id(name))?;
+        let corrections = Corrections::new();
+        match self.evaluate_boolean(&filter, analyzer, options, &corrections, &mut Vec::new())? {
+            Some(operand) => Ok(Some(self.get_operand_doc_ids(operand)?)),
+            None => Ok(None),
+        }
     }
 
-    fn infix_to_postfix_boolean(query: &str) -> Vec<String> {
-        let mut res = Vec::new();
-        let mut stack = Vec::new();
+    fn and_operands(&self, operands: Vec<BooleanOperand>) -> Result<DocumentIdsList> {
+        let mut lists = Vec::new();
+        let mut terms = Vec::new();
+        for operand in operands {
+            match operand {
+                BooleanOperand::Term(field, term) => {
+                    let len = self.get_term_document_frequency(field, &term)?;
+                    terms.push((len, field, term));
+                }
+                operand => lists.push(self.get_operand_doc_ids(operand)?),
+            }
+        }
+
+        // candidates come from the loaded operands, or from the rarest term, and are
+        // looked up in the postings of the other terms, skipping the blocks without them
+        terms.sort_by_key(|(len, _, _)| *len);
+        let mut terms = terms.into_iter();
+        let mut candidates = match lists.into_iter().reduce(Postings::and_operator) {
+            Some(doc_ids) => doc_ids,
+            None => match terms.next() {
+                Some((_, field, term)) => self.get_term_doc_ids(field, &term)?,
+                None => DocumentIdsList::new(),
+            },
+        };
+
+        for (_, field, term) in terms {
+            if candidates.is_empty() {
+                break;
+            }
+            candidates = self.filter_term_doc_ids(field, &term, &candidates)?;
+        }
 
-        let sanitized_query = query.replace('(', " ( ").replace(')', " ) ");
+        Ok(candidates)
+    }
 
-        for t in sanitized_query.split_ascii_whitespace() {
-            if t == "(" {
-                stack.push(t);
-            } else if t == ")" {
-                let mut last = stack.pop().unwrap();
-                while last != "(" {
-                    res.push(last);
-                    last = stack.pop().unwrap();
+    fn get_operand_doc_ids(&self, operand: BooleanOperand) -> Result<DocumentIdsList> {
+        match operand {
+            BooleanOperand::Term(field, term) => self.get_term_doc_ids(field, &term),
+            BooleanOperand::Terms(field, terms) => {
+                let mut result = DocumentIdsList::new();
+                for term in terms {
+                    result = Postings::or_operator(result, self.get_term_doc_ids(field, &term)?);
                 }
-            } else if let Some(current_precedence) = BOOLEAN_PRECEDENCE.get(t) {
-                while !stack.is_empty() {
-                    let last = stack.last().unwrap();
-                    if BOOLEAN_PRECEDENCE.get(last).unwrap() > current_precedence {
-                        res.push(stack.pop().unwrap());
-                    } else {
-                        break;
+                Ok(result)
+            }
+            BooleanOperand::Postings(p) => Ok(p.iter().map(|e| e.document_id).collect()),
+            BooleanOperand::DocIds(ids) => Ok(ids),
+        }
+    }
+
+    fn get_operand_postings(&self, operand: BooleanOperand) -> Result<PostingsList> {
+        match operand {
+            BooleanOperand::Term(field, term) => {
+                Ok(self.get_term_postings(field, &term)?.unwrap_or_default())
+            }
+            BooleanOperand::Terms(field, terms) => {
+                let mut result = PostingsList::new();
+                for term in terms {
+                    if let Some(postings) = self.get_term_postings(field, &term)? {
+                        result = Postings::union_operator(result, postings);
                     }
                 }
-                stack.push(t);
-            } else {
-                res.push(t);
+                Ok(result)
             }
+            BooleanOperand::Postings(p) => Ok(p),
+            // the parser keeps AND, OR and NOT away from NEAR/k, only
+            // terms missing from the index get here, matching nothing
+            BooleanOperand::DocIds(_) => Ok(PostingsList::default()),
         }
+    }
 
-        stack.iter().rev().for_each(|e| res.push(e));
-        res.iter().map(|s| (*s).to_string()).collect()
+    fn get_query_terms(&self, terms: Vec<(Option<usize>, String)>) -> Vec<String> {
+        // terms searched in a single field are shown with their prefix
+        terms
+            .into_iter()
+            .map(|(field, term)| match field {
+                Some(f) => format!("{}:{term}", self.fields.get_name(f)),
+                None => term,
+            })
+            .collect()
     }
 
-    fn get_term_postings(&mut self, term: &str) -> Option<PostingsList> {
-        self.vocabulary
-            .get_term_index(term)
-            .map(|i| self.postings.load_postings_list(i))
+    fn get_phrase_doc_ids(
+        &self,
+        field: Option<usize>,
+        phrase: &str,
+        analyzer: &dyn Analyzer,
+        corrections: &Corrections,
+    ) -> Result<DocumentIdsList> {
+        Ok(self
+            .get_phrase_postings(field, phrase, analyzer, corrections)?
+            .iter()
+            .map(|p| p.document_id)
+            .collect())
     }
 
-    fn compute_score(document_score: &DocumentScore, num_tokens: usize) -> f64 {
-        let mut window = u32::MAX;
+    fn get_phrase_postings(
+        &self,
+        field: Option<usize>,
+        phrase: &str,
+        analyzer: &dyn Analyzer,
+        corrections: &Corrections,
+    ) -> Result<PostingsList> {
+        // a term missing from the vocabulary leaves the phrase without matches,
+        // and only the first of the terms stacked on a position is matched
+        let mut tokens = analyzer.analyze(phrase);
+        tokens.dedup_by_key(|t| t.position);
 
-        let mut arr: Vec<(u32, u32)> = document_score
-            .term_positions
+        let first_position = tokens.first().map(|t| t.position).unwrap_or_default();
+        let Some(terms) = tokens
             .iter()
-            .flat_map(|(id, positions)| positions.iter().map(|p| (*p, *id)))
-            .collect();
+            .map(|t| {
+                self.correct_term(field, &t.text, corrections)
+                    .map(|term| (t.position - first_position, term))
+            })
+            .collect::<Option<Vec<(u32, String)>>>()
+        else {
+            return Ok(PostingsList::new());
+        };
 
-        arr.sort_unstable();
+        let mut lists = Vec::with_capacity(terms.len());
+        for (offset, t) in &terms {
+            lists.push((
+                *offset,
+                self.get_term_postings(field, t)?.unwrap_or_default(),
+            ));
+        }
 
-        let mut j = 0;
-        let mut seen: HashMap<u32, u32> = HashMap::new();
-        for (pos, id) in arr.iter().copied() {
-            seen.entry(id).and_modify(|c| *c += 1).or_insert(1);
+        Ok(Postings::phrase_operator(lists))
+    }
 
-            while seen.len() == num_tokens && j < arr.len() {
-                let (j_pos, j_id) = arr[j];
-                window = min(window, pos - j_pos + 1);
+    fn split_field<'a>(&self, token: &'a str) -> (Option<usize>, &'a str) {
+        // only known field names are prefixes, anything else is left to the analyzer
+        token
+            .split_once(':')
+            .and_then(|(name, rest)| self.fields.get_field_id(name).map(|f| (Some(f), rest)))
+            .unwrap_or((None, token))
+    }
 
-                seen.entry(j_id).and_modify(|c| *c -= 1);
-                if *seen.get(&j_id).unwrap() == 0 {
-                    seen.remove(&j_id);
-                }
+    fn get_field_ids(&self, field: Option<usize>) -> Vec<usize> {
+        match field {
+            Some(f) => vec![f],
+            None => (0..self.fields.get_num_fields()).collect(),
+        }
+    }
 
-                j += 1;
+    fn get_field_postings(&self, field_id: usize, term: &str) -> Result<PostingsList> {
+        // segments are sorted by base doc id, so concatenating
+        // their postings keeps doc ids sorted
+        let mut postings = PostingsList::new();
+        for segment in &self.segments {
+            if let Some(p) = segment.get_term_postings(field_id, term)? {
+                postings.extend(p);
             }
         }
+        Ok(postings)
+    }
 
-        WINDOW_SCORE_MULTIPLIER * (num_tokens as f64 / window as f64)
-            + BM25_SCORE_MULTIPLIER * document_score.tf_idf
+    fn get_term_doc_ids(&self, field: Option<usize>, term: &str) -> Result<DocumentIdsList> {
+        let mut result = DocumentIdsList::new();
+        for f in self.get_field_ids(field) {
+            let mut doc_ids = DocumentIdsList::new();
+            for segment in &self.segments {
+                doc_ids.extend(segment.get_term_doc_ids(f, term)?);
+            }
+            result = Postings::or_operator(result, doc_ids);
+        }
+        Ok(result)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::test_utils::utils::create_temporary_dir_path;
+    fn filter_term_doc_ids(
+        &self,
+        field: Option<usize>,
+        term: &str,
+        doc_ids: &[u32],
+    ) -> Result<DocumentIdsList> {
+        // a document matches if any of the searched fields has the term
+        let mut result = DocumentIdsList::new();
+        for f in self.get_field_ids(field) {
+            let mut matched = DocumentIdsList::new();
+            for segment in &self.segments {
+                matched.extend(segment.filter_term_doc_ids(f, term, doc_ids)?);
+            }
+            result = Postings::or_operator(result, matched);
+        }
+        Ok(result)
+    }
 
-    #[test]
-    fn test_build() {
-        let index_path = &create_temporary_dir_path();
-        Engine::build_engine("test_data/docs", index_path, 1.0, 0);
-        let mut idx = Engine::load_index(index_path);
+    fn get_term_document_frequency(&self, field: Option<usize>, term: &str) -> Result<usize> {
+        let mut frequency = 0;
+        for f in self.get_field_ids(field) {
+            for segment in &self.segments {
+                frequency += segment.get_term_postings_len(f, term)?;
+            }
+        }
+        Ok(frequency)
+    }
 
-        for ele in ["hello", "man", "world"] {
-            assert!(idx.vocabulary.get_term_index(ele).is_some());
+    fn get_term_document_count(&self, field: Option<usize>, term: &str) -> Result<usize> {
+        // the lengths of the lists are enough, unless a document can be
+        // in several of them or some of their documents were deleted
+        let mut frequencies = Vec::new();
+        for f in self.get_field_ids(field) {
+            let mut frequency = 0;
+            for segment in &self.segments {
+                frequency += segment.get_term_postings_len(f, term)?;
+            }
+            frequencies.push(frequency);
         }
 
-        let mut free_query: Vec<String> = idx
-            .free_query("hello", 10)
-            .documents
+        let has_deleted = self.segments.iter().any(|s| !s.deleted.is_empty());
+        match frequencies.iter().filter(|f| **f > 0).count() {
+            0 | 1 if !has_deleted => Ok(frequencies.iter().sum()),
+            _ => Ok(self.get_term_doc_ids(field, term)?.len()),
+        }
+    }
+
+    fn get_term_collection_frequency(&self, field: Option<usize>, term: &str) -> Result<usize> {
+        // every block of the lists is decoded, but none of their positions
+        let mut frequency = 0;
+        for f in self.get_field_ids(field) {
+            for segment in &self.segments {
+                let Some(mut cursor) = segment.get_term_cursor(f, term)? else {
+                    continue;
+                };
+                while let Some(doc_id) = cursor.get_document_id() {
+                    frequency += cursor.get_frequency() as usize;
+                    cursor.seek_document(doc_id + 1)?;
+                }
+            }
+        }
+        Ok(frequency)
+    }
+
+    fn estimate_total_hits(&self, document_counts: &[usize], candidates: Option<&[u32]>) -> usize {
+        // the chance for a document to have none of the terms,
+        // as if terms were spread independently of each other
+        let n = self.get_num_live_documents() as f64;
+        let none_probability: f64 = document_counts
             .iter()
-            .map(|d| d.path.clone())
-            .collect();
-        free_query.sort();
+            .map(|nq| 1.0 - (*nq as f64 / n).min(1.0))
+            .product();
 
-        assert_eq!(free_query, ["test_data/docs/1.txt", "test_data/docs/2.txt"]);
+        let estimate = (n * (1.0 - none_probability)).round() as usize;
+        candidates.map_or(estimate, |ids| min(estimate, ids.len()))
+    }
 
-        let mut boolean_query: Vec<String> = idx
-            .boolean_query("hello AND NOT world")
-            .documents
+    fn get_term_postings(&self, field: Option<usize>, term: &str) -> Result<Option<PostingsList>> {
+        // fields searched together have their positions shifted apart
+        let mut lists = Vec::new();
+        for f in self.get_field_ids(field) {
+            let mut postings = self.get_field_postings(f, term)?;
+            postings.iter_mut().for_each(|p| {
+                p.positions
+                    .iter_mut()
+                    .for_each(|pos| *pos += f as u32 * FIELD_POSITION_GAP)
+            });
+            if !postings.is_empty() {
+                lists.push(postings);
+            }
+        }
+        Ok(lists.into_iter().reduce(Postings::union_operator))
+    }
+
+    fn has_term(&self, field: Option<usize>, term: &str) -> bool {
+        self.segments.iter().any(|s| {
+            self.get_field_ids(field)
+                .iter()
+                .filter_map(|f| s.get_field(*f))
+                .any(|f| f.vocabulary.get_term_index(term).is_some())
+        })
+    }
+
+    fn correct_term(
+        &self,
+        field: Option<usize>,
+        term: &str,
+        corrections: &Corrections,
+    ) -> Option<String> {
+        // terms missing from the index are left out, unless they were corrected
+        match self.has_term(field, term) {
+            true => Some(term.to_string()),
+            false => corrections.get(&(field, term.to_string())).cloned(),
+        }
+    }
+
+    fn get_closest_term(
+        &self,
+        field: Option<usize>,
+        term: &str,
+    ) -> Result<Option<(String, usize)>> {
+        // each vocabulary has its own closest term, the one in the most documents
+        // across segments and fields is taken, ties broken by their order
+        let field_ids = self.get_field_ids(field);
+        let terms: BTreeSet<String> = self
+            .segments
             .iter()
-            .map(|d| d.path.clone())
+            .flat_map(|s| field_ids.iter().filter_map(|f| s.get_field(*f)))
+            .filter_map(|f| f.vocabulary.spellcheck_term(term))
             .collect();
-        boolean_query.sort();
 
-        assert_eq!(boolean_query, ["test_data/docs/2.txt"]);
+        let mut closest: Option<(String, usize)> = None;
+        for t in terms {
+            let document_count = self.get_term_document_frequency(field, &t)?;
+            if closest
+                .as_ref()
+                .is_none_or(|(_, count)| document_count > *count)
+            {
+                closest = Some((t, document_count));
+            }
+        }
+        Ok(closest)
     }
 
-    #[test]
-    fn test_infix_postfix() {
-        assert_eq!(
-            Engine::infix_to_postfix_boolean("a AND (b OR NOT c)"),
-            ["a", "b", "c", "NOT", "OR", "AND"]
-        );
+    fn check_spelling(
+        &self,
+        terms: &[(Option<usize>, String, bool)],
+        options: &QueryOptions,
+    ) -> Result<Spelling> {
+        let mut candidates = Vec::with_capacity(terms.len());
+        for (field, term, positive) in terms {
+            candidates.push(TermCandidates {
+                positive: *positive,
+                candidates: self.get_term_candidates(*field, term)?,
+            });
+        }
 
-        assert_eq!(
-            Engine::infix_to_postfix_boolean("a AND b OR NOT c"),
-            ["a", "b", "AND", "c", "NOT", "OR"]
-        );
+        // queries whose terms have no other candidates are not looked into
+        let is_unchanged =
+            |i: usize, choice: usize| candidates[i].candidates[choice].0 == terms[i].1;
+        if candidates
+            .iter()
+            .enumerate()
+            .all(|(i, c)| c.candidates.len() == 1 && is_unchanged(i, 0))
+        {
+            return Ok(Spelling::default());
+        }
 
-        assert_eq!(
-            Engine::infix_to_postfix_boolean("NOT (a AND b) OR NOT (c OR d)"),
-            ["a", "b", "AND", "NOT", "c", "d", "OR", "NOT", "OR"]
-        );
+        let num_documents = self.get_num_live_documents() as usize;
+        let corrections = rank_corrections(&candidates, num_documents, |i, term, doc_ids| {
+            let field = terms[i].0;
+            match doc_ids {
+                Some(ids) => self.filter_term_doc_ids(field, term, ids),
+                None => self.get_live_doc_ids(self.get_term_doc_ids(field, term)?),
+            }
+        })?;
+        let get_choices = |c: &Correction| -> Vec<(Option<usize>, String)> {
+            c.choices
+                .iter()
+                .enumerate()
+                .map(|(i, choice)| (terms[i].0, candidates[i].candidates[*choice].0.clone()))
+                .collect()
+        };
 
-        assert_eq!(
-            Engine::infix_to_postfix_boolean("a AND b AND c OR d OR e"),
-            ["a", "b", "c", "AND", "AND", "d", "e", "OR", "OR"]
-        );
+        // only terms missing from the index are corrected, with the most likely correction
+        let mut spelling = Spelling::default();
+        if let (true, Some(best)) = (options.spellcheck, corrections.first()) {
+            for (i, (field, term)) in get_choices(best).into_iter().enumerate() {
+                let original = &terms[i].1;
+                if term != *original && !self.has_term(field, original) {
+                    spelling.corrections.insert((field, original.clone()), term);
+                }
+            }
+        }
 
-        assert_eq!(
-            Engine::infix_to_postfix_boolean("a AND (b OR c)"),
-            ["a", "b", "c", "OR", "AND"]
-        );
+        spelling.suggestions = corrections
+            .iter()
+            .filter(|c| {
+                c.choices
+                    .iter()
+                    .enumerate()
+                    .any(|(i, choice)| !is_unchanged(i, *choice))
+            })
+            .take(MAX_SUGGESTIONS)
+            .map(|c| Suggestion {
+                query: self.get_query_terms(get_choices(c)),
+                hits: c.hits,
+                score: c.score,
+            })
+            .collect();
+
+        Ok(spelling)
+    }
+
+    fn get_term_candidates(
+        &self,
+        field: Option<usize>,
+        term: &str,
+    ) -> Result<Vec<(String, u32, usize)>> {
+        // short terms are within fewer edits, so that they are not taken for any other
+        let distance = match term.chars().count() {
+            0..=4 => 1,
+            _ => MAX_FUZZY_DISTANCE,
+        };
+        let mut candidates: Vec<(String, u32, usize)> = self
+            .get_fuzzy_terms(field, term, distance, true)
+            .into_iter()
+            .map(|(t, d, f)| (t, d, f as usize))
+            .collect();
+
+        match candidates.first() {
+            // valid terms are kept first, the others are only offered when much more frequent
+            Some((t, _, document_count)) if t == term => {
+                let min_count = RARE_TERM_RATIO * document_count;
+                let term = candidates.remove(0);
+                candidates.retain(|(_, _, count)| *count >= min_count);
+                candidates.insert(0, term);
+            }
+            Some(_) => {}
+            // terms sharing trigrams with it are looked up beyond the largest distance
+            None => match self.get_closest_term(field, term)? {
+                Some((t, document_count)) => {
+                    candidates.push((t, MAX_FUZZY_DISTANCE + 1, document_count));
+                }
+                None => candidates.push((term.to_string(), 0, 0)),
+            },
+        }
+
+        candidates.truncate(MAX_TERM_CANDIDATES);
+        Ok(candidates)
+    }
+
+    fn get_free_query_terms(
+        clauses: &[QueryNode],
+        analyzer: &dyn Analyzer,
+    ) -> Vec<(Option<usize>, String, bool)> {
+        // the terms of words and phrases, wildcards and fuzzy terms are not misspelled
+        let mut terms = Vec::new();
+        for clause in clauses {
+            let (field, text) = match clause {
+                QueryNode::Term { field, text }
+                    if split_fuzzy(text).is_none() && !is_free_wildcard(text) =>
+                {
+                    (*field, text)
+                }
+                QueryNode::Phrase { field, text } => (*field, text),
+                _ => continue,
+            };
+
+            let mut tokens = analyzer.analyze(text);
+            tokens.dedup_by_key(|t| t.position);
+            terms.extend(tokens.into_iter().map(|t| (field, t.text, true)));
+        }
+        terms
+    }
+
+    fn get_boolean_terms(
+        node: &QueryNode,
+        analyzer: &dyn Analyzer,
+        positive: bool,
+        terms: &mut Vec<(Option<usize>, String, bool)>,
+    ) {
+        match node {
+            QueryNode::Term { field, text } | QueryNode::Phrase { field, text } => {
+                let mut tokens = analyzer.analyze(text);
+                tokens.dedup_by_key(|t| t.position);
+                terms.extend(tokens.into_iter().map(|t| (*field, t.text, positive)));
+            }
+            QueryNode::Wildcard { .. } | QueryNode::Fuzzy { .. } => {}
+            QueryNode::And(nodes) | QueryNode::Or(nodes) => nodes
+                .iter()
+                .for_each(|n| Self::get_boolean_terms(n, analyzer, positive, terms)),
+            QueryNode::Not(node) => Self::get_boolean_terms(node, analyzer, !positive, terms),
+            QueryNode::Near { left, right, .. } => {
+                Self::get_boolean_terms(left, analyzer, positive, terms);
+                Self::get_boolean_terms(right, analyzer, positive, terms);
+            }
+        }
+    }
+
+    fn get_original_query(&self, terms: Vec<(Option<usize>, String, bool)>) -> Vec<String> {
+        self.get_query_terms(terms.into_iter().map(|(f, t, _)| (f, t)).collect())
+    }
+
+    fn expand_wildcard(&self, field: Option<usize>, pattern: &str) -> Vec<String> {
+        // documents holding a term are summed over the vocabularies of fields and segments
+        let mut frequencies: HashMap<&str, u32> = HashMap::new();
+        for segment in &self.segments {
+            for f in self.get_field_ids(field) {
+                let Some(field) = segment.get_field(f) else {
+                    continue;
+                };
+                for (term, frequency) in field.vocabulary.get_wildcard_terms(pattern) {
+                    *frequencies.entry(term).or_default() += frequency;
+                }
+            }
+        }
+
+        let mut terms: Vec<(&str, u32)> = frequencies.into_iter().collect();
+        terms.sort_unstable_by(|(t1, f1), (t2, f2)| f2.cmp(f1).then(t1.cmp(t2)));
+        terms
+            .into_iter()
+            .take(MAX_EXPANDED_TERMS)
+            .map(|(t, _)| t.to_string())
+            .collect()
+    }
+
+    fn expand_fuzzy(
+        &self,
+        field: Option<usize>,
+        text: &str,
+        distance: u32,
+        analyzer: &dyn Analyzer,
+        options: &QueryOptions,
+    ) -> Vec<String> {
+        // terms closest to any of the analyzed terms come first, then the most frequent
+        let mut terms = Vec::new();
+        for term in analyzer.analyze_terms(text) {
+            terms.extend(self.get_fuzzy_terms(field, &term, distance, options.transpositions));
+        }
+        terms.sort_unstable_by(|(t1, d1, f1), (t2, d2, f2)| {
+            d1.cmp(d2).then(f2.cmp(f1)).then(t1.cmp(t2))
+        });
+
+        let mut seen = HashSet::new();
+        terms
+            .into_iter()
+            .filter(|(t, _, _)| seen.insert(t.clone()))
+            .take(MAX_EXPANDED_TERMS)
+            .map(|(t, _, _)| t)
+            .collect()
+    }
+
+    fn get_fuzzy_terms(
+        &self,
+        field: Option<usize>,
+        term: &str,
+        distance: u32,
+        transpositions: bool,
+    ) -> Vec<(String, u32, u32)> {
+        // documents holding a term are summed over the vocabularies of fields and segments,
+        // and the closest terms come first, then the most frequent
+        let mut matches: HashMap<&str, (u32, u32)> = HashMap::new();
+        for segment in &self.segments {
+            for f in self.get_field_ids(field) {
+                let Some(field) = segment.get_field(f) else {
+                    continue;
+                };
+                let vocabulary = &field.vocabulary;
+                for (t, d, frequency) in vocabulary.get_fuzzy_terms(term, distance, transpositions)
+                {
+                    matches.entry(t).or_insert((d, 0)).1 += frequency;
+                }
+            }
+        }
+
+        let mut terms: Vec<(String, u32, u32)> = matches
+            .into_iter()
+            .map(|(t, (d, f))| (t.to_string(), d, f))
+            .collect();
+        terms.sort_unstable_by(|(t1, d1, f1), (t2, d2, f2)| {
+            d1.cmp(d2).then(f2.cmp(f1)).then(t1.cmp(t2))
+        });
+        terms
+    }
+
+    fn get_segment(&self, doc_id: u32) -> Result<&Segment> {
+        self.segments
+            .iter()
+            .find(|s| s.contains_doc_id(doc_id))
+            .ok_or_else(|| Error::corrupted("document id out of range"))
+    }
+
+    fn is_live(&self, doc_id: u32) -> Result<bool> {
+        let segment = self.get_segment(doc_id)?;
+        Ok(segment.is_live(doc_id - segment.base_doc_id))
+    }
+
+    fn get_live_doc_ids(&self, doc_ids: DocumentIdsList) -> Result<DocumentIdsList> {
+        let mut live = DocumentIdsList::with_capacity(doc_ids.len());
+        for doc_id in doc_ids {
+            if self.is_live(doc_id)? {
+                live.push(doc_id);
+            }
+        }
+        Ok(live)
+    }
+
+    fn get_doc_path(&self, doc_id: u32) -> Result<String> {
+        let segment = self.get_segment(doc_id)?;
+        Ok(segment.documents.get_doc_path(doc_id - segment.base_doc_id))
+    }
+
+    fn get_external_id(&self, doc_id: u32) -> Result<Option<String>> {
+        let segment = self.get_segment(doc_id)?;
+        Ok(segment
+            .documents
+            .get_external_id(doc_id - segment.base_doc_id))
+    }
+
+    fn get_doc_len(&self, doc_id: u32) -> Result<u32> {
+        // the length of a document is the one of all its fields
+        let segment = self.get_segment(doc_id)?;
+        Ok((0..self.fields.get_num_fields())
+            .map(|f| segment.get_field_len(f, doc_id))
+            .sum())
+    }
+
+    fn get_field_len(&self, field_id: usize, doc_id: u32) -> Result<u32> {
+        Ok(self.get_segment(doc_id)?.get_field_len(field_id, doc_id))
+    }
+
+    fn get_num_documents(&self) -> u32 {
+        self.segments
+            .iter()
+            .map(|s| s.documents.get_num_documents())
+            .sum()
+    }
+
+    fn get_num_live_documents(&self) -> u32 {
+        self.segments
+            .iter()
+            .map(|s| s.get_num_live_documents())
+            .sum()
+    }
+
+    fn get_avg_field_len(&self, field_id: usize) -> f64 {
+        let total_len: u64 = self
+            .segments
+            .iter()
+            .map(|s| s.get_live_lengths_sum(field_id))
+            .sum();
+
+        match self.get_num_live_documents() {
+            // every document was deleted
+            0 => 0.0,
+            n => total_len as f64 / n as f64,
+        }
+    }
+
+    fn compute_score(
+        document_score: &DocumentScore,
+        num_tokens: usize,
+        proximity_weight: f64,
+    ) -> f64 {
+        if proximity_weight == 0.0 {
+            return document_score.relevance;
+        }
+
+        let mut window = u32::MAX;
+
+        let mut arr: Vec<(u32, u32)> = document_score
+            .term_positions
+            .iter()
+            .flat_map(|(id, positions)| positions.iter().map(|p| (*p, *id)))
+            .collect();
+
+        arr.sort_unstable();
+
+        let mut j = 0;
+        let mut seen: HashMap<u32, u32> = HashMap::new();
+        for (pos, id) in arr.iter().copied() {
+            seen.entry(id).and_modify(|c| *c += 1).or_insert(1);
+
+            while seen.len() == num_tokens && j < arr.len() {
+                let (j_pos, j_id) = arr[j];
+                window = min(window, pos - j_pos + 1);
+
+                seen.entry(j_id).and_modify(|c| *c -= 1);
+                if *seen.get(&j_id).unwrap() == 0 {
+                    seen.remove(&j_id);
+                }
+
+                j += 1;
+            }
+        }
+
+        proximity_weight * (num_tokens as f64 / window as f64) + document_score.relevance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::utils::{
+        build_test_dir_index, build_test_index, create_records, create_temporary_dir_path,
+        get_sorted_paths,
+    };
+    use std::{fs, path::Path, thread, time::Duration};
+
+    #[test]
+    fn test_build() {
+        let index_path = &create_temporary_dir_path();
+        Engine::build_engine(
+            "test_data/docs",
+            index_path,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+
+        for ele in ["hello", "man", "world"] {
+            assert!(idx.segments[0]
+                .get_field(0)
+                .unwrap()
+                .vocabulary
+                .get_term_index(ele)
+                .is_some());
+        }
+
+        let mut free_query: Vec<String> = idx
+            .free_query("hello", 0, 10)
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| d.path.clone())
+            .collect();
+        free_query.sort();
+
+        assert_eq!(free_query, ["test_data/docs/1.txt", "test_data/docs/2.txt"]);
+
+        let mut boolean_query: Vec<String> = idx
+            .boolean_query("hello AND NOT world", 0, 10)
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| d.path.clone())
+            .collect();
+        boolean_query.sort();
+
+        assert_eq!(boolean_query, ["test_data/docs/2.txt"]);
+
+        // negating a term missing from the index matches every document
+        let not_missing = idx.boolean_query("NOT qqqq", 0, 10).unwrap();
+        assert_eq!(not_missing.total_hits, 3);
+    }
+
+    #[test]
+    fn test_build_with_memory_budget() {
+        let in_memory_path = &create_temporary_dir_path();
+        let external_path = &create_temporary_dir_path();
+
+        // a zero budget flushes a run after every document
+        Engine::build_engine(
+            "test_data/near",
+            in_memory_path,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+        Engine::build_engine(
+            "test_data/near",
+            external_path,
+            1.0,
+            0,
+            0,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+
+        let in_memory = Engine::load_index(in_memory_path).unwrap();
+        let external = Engine::load_index(external_path).unwrap();
+
+        for query in ["tax reform", "income of the schools", "reform"] {
+            let in_memory_result: Vec<(String, f64)> = in_memory
+                .free_query(query, 0, 10)
+                .unwrap()
+                .documents
+                .iter()
+                .map(|d| (d.path.clone(), d.score))
+                .collect();
+            let external_result: Vec<(String, f64)> = external
+                .free_query(query, 0, 10)
+                .unwrap()
+                .documents
+                .iter()
+                .map(|d| (d.path.clone(), d.score))
+                .collect();
+
+            assert_eq!(in_memory_result, external_result);
+        }
+    }
+
+    #[test]
+    fn test_snippets() {
+        let idx = build_test_dir_index("test_data/near", &IndexConfig::default());
+
+        let highlighted = |query: &QueryResult| -> Vec<String> {
+            let document = &query.documents[0];
+            let text = fs::read_to_string(&document.path).unwrap();
+            let snippet = idx.get_snippet(&text, query, document);
+            snippet
+                .get_parts()
+                .iter()
+                .filter(|(_, h)| *h)
+                .map(|(t, _)| t.to_string())
+                .collect()
+        };
+
+        let result = idx.free_query("reforms", 0, 1).unwrap();
+        assert_eq!(result.documents[0].path, "test_data/near/1.txt");
+        assert_eq!(highlighted(&result), ["reform"]);
+
+        // ranked boolean matches know the positions of their terms
+        let result = idx.boolean_query("income AND tax", 0, 10).unwrap();
+        assert!(!result.documents[0].positions.is_empty());
+        assert_eq!(highlighted(&result), ["income", "tax"]);
+
+        // no positions are known after boolean operators when matches are not ranked
+        let unranked = QueryOptions {
+            ranked: false,
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .boolean_query_with_options("income AND tax", 0, 10, &unranked)
+            .unwrap();
+        assert!(result.documents[0].positions.is_empty());
+        assert_eq!(highlighted(&result), ["income", "tax"]);
+    }
+
+    #[test]
+    fn test_pagination() {
+        let idx = build_test_dir_index("test_data/near", &IndexConfig::default());
+
+        let all = idx.free_query("tax reform schools", 0, 10).unwrap();
+        assert_eq!(all.total_hits, 3);
+        assert!(all.total_hits_exact);
+
+        let page: Vec<u32> = (0..3)
+            .flat_map(|i| {
+                idx.free_query("tax reform schools", i, 1)
+                    .unwrap()
+                    .documents
+            })
+            .map(|d| d.id)
+            .collect();
+        let expected: Vec<u32> = all.documents.iter().map(|d| d.id).collect();
+        assert_eq!(page, expected);
+
+        let all = idx.boolean_query("tax OR schools", 0, 10).unwrap();
+        let result = idx.boolean_query("tax OR schools", 1, 1).unwrap();
+        assert_eq!(result.total_hits, 3);
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].id, all.documents[1].id);
+
+        let unranked = QueryOptions {
+            ranked: false,
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .boolean_query_with_options("tax OR schools", 1, 1, &unranked)
+            .unwrap();
+        assert_eq!(result.documents[0].id, 1);
+
+        let result = idx.boolean_query("tax", 5, 10).unwrap();
+        assert_eq!(result.total_hits, 3);
+        assert!(result.documents.is_empty());
+
+        // pages past the end of the results are empty, however far they are
+        for result in [
+            idx.free_query("tax", usize::MAX, 10).unwrap(),
+            idx.boolean_query("tax", usize::MAX, 10).unwrap(),
+        ] {
+            assert_eq!(result.total_hits, 3);
+            assert!(result.documents.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_phrase_queries() {
+        let idx = build_test_dir_index("test_data/docs", &IndexConfig::default());
+
+        assert_eq!(
+            get_sorted_paths(idx.free_query("\"hello world\"", 0, 10).unwrap()),
+            ["test_data/docs/1.txt"]
+        );
+        assert!(get_sorted_paths(idx.free_query("\"world hello\"", 0, 10).unwrap()).is_empty());
+        assert!(get_sorted_paths(idx.boolean_query("\"hello zzzz\"", 0, 10).unwrap()).is_empty());
+
+        // phrases without terms do not restrict the free query
+        for query in ["hello \"", "hello \"\"", "hello \" \""] {
+            assert_eq!(
+                get_sorted_paths(idx.free_query(query, 0, 10).unwrap()),
+                ["test_data/docs/1.txt", "test_data/docs/2.txt"]
+            );
+        }
+
+        assert_eq!(
+            get_sorted_paths(
+                idx.boolean_query("\"man world\" OR \"hello man\"", 0, 10)
+                    .unwrap()
+            ),
+            ["test_data/docs/2.txt", "test_data/docs/3.txt"]
+        );
+        assert_eq!(
+            get_sorted_paths(
+                idx.boolean_query("world AND NOT \"hello world\"", 0, 10)
+                    .unwrap()
+            ),
+            ["test_data/docs/3.txt"]
+        );
+    }
+
+    #[test]
+    fn test_near_queries() {
+        let idx = build_test_dir_index("test_data/near", &IndexConfig::default());
+
+        assert_eq!(
+            get_sorted_paths(idx.boolean_query("tax NEAR/1 reform", 0, 10).unwrap()),
+            ["test_data/near/1.txt"]
+        );
+        assert_eq!(
+            get_sorted_paths(idx.boolean_query("tax NEAR/5 reform", 0, 10).unwrap()),
+            ["test_data/near/1.txt", "test_data/near/2.txt"]
+        );
+        assert_eq!(
+            get_sorted_paths(
+                idx.boolean_query("reform NEAR/5 tax AND NOT \"tax reform\"", 0, 10)
+                    .unwrap()
+            ),
+            ["test_data/near/2.txt"]
+        );
+
+        assert!(matches!(
+            idx.boolean_query("tax NEAR/5 (reform OR cuts)", 0, 10),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_boolean_queries() {
+        let index_path = &create_temporary_dir_path();
+        Engine::build_engine(
+            "test_data/near",
+            index_path,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+
+        for query in [
+            "AND",
+            "tax AND",
+            "NOT",
+            "tax OR",
+            "(tax",
+            "tax)",
+            "NEAR/2 tax",
+            "",
+        ] {
+            assert!(
+                matches!(idx.boolean_query(query, 0, 10), Err(Error::InvalidQuery(_))),
+                "{query}"
+            );
+        }
+
+        // the engine is still usable after a bad query
+        assert_eq!(
+            idx.boolean_query("tax AND reform", 0, 10)
+                .unwrap()
+                .total_hits,
+            3
+        );
+    }
+
+    #[test]
+    fn test_boolean_query_syntax() {
+        let index_path = &create_temporary_dir_path();
+        Engine::build_engine(
+            "test_data/near",
+            index_path,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+
+        let paths = |query: &str| -> Vec<String> {
+            let mut paths: Vec<String> = idx
+                .boolean_query(query, 0, 10)
+                .unwrap()
+                .documents
+                .iter()
+                .map(|d| d.path.clone())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        // operands are analyzed as the indexed text, and juxtaposed ones are joined by AND
+        let result = idx.boolean_query("TAX Reforms", 0, 10).unwrap();
+        assert_eq!(result.query, ["tax", "reform"]);
+        assert_eq!(result.total_hits, 3);
+
+        assert_eq!(
+            paths("tax -cuts"),
+            ["test_data/near/1.txt", "test_data/near/2.txt"]
+        );
+        assert_eq!(
+            paths("+tax -\"tax reform\""),
+            ["test_data/near/2.txt", "test_data/near/3.txt"]
+        );
+        assert_eq!(paths("(income OR cuts) -schools"), ["test_data/near/2.txt"]);
+    }
+
+    #[test]
+    fn test_conjunctions_across_blocks() {
+        let records = create_records(1000, |i| {
+            let mut text = String::from("common");
+            if i % 2 == 0 {
+                text += " even";
+            }
+            if i % 7 == 0 {
+                text += " seventh";
+            }
+            if [3, 500, 994].contains(&i) {
+                text += " rare";
+            }
+            vec![("title", text)]
+        });
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        let ids = |query: &str| -> Vec<u32> {
+            let mut ids: Vec<u32> = idx
+                .boolean_query(query, 0, 1000)
+                .unwrap()
+                .documents
+                .iter()
+                .map(|d| d.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(ids("common AND rare AND even"), [500, 994]);
+        assert_eq!(
+            ids("even seventh"),
+            (0..1000).step_by(14).collect::<Vec<u32>>()
+        );
+        assert_eq!(ids("seventh rare"), [994]);
+        assert_eq!(ids("(even OR seventh) rare -common"), Vec::<u32>::new());
+        assert_eq!(ids("title:rare common"), [3, 500, 994]);
+    }
+
+    #[test]
+    fn test_pruned_free_queries() {
+        let records = create_records(2000, |i| {
+            let mut body = vec!["alpha"; i % 5 + 1];
+            body.extend(vec!["lorem"; i % 17]);
+            if i % 3 == 0 {
+                body.push("beta");
+            }
+            if i % 11 == 0 {
+                body.insert(0, "gamma");
+            }
+            let title = match i % 13 {
+                0 => "alpha delta",
+                _ if i % 2 == 0 => "delta",
+                _ => "lorem",
+            };
+            vec![
+                ("title", title.to_string()),
+                (DEFAULT_FIELD, body.join(" ")),
+            ]
+        });
+        let idx = build_test_index(records, &IndexConfig::default());
+        let options = QueryOptions::default();
+
+        // skipping documents must not change the top k, nor their scores
+        for query in [
+            "alpha",
+            "beta gamma",
+            "alpha gamma",
+            "title:delta beta",
+            "alpha beta gamma delta",
+            "\"gamma alpha\" beta",
+        ] {
+            for (offset, limit) in [(0, 10), (5, 5), (0, 1)] {
+                let pruned = idx
+                    .run_free_query(query, None, offset, limit, &options, true)
+                    .unwrap();
+                let all = idx
+                    .run_free_query(query, None, offset, limit, &options, false)
+                    .unwrap();
+
+                let results = |r: &QueryResult| -> Vec<(u32, f64)> {
+                    r.documents.iter().map(|d| (d.id, d.score)).collect()
+                };
+                assert_eq!(results(&pruned), results(&all), "{query}");
+                assert!(all.total_hits_exact);
+                assert!(pruned.total_hits > 0);
+            }
+        }
+
+        let all = idx
+            .run_free_query("beta gamma", None, 0, 10, &options, false)
+            .unwrap();
+        assert_eq!(all.total_hits, 788);
+
+        // terms spread independently of each other give a close estimate
+        let pruned = idx.free_query("beta gamma", 0, 10).unwrap();
+        assert!(!pruned.total_hits_exact);
+        assert!(pruned.total_hits.abs_diff(all.total_hits) <= all.total_hits / 20);
+    }
+
+    #[test]
+    fn test_ranked_boolean_queries() {
+        let records = create_records(300, |i| {
+            let mut body = vec!["alpha"; i % 4 + 1];
+            body.extend(vec!["lorem"; i % 7]);
+            if i % 3 == 0 {
+                body.push("beta");
+            }
+            if i % 5 == 0 {
+                body.push("gamma");
+            }
+            vec![(DEFAULT_FIELD, body.join(" "))]
+        });
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        // matches are scored like free queries on their positive terms
+        let free: HashMap<u32, f64> = idx
+            .free_query("alpha", 0, 300)
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| (d.id, d.score))
+            .collect();
+        let result = idx.boolean_query("alpha AND NOT beta", 0, 20).unwrap();
+        assert_eq!(result.total_hits, 200);
+        assert!(result.total_hits_exact);
+        assert_eq!(result.documents.len(), 20);
+        assert!(result
+            .documents
+            .windows(2)
+            .all(|d| d[0].score >= d[1].score));
+        assert!(result
+            .documents
+            .iter()
+            .all(|d| d.id % 3 != 0 && d.score == free[&d.id]));
+
+        // matches without positive terms come last, in doc id order
+        let result = idx.boolean_query("gamma OR NOT beta", 0, 300).unwrap();
+        assert_eq!(result.total_hits, 220);
+        let (scored, unscored) = result.documents.split_at(60);
+        assert!(scored.iter().all(|d| d.id % 5 == 0 && d.score > 0.0));
+        assert!(unscored.iter().all(|d| d.id % 5 != 0 && d.score == 0.0));
+        assert!(unscored.windows(2).all(|d| d[0].id < d[1].id));
+
+        let result = idx.boolean_query("NOT beta", 190, 20).unwrap();
+        assert_eq!(result.total_hits, 200);
+        assert_eq!(result.documents.len(), 10);
+        assert_eq!(result.documents[0].id, 286);
+    }
+
+    #[test]
+    fn test_filtered_queries() {
+        let records = create_records(300, |i| {
+            let mut body = vec!["alpha"; i % 4 + 1];
+            if i % 3 == 0 {
+                body.push("beta");
+            }
+            let title = if i % 5 == 0 { "gamma" } else { "lorem" };
+            vec![
+                ("title", title.to_string()),
+                (DEFAULT_FIELD, body.join(" ")),
+            ]
+        });
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        // documents are scored as without the filter, whose terms are not ranked
+        let free: HashMap<u32, f64> = idx
+            .free_query("alpha", 0, 300)
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| (d.id, d.score))
+            .collect();
+        let result = idx
+            .filtered_query("alpha", "title:gamma AND NOT beta", 0, 100)
+            .unwrap();
+        assert_eq!(result.query, ["alpha"]);
+        assert_eq!(result.total_hits, 40);
+        assert!(result
+            .documents
+            .iter()
+            .all(|d| d.id % 5 == 0 && d.id % 3 != 0 && d.score == free[&d.id]));
+
+        // the top k of the filtered documents is kept when others are skipped
+        let top = idx.filtered_query("alpha", "NOT beta", 0, 5).unwrap();
+        let all = idx.filtered_query("alpha", "NOT beta", 0, 200).unwrap();
+        let ids = |r: &QueryResult| -> Vec<u32> { r.documents.iter().map(|d| d.id).collect() };
+        assert_eq!(ids(&top), ids(&all)[..5]);
+        assert_eq!(all.total_hits, 200);
+
+        // filters matching nothing leave nothing to rank, malformed ones are reported
+        let result = idx.filtered_query("alpha", "title:alpha", 0, 10).unwrap();
+        assert_eq!(result.total_hits, 0);
+        assert!(matches!(
+            idx.filtered_query("alpha", "beta AND", 0, 10),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_wildcard_queries() {
+        let bodies = [
+            "computer networks",
+            "computing power",
+            "organization chart",
+            "realization cat",
+        ];
+        let records = create_records(200, |i| {
+            let title = if i % 2 == 0 { "cot" } else { "cut" };
+            vec![
+                ("title", title.to_string()),
+                (DEFAULT_FIELD, format!("{} w{i:03}", bodies[i % 4])),
+            ]
+        });
+        // suffixes are only found in terms that are not stemmed
+        let config = IndexConfig {
+            analyzer: AnalyzerConfig::parse("unicode,lowercase").unwrap(),
+            ..IndexConfig::default()
+        };
+        let idx = build_test_index(records, &config);
+
+        let result = idx.free_query("Comput*", 0, 10).unwrap();
+        let mut terms = result.query.clone();
+        terms.sort();
+        assert_eq!(terms, ["computer", "computing"]);
+        assert_eq!(result.total_hits, 100);
+
+        let total_hits = |query: &str| idx.boolean_query(query, 0, 10).unwrap().total_hits;
+        assert_eq!(total_hits("*ization AND NOT cat"), 50);
+        assert_eq!(total_hits("title:c?t comput*"), 100);
+        assert_eq!(total_hits("title:co? comput*"), 50);
+        assert_eq!(total_hits("comput* NEAR/1 power"), 50);
+        assert_eq!(total_hits("title:*ization"), 0);
+
+        // a trailing `?` ends a question in free queries, it is not a wildcard
+        let result = idx.free_query("realization cat?", 0, 10).unwrap();
+        assert_eq!(result.query, ["realization", "cat"]);
+        assert_eq!(result.total_hits, 50);
+
+        // only the most frequent terms are searched, ties broken by their order
+        let result = idx.free_query("w*", 0, 10).unwrap();
+        assert_eq!(result.query.len(), MAX_EXPANDED_TERMS);
+        assert_eq!(result.query[0], "w000");
+        assert_eq!(result.total_hits, MAX_EXPANDED_TERMS);
+
+        assert!(matches!(
+            idx.boolean_query("cat OR *", 0, 10),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy_queries() {
+        let bodies = [
+            "color wheel",
+            "colour chart",
+            "collar button",
+            "dollar bill",
+        ];
+        let records = create_records(200, |i| vec![(DEFAULT_FIELD, bodies[i % 4].to_string())]);
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        let result = idx.free_query("colr~1", 0, 10).unwrap();
+        assert_eq!(result.query, ["color"]);
+        assert_eq!(result.total_hits, 50);
+
+        // the closest terms come first, and free queries cap the distance
+        let result = idx.free_query("Colr~", 0, 10).unwrap();
+        assert_eq!(result.query, ["color", "collar", "colour"]);
+        assert_eq!(result.total_hits, 150);
+        assert_eq!(idx.free_query("colr~7", 0, 10).unwrap().total_hits, 150);
+
+        let total_hits = |query: &str, options: &QueryOptions| {
+            idx.boolean_query_with_options(query, 0, 10, options)
+                .unwrap()
+                .total_hits
+        };
+        let options = QueryOptions::default();
+        assert_eq!(total_hits("colour~1 AND NOT chart", &options), 50);
+        assert_eq!(total_hits("colro~1", &options), 50);
+        assert_eq!(total_hits("dolar~1 OR colro~1", &options), 100);
+
+        // without transpositions, swapped chars are two edits
+        let levenshtein = QueryOptions {
+            transpositions: false,
+            ..QueryOptions::default()
+        };
+        assert_eq!(total_hits("colro~1", &levenshtein), 0);
+        assert_eq!(total_hits("colro~2", &levenshtein), 50);
+
+        assert!(matches!(
+            idx.boolean_query("color~3", 0, 10),
+            Err(Error::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_spelling_suggestions() {
+        let records = create_records(200, |i| {
+            let body = match i {
+                0..80 => "apple juice",
+                80..90 => "ample pie",
+                90..100 => "cherry pie",
+                100 => "aple tree",
+                _ => "lorem ipsum",
+            };
+            vec![(DEFAULT_FIELD, body.to_string())]
+        });
+        let config = IndexConfig {
+            analyzer: AnalyzerConfig::parse("unicode,lowercase").unwrap(),
+            ..IndexConfig::default()
+        };
+        let idx = build_test_index(records, &config);
+
+        // the other terms of the query tell which correction was meant
+        let result = idx.free_query("ampel pie", 0, 10).unwrap();
+        assert_eq!(result.original_query, ["ampel", "pie"]);
+        assert_eq!(result.query, ["ample", "pie"]);
+        assert!(result.corrected);
+        assert_eq!(result.suggestions[0].query, ["ample", "pie"]);
+        assert_eq!(result.suggestions[0].hits, 10);
+        assert!(result.suggestions.len() > 1 && result.suggestions.len() <= MAX_SUGGESTIONS);
+        assert!(result.documents.iter().all(|d| (80..90).contains(&d.id)));
+
+        let result = idx.boolean_query("ampel AND pie", 0, 10).unwrap();
+        assert!(result.corrected);
+        assert_eq!(result.total_hits, 10);
+        let result = idx.boolean_query("pie AND NOT ampel", 0, 10).unwrap();
+        assert_eq!(result.query, ["pie", "ample"]);
+        assert_eq!(result.total_hits, 10);
+
+        // without correction, misspelled terms are searched as typed and only suggested
+        let literal = QueryOptions {
+            spellcheck: false,
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .boolean_query_with_options("ampel AND pie", 0, 10, &literal)
+            .unwrap();
+        assert!(!result.corrected);
+        assert_eq!(result.total_hits, 0);
+        assert_eq!(result.suggestions[0].query, ["ample", "pie"]);
+
+        // rare terms are searched, and much more frequent ones are suggested
+        let result = idx.free_query("aple", 0, 10).unwrap();
+        assert!(!result.corrected);
+        assert_eq!(result.query, ["aple"]);
+        assert_eq!(result.total_hits, 1);
+        assert_eq!(result.suggestions[0].query, ["apple"]);
+        assert_eq!(result.suggestions[0].hits, 80);
+
+        let result = idx.free_query("cherry pie", 0, 10).unwrap();
+        assert!(!result.corrected && result.suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_scorers() {
+        let records = create_records(800, |i| {
+            let mut body = vec!["alpha"; i % 4 + 1];
+            body.extend(vec!["lorem"; i % 23]);
+            if i % 3 == 0 {
+                body.push("beta");
+            }
+            if i % 7 == 0 {
+                body.insert(0, "gamma");
+            }
+            let title = if i % 5 == 0 { "beta" } else { "lorem" };
+            vec![
+                ("title", title.to_string()),
+                (DEFAULT_FIELD, body.join(" ")),
+            ]
+        });
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        // every scorer bounds its scores, so that pruning keeps the same top k
+        for scorer in ["bm25:0.9:0.4", "bm25+", "tfidf", "dirichlet:500", "dfr"] {
+            for proximity_weight in [0.0, 3.0] {
+                let options = QueryOptions {
+                    scorer: parse_scorer(scorer).unwrap(),
+                    proximity_weight,
+                    ..QueryOptions::default()
+                };
+                for query in [
+                    "alpha",
+                    "beta gamma",
+                    "alpha gamma lorem",
+                    "title:beta alpha",
+                ] {
+                    let results = |prune: bool| -> Vec<(u32, f64)> {
+                        idx.run_free_query(query, None, 0, 10, &options, prune)
+                            .unwrap()
+                            .documents
+                            .iter()
+                            .map(|d| (d.id, d.score))
+                            .collect()
+                    };
+                    assert_eq!(results(true), results(false), "{scorer} {query}");
+                }
+            }
+        }
+
+        // without proximity, scores are the relevance alone
+        let options = QueryOptions {
+            scorer: Arc::new(TfIdf),
+            proximity_weight: 0.0,
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .free_query_with_options("gamma", 0, 1, &options)
+            .unwrap();
+        let idf = (1.0 + 800.0 / 115.0f64).ln();
+        assert!((result.documents[0].score - 2.0f64.ln() * idf).abs() < 1e-9);
+        // positions are not decoded to score, but still found for the returned page
+        assert!(!result.documents[0].positions.is_empty());
+
+        // long documents are not penalized twice for their length
+        let bm25 = QueryOptions {
+            scorer: Arc::new(Bm25 { k1: 1.2, b: 0.0 }),
+            proximity_weight: 0.0,
+            ..QueryOptions::default()
+        };
+        let result = idx.free_query_with_options("gamma", 0, 200, &bm25).unwrap();
+        assert!(result
+            .documents
+            .iter()
+            .all(|d| (d.score - result.documents[0].score).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_postings_codecs() {
+        let records = || {
+            create_records(600, |i| {
+                let mut body = vec!["alpha"; i % 5 + 1];
+                if i % 3 == 0 {
+                    body.push("beta");
+                }
+                if i % 7 == 0 {
+                    body.insert(0, "gamma");
+                }
+                vec![(DEFAULT_FIELD, body.join(" "))]
+            })
+        };
+        let results = |idx: &Engine| -> Vec<Vec<(u32, f64)>> {
+            let free = ["alpha", "beta gamma", "\"gamma alpha\""]
+                .map(|q| idx.free_query(q, 0, 20).unwrap());
+            let boolean = ["beta AND gamma", "gamma NEAR/1 alpha", "NOT beta"]
+                .map(|q| idx.boolean_query(q, 0, 20).unwrap());
+            free.iter()
+                .chain(&boolean)
+                .map(|r| r.documents.iter().map(|d| (d.id, d.score)).collect())
+                .collect()
+        };
+
+        // every codec answers queries like the gamma codes, and is kept by compactions
+        let mut expected = None;
+        for codec in [
+            PostingsCodec::Gamma,
+            PostingsCodec::BitPacking,
+            PostingsCodec::PForDelta,
+            PostingsCodec::EliasFano,
+        ] {
+            let index_path = &create_temporary_dir_path();
+            let config = IndexConfig {
+                codec,
+                ..IndexConfig::default()
+            };
+            Engine::build_engine_from_records(records(), index_path, 1.0, 0, 1 << 20, &config)
+                .unwrap();
+
+            let idx = Engine::load_index(index_path).unwrap();
+            assert_eq!(idx.get_manifest().codec, codec.get_name());
+            let expected = expected.get_or_insert_with(|| results(&idx));
+            assert_eq!(&results(&idx), expected, "{}", codec.get_name());
+
+            Engine::compact_engine(index_path).unwrap();
+            let idx = Engine::load_index(index_path).unwrap();
+            assert_eq!(idx.get_manifest().codec, codec.get_name());
+            assert_eq!(&results(&idx), expected, "{}", codec.get_name());
+        }
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let docs_path = &create_temporary_dir_path();
+        let index_path = &format!("{docs_path}/.index/idx");
+
+        let write_doc = |name: &str, content: &str| {
+            fs::create_dir_all(docs_path).unwrap();
+            fs::write(format!("{docs_path}/{name}"), content).unwrap();
+        };
+
+        let paths = |r: QueryResult| -> Vec<String> {
+            let mut paths: Vec<String> = r
+                .documents
+                .iter()
+                .map(|d| d.path.replace(docs_path, ""))
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        write_doc("1.txt", "hello world");
+        write_doc("2.txt", "hello man");
+        Engine::build_engine(
+            docs_path,
+            index_path,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+
+        // old files are seen as modified only when written after the index
+        thread::sleep(Duration::from_millis(1100));
+
+        write_doc("2.txt", "goodbye man");
+        write_doc("3.txt", "hello there");
+        fs::remove_file(format!("{docs_path}/1.txt")).unwrap();
+        Engine::update_engine(docs_path, index_path).unwrap();
+
+        let idx = Engine::load_index(index_path).unwrap();
+        assert_eq!(idx.segments.len(), 2);
+        assert_eq!(idx.get_manifest().num_documents, 2);
+        assert_eq!(
+            paths(idx.boolean_query("hello", 0, 10).unwrap()),
+            ["/3.txt"]
+        );
+        assert_eq!(paths(idx.free_query("goodbye", 0, 10).unwrap()), ["/2.txt"]);
+        assert_eq!(
+            paths(idx.boolean_query("NOT there", 0, 10).unwrap()),
+            ["/2.txt"]
+        );
+
+        // deleted documents do not count in the statistics, compacted or not
+        let scores = |idx: &Engine| -> Vec<f64> {
+            let mut documents = idx.free_query("hello man", 0, 10).unwrap().documents;
+            documents.sort_by(|d1, d2| d1.path.cmp(&d2.path));
+            documents.iter().map(|d| d.score).collect()
+        };
+        let updated_scores = scores(&idx);
+        let timestamp = Segment::load_manifest(index_path).unwrap()[1].timestamp;
+
+        Engine::compact_engine(index_path).unwrap();
+
+        let idx = Engine::load_index(index_path).unwrap();
+        let compacted_scores = scores(&idx);
+        assert_eq!(updated_scores.len(), 2);
+        for (s1, s2) in updated_scores.iter().zip(&compacted_scores) {
+            assert!((s1 - s2).abs() < 1e-9, "{s1} != {s2}");
+        }
+        assert_eq!(idx.segments.len(), 1);
+        assert_eq!(idx.get_num_documents(), 2);
+        // the compacted segment keeps the newest timestamp, so that files
+        // re-indexed by the update are not re-indexed again
+        assert_eq!(
+            Segment::load_manifest(index_path).unwrap(),
+            [segments::SegmentInfo { id: 2, timestamp }]
+        );
+        // the merged segments are removed once the compacted one is in place
+        assert!(!Path::new(&(index_path.to_string() + DOCUMENTS_EXTENSION)).exists());
+        assert!(Path::new(&(index_path.to_string() + ".2" + DOCUMENTS_EXTENSION)).exists());
+        assert_eq!(
+            paths(idx.boolean_query("hello OR man", 0, 10).unwrap()),
+            ["/2.txt", "/3.txt"]
+        );
+        assert!(idx.segments[0]
+            .get_field(0)
+            .unwrap()
+            .vocabulary
+            .get_term_index("world")
+            .is_none());
+
+        // once every file is removed, statistics are empty rather than undefined
+        fs::remove_file(format!("{docs_path}/2.txt")).unwrap();
+        fs::remove_file(format!("{docs_path}/3.txt")).unwrap();
+        Engine::update_engine(docs_path, index_path).unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+        assert_eq!(idx.get_avg_field_len(0), 0.0);
+        assert_eq!(idx.free_query("hello", 0, 10).unwrap().total_hits, 0);
+    }
+
+    #[test]
+    fn test_index_manifest() {
+        let index_path = &create_temporary_dir_path();
+        let config = IndexConfig {
+            analyzer: AnalyzerConfig::parse("unicode,lowercase,stemmer").unwrap(),
+            ..IndexConfig::default()
+        };
+        Engine::build_engine("test_data/docs", index_path, 0.9, 1, 1 << 20, &config).unwrap();
+
+        let idx = Engine::load_index(index_path).unwrap();
+        let manifest = idx.get_manifest();
+        assert_eq!(manifest.analyzer, "unicode,lowercase,stemmer:english");
+        assert_eq!(manifest.min_freq_threshold, 1);
+        assert_eq!(manifest.max_freq_percentage_threshold, 0.9);
+        assert_eq!(manifest.num_documents, 3);
+        assert!(manifest.num_terms > 0);
+        assert!(manifest
+            .files
+            .iter()
+            .any(|f| f.name.ends_with(POSTINGS_EXTENSION)));
+
+        // damaged files are reported instead of being decoded
+        let postings_path = index_path.to_string() + POSTINGS_EXTENSION;
+        let mut postings = fs::read(&postings_path).unwrap();
+        postings[0] ^= 0xff;
+        fs::write(&postings_path, &postings).unwrap();
+        assert!(matches!(
+            Engine::load_index(index_path),
+            Err(Error::Index(IndexError::ChecksumMismatch(_)))
+        ));
+
+        let documents_path = index_path.to_string() + DOCUMENTS_EXTENSION;
+        let documents = fs::read(&documents_path).unwrap();
+        fs::write(&documents_path, &documents[..documents.len() / 2]).unwrap();
+        assert!(matches!(
+            Engine::load_index(index_path),
+            Err(Error::Index(IndexError::SizeMismatch { .. }))
+        ));
+
+        fs::remove_file(index_path.to_string() + MANIFEST_EXTENSION).unwrap();
+        assert!(matches!(
+            Engine::load_index(index_path),
+            Err(Error::Index(IndexError::MissingManifest(_)))
+        ));
+    }
+
+    #[test]
+    fn test_recorded_analyzer() {
+        let index_path = &create_temporary_dir_path();
+        let analyzer = AnalyzerConfig::parse("standard,lowercase,stop_words,stemmer").unwrap();
+        let config = IndexConfig {
+            analyzer: analyzer.clone(),
+            ..IndexConfig::default()
+        };
+        Engine::build_engine("test_data/near", index_path, 1.0, 0, 1 << 20, &config).unwrap();
+
+        assert_eq!(AnalyzerConfig::load_analyzer(index_path).unwrap(), analyzer);
+
+        // queries go through the recorded analyzer, dropping stop words
+        let idx = Engine::load_index(index_path).unwrap();
+        assert!(idx.free_query("the", 0, 10).unwrap().documents.is_empty());
+        assert_eq!(
+            idx.free_query("the reforms", 0, 10).unwrap().query,
+            ["reform"]
+        );
+        assert_eq!(
+            idx.free_query("reforms \"the\"", 0, 10).unwrap().total_hits,
+            idx.free_query("reforms", 0, 10).unwrap().total_hits
+        );
+
+        // stop words leave a gap that phrases must respect
+        let result = idx.free_query("\"reform of the income\"", 0, 10).unwrap();
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].path, "test_data/near/2.txt");
+        assert!(idx
+            .boolean_query("\"reform income\"", 0, 10)
+            .unwrap()
+            .documents
+            .is_empty());
+    }
+
+    #[test]
+    fn test_document_languages() {
+        let idx = build_test_dir_index("test_data/languages", &IndexConfig::default());
+
+        // accented words are kept, and stemmed in the language of the document
+        assert_eq!(
+            get_sorted_paths(idx.free_query("été", 0, 10).unwrap()),
+            ["test_data/languages/2.fr.txt"]
+        );
+
+        let options = QueryOptions {
+            language: Some(Language::French),
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .free_query_with_options("la maison construite", 0, 10, &options)
+            .unwrap();
+        assert_eq!(result.query, ["la", "maison", "construit"]);
+        assert_eq!(get_sorted_paths(result), ["test_data/languages/2.fr.txt"]);
+
+        let options = QueryOptions {
+            language: Some(Language::German),
+            ..QueryOptions::default()
+        };
+        assert_eq!(
+            get_sorted_paths(
+                idx.boolean_query_with_options("\"alten Häusern\"", 0, 10, &options)
+                    .unwrap()
+            ),
+            ["test_data/languages/3.de.txt"]
+        );
+    }
+
+    #[test]
+    fn test_fielded_records() {
+        let records = vec![
+            Record::new("records/1")
+                .with_field("title", "Rust programming")
+                .with_field("body", "Writing async code with tokio"),
+            Record::new("records/2")
+                .with_field("title", "Gardening")
+                .with_field("body", "Removing rust from old tools"),
+            Record::new("records/3")
+                .with_field("title", "Async patterns")
+                .with_field("body", "Notes on rust and futures"),
+        ];
+        let idx = build_test_index(records, &IndexConfig::default());
+
+        let paths = |r: QueryResult| -> Vec<String> {
+            r.documents.iter().map(|d| d.path.clone()).collect()
+        };
+
+        let result = idx.free_query("title:rust", 0, 10).unwrap();
+        assert_eq!(result.query, ["title:rust"]);
+        assert_eq!(paths(result), ["records/1"]);
+        assert_eq!(idx.free_query("rust", 0, 10).unwrap().total_hits, 3);
+
+        // field weights move documents matching in the title up or down
+        let weights = |w: f64| QueryOptions {
+            field_weights: vec![("title".to_string(), w)],
+            ..QueryOptions::default()
+        };
+        let result = idx
+            .free_query_with_options("rust", 0, 10, &weights(5.0))
+            .unwrap();
+        assert_eq!(paths(result)[0], "records/1");
+        let result = idx
+            .free_query_with_options("rust", 0, 10, &weights(0.0))
+            .unwrap();
+        assert_eq!(paths(result)[2], "records/1");
+
+        assert_eq!(
+            paths(
+                idx.boolean_query("title:async AND body:rust", 0, 10)
+                    .unwrap()
+            ),
+            ["records/3"]
+        );
+        assert_eq!(
+            paths(
+                idx.boolean_query("title:\"rust programming\"", 0, 10)
+                    .unwrap()
+            ),
+            ["records/1"]
+        );
+
+        // phrases never span two fields
+        assert!(idx
+            .boolean_query("\"programming writing\"", 0, 10)
+            .unwrap()
+            .documents
+            .is_empty());
+    }
+
+    #[test]
+    fn test_build_from_source() {
+        let index_path = &create_temporary_dir_path();
+        let source = SourceConfig::parse("jsonl:id:title,body").unwrap();
+        Engine::build_engine_from_source(
+            "test_data/records",
+            index_path,
+            &source,
+            1.0,
+            0,
+            1 << 20,
+            &IndexConfig::default(),
+        )
+        .unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+
+        // only the json lines dump is read, and results carry the record ids
+        let result = idx.free_query("ownership", 0, 10).unwrap();
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].external_id.as_deref(), Some("b1"));
+        assert_eq!(result.documents[0].path, "test_data/records/books.jsonl:1");
+
+        assert_eq!(idx.free_query("title:async", 0, 10).unwrap().total_hits, 1);
+        assert_eq!(idx.free_query("pasta", 0, 10).unwrap().total_hits, 0);
+
+        // updates keep reading the recorded source, unchanged dumps are left alone
+        Engine::update_engine("test_data/records", index_path).unwrap();
+        let idx = Engine::load_index(index_path).unwrap();
+        assert_eq!(idx.free_query("seeds", 0, 10).unwrap().total_hits, 1);
+        assert_eq!(idx.segments.len(), 1);
+    }
+
+    #[test]
+    fn test_document_store() {
+        let docs_path = &create_temporary_dir_path();
+        let index_path = &format!("{docs_path}/.index/idx");
+        let write_doc = |name: &str, content: &str| {
+            fs::create_dir_all(docs_path).unwrap();
+            fs::write(format!("{docs_path}/{name}"), content).unwrap();
+        };
+
+        write_doc("1.txt", "the old house by the river");
+        write_doc("2.txt", "a new house");
+        let config = IndexConfig {
+            store: StoreConfig::all(),
+            ..IndexConfig::default()
+        };
+        Engine::build_engine(docs_path, index_path, 1.0, 0, 1 << 20, &config).unwrap();
+
+        thread::sleep(Duration::from_millis(1100));
+        write_doc("3.txt", "houses on the river bank");
+        fs::remove_file(format!("{docs_path}/2.txt")).unwrap();
+        Engine::update_engine(docs_path, index_path).unwrap();
+        Engine::compact_engine(index_path).unwrap();
+
+        // the source files are not needed to serve results and snippets
+        for name in ["1.txt", "3.txt"] {
+            fs::remove_file(format!("{docs_path}/{name}")).unwrap();
+        }
+        let idx = Engine::load_index(index_path).unwrap();
+
+        let result = idx.free_query("river", 0, 10).unwrap();
+        assert_eq!(result.total_hits, 2);
+        for document in &result.documents {
+            let text = idx.get_stored_text(document.id).unwrap().unwrap();
+            assert!(text.contains("river"));
+
+            let snippet = idx.get_snippet(&text, &result, document);
+            assert!(snippet
+                .get_parts()
+                .iter()
+                .any(|(part, highlighted)| *highlighted && *part == "river"));
+        }
+
+        // only the configured fields are stored
+        let records = vec![Record::new("records/1")
+            .with_field("title", "Rust")
+            .with_field("body", "Ownership")];
+        let config = IndexConfig {
+            store: StoreConfig::parse("title").unwrap(),
+            ..IndexConfig::default()
+        };
+        let idx = build_test_index(records, &config);
+        assert_eq!(
+            idx.get_stored_fields(0).unwrap(),
+            [("title".to_string(), "Rust".to_string())]
+        );
+        assert_eq!(idx.get_stored_text(0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_engine_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Engine>();
     }
 }
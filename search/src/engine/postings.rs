@@ -1,10 +1,18 @@
 use std::cmp::min;
 
-use super::{InMemory, OFFSETS_EXTENSION, POSTINGS_EXTENSION};
-use crate::disk::{bits_reader::BitsReader, bits_writer::BitsWriter};
+use super::{CODEC_EXTENSION, OFFSETS_EXTENSION, POSTINGS_EXTENSION};
+use crate::{
+    disk::{
+        bits_reader::{BitsReader, BitsSource},
+        bits_writer::BitsWriter,
+        codecs,
+    },
+    Error, Result,
+};
 use std::cmp::Ordering::{Equal, Greater, Less};
+use std::path::Path;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Posting {
     pub document_id: u32,
     pub document_frequency: u32,
@@ -14,97 +22,514 @@ pub struct Posting {
 pub type PostingsList = Vec<Posting>;
 pub type DocumentIdsList = Vec<u32>;
 
+// postings are grouped in blocks, the unit that can be skipped
+const POSTINGS_BLOCK_SIZE: usize = 128;
+
+// skip pointers of a block: its last doc id, and the length in
+// bits of its doc ids and frequencies, and of its positions, along with
+// the largest frequency and the shortest field length of its documents,
+// which bound the score any of them can get
+pub struct BlockHeader {
+    pub last_document_id: u32,
+    ids_len: u64,
+    positions_len: u64,
+    pub max_frequency: u32,
+    pub min_length: u32,
+}
+
+// how the doc ids and frequencies of blocks are encoded, positions are always gamma codes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PostingsCodec {
+    // gaps and frequencies interleaved, as gamma codes
+    #[default]
+    Gamma,
+    // gaps, then frequencies, each packed at the width of the largest
+    BitPacking,
+    // as bit packing, with the largest values patched after the block
+    PForDelta,
+    // doc ids with Elias-Fano, then bit packed frequencies
+    EliasFano,
+}
+
+const CODECS: [(PostingsCodec, &str); 4] = [
+    (PostingsCodec::Gamma, "gamma"),
+    (PostingsCodec::BitPacking, "bitpack"),
+    (PostingsCodec::PForDelta, "pfor"),
+    (PostingsCodec::EliasFano, "elias_fano"),
+];
+
 pub struct Postings {
+    source: BitsSource,
+    offsets: Vec<u64>,
+    codec: PostingsCodec,
+}
+
+// walks a postings list document at a time, only decoding the blocks it stops in
+pub struct PostingsCursor {
     reader: BitsReader,
+    headers: Vec<BlockHeader>,
+    block_offsets: Vec<u64>,
+    codec: PostingsCodec,
+    n: usize,
+    block: usize,
+    index: usize,
+    doc_ids: DocumentIdsList,
+    frequencies: Vec<u32>,
+    // positions of the current block, decoded on first use
+    positions: Option<Vec<Vec<u32>>>,
+}
+
+// writes postings lists one at a time, keeping only their offsets in memory
+pub struct PostingsWriter {
+    postings_writer: BitsWriter,
+    codec: PostingsCodec,
+    offsets_path: String,
     offsets: Vec<u64>,
+    offset: u64,
 }
 
-impl Postings {
-    pub fn load_postings_reader(input_path: &str) -> Postings {
-        let path = input_path.to_string() + OFFSETS_EXTENSION;
-        let mut offsets_reader = BitsReader::new(&path);
+impl PostingsCodec {
+    pub fn from_name(name: &str) -> Option<PostingsCodec> {
+        CODECS.iter().find(|(_, n)| *n == name).map(|(c, _)| *c)
+    }
 
-        let mut offset = 0;
-        let offsets = (0..offsets_reader.read_vbyte())
-            .map(|_| {
-                offset += offsets_reader.read_gamma() as u64;
-                offset
+    pub fn get_name(&self) -> &'static str {
+        CODECS
+            .iter()
+            .find(|(c, _)| c == self)
+            .map_or("", |(_, n)| n)
+    }
+
+    pub fn write_codec(&self, index_path: &str) -> Result<()> {
+        let path = index_path.to_string() + CODEC_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+        writer.write_str(self.get_name());
+        writer.flush()
+    }
+
+    pub fn load_codec(index_path: &str) -> Result<PostingsCodec> {
+        let path = index_path.to_string() + CODEC_EXTENSION;
+
+        // indexes without a codec predate them, and use gamma codes
+        if !Path::new(&path).exists() {
+            return Ok(PostingsCodec::Gamma);
+        }
+
+        let name = BitsReader::new(&path)?.read_str()?;
+        PostingsCodec::from_name(&name)
+            .ok_or_else(|| Error::corrupted(&format!("unknown postings codec `{name}`")))
+    }
+
+    fn get_block_values(&self, block: &[Posting], prev_doc_id: u32) -> (Vec<u32>, Vec<u32>) {
+        // Elias-Fano takes the ids themselves, from the end of the previous block,
+        // the other codecs take their gaps, frequencies are never zero
+        let mut prev = prev_doc_id;
+        let ids = block
+            .iter()
+            .map(|p| match self {
+                PostingsCodec::EliasFano => p.document_id - prev_doc_id,
+                _ => p.document_id - std::mem::replace(&mut prev, p.document_id),
             })
             .collect();
+        let frequencies = block.iter().map(|p| p.document_frequency - 1).collect();
 
-        let path = input_path.to_string() + POSTINGS_EXTENSION;
-        let reader = BitsReader::new(&path);
+        (ids, frequencies)
+    }
+
+    fn write_block(&self, writer: &mut BitsWriter, block: &[Posting], prev_doc_id: u32) -> u64 {
+        let (ids, frequencies) = self.get_block_values(block, prev_doc_id);
+
+        match self {
+            PostingsCodec::Gamma => ids
+                .iter()
+                .zip(&frequencies)
+                .map(|(id, f)| writer.write_gamma(*id) + writer.write_gamma(f + 1))
+                .sum(),
+            PostingsCodec::BitPacking => {
+                codecs::write_packed(writer, &ids) + codecs::write_packed(writer, &frequencies)
+            }
+            PostingsCodec::PForDelta => {
+                codecs::write_pfor(writer, &ids) + codecs::write_pfor(writer, &frequencies)
+            }
+            PostingsCodec::EliasFano => {
+                codecs::write_elias_fano(writer, &ids) + codecs::write_packed(writer, &frequencies)
+            }
+        }
+    }
+
+    fn get_block_len(&self, block: &[Posting], prev_doc_id: u32) -> u64 {
+        let (ids, frequencies) = self.get_block_values(block, prev_doc_id);
+
+        match self {
+            PostingsCodec::Gamma => ids
+                .iter()
+                .zip(&frequencies)
+                .map(|(id, f)| BitsWriter::get_gamma_len(*id) + BitsWriter::get_gamma_len(f + 1))
+                .sum(),
+            PostingsCodec::BitPacking => {
+                codecs::get_packed_len(&ids) + codecs::get_packed_len(&frequencies)
+            }
+            PostingsCodec::PForDelta => {
+                codecs::get_pfor_len(&ids) + codecs::get_pfor_len(&frequencies)
+            }
+            PostingsCodec::EliasFano => {
+                codecs::get_elias_fano_len(&ids) + codecs::get_packed_len(&frequencies)
+            }
+        }
+    }
+
+    fn read_block(
+        &self,
+        reader: &mut BitsReader,
+        n: usize,
+        prev_doc_id: u32,
+        doc_ids: &mut DocumentIdsList,
+        frequencies: &mut Vec<u32>,
+    ) -> Result<()> {
+        let mut ids = Vec::with_capacity(n);
+        let start = frequencies.len();
+
+        match self {
+            PostingsCodec::Gamma => {
+                // frequencies are interleaved with ids
+                for _ in 0..n {
+                    ids.push(reader.read_gamma()?);
+                    frequencies.push(reader.read_gamma()?.saturating_sub(1));
+                }
+            }
+            PostingsCodec::BitPacking => {
+                codecs::read_packed(reader, n, &mut ids)?;
+                codecs::read_packed(reader, n, frequencies)?;
+            }
+            PostingsCodec::PForDelta => {
+                codecs::read_pfor(reader, n, &mut ids)?;
+                codecs::read_pfor(reader, n, frequencies)?;
+            }
+            PostingsCodec::EliasFano => {
+                codecs::read_elias_fano(reader, n, &mut ids)?;
+                codecs::read_packed(reader, n, frequencies)?;
+            }
+        }
+
+        frequencies[start..]
+            .iter_mut()
+            .for_each(|f| *f = f.saturating_add(1));
+
+        let mut document_id = prev_doc_id;
+        for id in ids {
+            document_id = match self {
+                PostingsCodec::EliasFano => add_gap(prev_doc_id, id)?,
+                _ => add_gap(document_id, id)?,
+            };
+            doc_ids.push(document_id);
+        }
+        Ok(())
+    }
+}
+
+impl PostingsWriter {
+    pub fn new(output_path: &str, codec: PostingsCodec) -> Result<PostingsWriter> {
+        Ok(PostingsWriter {
+            postings_writer: BitsWriter::new(&(output_path.to_string() + POSTINGS_EXTENSION))?,
+            codec,
+            offsets_path: output_path.to_string() + OFFSETS_EXTENSION,
+            offsets: Vec::new(),
+            offset: 0,
+        })
+    }
 
-        Postings { reader, offsets }
+    pub fn write_postings_list(&mut self, postings: &PostingsList, lengths: &[u32]) {
+        self.offsets.push(self.offset);
+        self.offset +=
+            Postings::write_postings_list(&mut self.postings_writer, postings, lengths, self.codec);
     }
 
-    pub fn write_postings(index: &InMemory, output_path: &str) {
-        let postings_path = output_path.to_string() + POSTINGS_EXTENSION;
-        let mut postings_writer = BitsWriter::new(&postings_path);
+    pub fn flush(mut self) -> Result<()> {
+        self.postings_writer.flush()?;
 
-        let offsets_path = output_path.to_string() + OFFSETS_EXTENSION;
-        let mut offsets_writer = BitsWriter::new(&offsets_path);
+        let mut offsets_writer = BitsWriter::new(&self.offsets_path)?;
+        offsets_writer.write_vbyte(self.offsets.len() as u32);
 
-        let mut offset: u64 = 0;
         let mut prev_offset = 0;
+        for offset in self.offsets {
+            offsets_writer.write_gamma((offset - prev_offset) as u32);
+            prev_offset = offset;
+        }
+
+        offsets_writer.flush()
+    }
+}
+
+impl Postings {
+    pub fn load_postings_reader(input_path: &str, codec: PostingsCodec) -> Result<Postings> {
+        let path = input_path.to_string() + OFFSETS_EXTENSION;
+        let mut offsets_reader = BitsReader::new(&path)?;
+
+        let mut offset = 0;
+        let offsets = (0..offsets_reader.read_vbyte()?)
+            .map(|_| {
+                offset += offsets_reader.read_gamma()? as u64;
+                Ok(offset)
+            })
+            .collect::<Result<_>>()?;
+
+        let path = input_path.to_string() + POSTINGS_EXTENSION;
+        let source = BitsReader::map_file(&path)?;
 
-        offsets_writer.write_vbyte(index.term_index_map.len() as u32);
+        Ok(Postings {
+            source,
+            offsets,
+            codec,
+        })
+    }
+
+    pub fn write_postings_list(
+        writer: &mut BitsWriter,
+        postings: &PostingsList,
+        lengths: &[u32],
+        codec: PostingsCodec,
+    ) -> u64 {
+        let mut written = writer.write_vbyte(postings.len() as u32);
 
-        for idx in index.term_index_map.values() {
-            offsets_writer.write_gamma(offset as u32 - prev_offset);
-            prev_offset = offset as u32;
+        // the headers of all blocks come first, so that readers can
+        // find a block, or jump over the positions, without decoding
+        let mut prev_doc_id = 0;
+        for block in postings.chunks(POSTINGS_BLOCK_SIZE) {
+            let (ids_len, positions_len) = Self::get_block_len(block, prev_doc_id, codec);
+            let last_document_id = block[block.len() - 1].document_id;
 
-            let postings = &index.postings[*idx];
+            // documents without a known length count as empty, which still bounds them
+            let max_frequency = block.iter().map(|p| p.document_frequency).max();
+            let min_length = block
+                .iter()
+                .map(|p| {
+                    lengths
+                        .get(p.document_id as usize)
+                        .copied()
+                        .unwrap_or_default()
+                })
+                .min();
 
-            offset += postings_writer.write_vbyte(postings.len() as u32);
+            written += writer.write_gamma(last_document_id - prev_doc_id);
+            written += writer.write_gamma(ids_len as u32);
+            written += writer.write_gamma(positions_len as u32);
+            written += writer.write_gamma(max_frequency.unwrap_or_default());
+            written += writer.write_gamma(min_length.unwrap_or_default());
+            prev_doc_id = last_document_id;
+        }
 
-            let mut prev_doc_id = 0;
-            for entry in postings {
-                offset += postings_writer.write_gamma(entry.document_id - prev_doc_id);
-                offset += postings_writer.write_gamma(entry.document_frequency);
+        let mut prev_doc_id = 0;
+        for block in postings.chunks(POSTINGS_BLOCK_SIZE) {
+            written += codec.write_block(writer, block, prev_doc_id);
+            prev_doc_id = block[block.len() - 1].document_id;
 
+            for entry in block {
                 let mut prev_pos = 0;
-                offset += postings_writer.write_vbyte(entry.positions.len() as u32);
+                written += writer.write_vbyte(entry.positions.len() as u32);
                 for pos in &entry.positions {
-                    offset += postings_writer.write_gamma(*pos - prev_pos);
+                    written += writer.write_gamma(*pos - prev_pos);
                     prev_pos = *pos;
                 }
+            }
+        }
+
+        written
+    }
+
+    fn get_block_len(block: &[Posting], prev_doc_id: u32, codec: PostingsCodec) -> (u64, u64) {
+        let ids_len = codec.get_block_len(block, prev_doc_id);
+        let mut positions_len = 0;
 
-                prev_doc_id = entry.document_id;
+        for entry in block {
+            let mut prev_pos = 0;
+            positions_len += BitsWriter::get_vbyte_len(entry.positions.len() as u32);
+            for pos in &entry.positions {
+                positions_len += BitsWriter::get_gamma_len(*pos - prev_pos);
+                prev_pos = *pos;
             }
         }
 
-        postings_writer.flush();
-        offsets_writer.flush();
+        (ids_len, positions_len)
     }
 
-    pub fn load_postings_list(&mut self, index: usize) -> PostingsList {
-        self.reader.seek(self.offsets[index]);
+    pub fn load_postings_list(&self, index: usize) -> Result<PostingsList> {
+        Self::read_postings_list(&mut self.get_reader(index)?, self.codec)
+    }
 
-        let n = self.reader.read_vbyte();
-        let mut document_id = 0;
-        let documents: Vec<Posting> = (0..n)
-            .map(|_| {
-                let doc_id_delta = self.reader.read_gamma();
-                let document_frequency = self.reader.read_gamma();
+    pub fn read_postings_list(
+        reader: &mut BitsReader,
+        codec: PostingsCodec,
+    ) -> Result<PostingsList> {
+        let n = reader.read_vbyte()? as usize;
+        let headers = Self::read_block_headers(reader, n)?;
+
+        // blocks follow the headers, read one after the other
+        let mut postings = PostingsList::new();
+        let mut doc_ids = DocumentIdsList::new();
+        let mut frequencies = Vec::new();
+        for (i, header) in headers.iter().enumerate() {
+            let prev_doc_id = postings.last().map_or(0, |p| p.document_id);
+            doc_ids.clear();
+            frequencies.clear();
+            codec.read_block(
+                reader,
+                Self::get_block_postings(i, n),
+                prev_doc_id,
+                &mut doc_ids,
+                &mut frequencies,
+            )?;
+
+            // ids beyond the header of their block can only come from damaged postings
+            if doc_ids.last() != Some(&header.last_document_id) {
+                return Err(Error::corrupted("postings block out of range"));
+            }
+
+            let start = postings.len();
+            postings.extend(doc_ids.iter().zip(&frequencies).map(|(id, f)| Posting {
+                document_id: *id,
+                document_frequency: *f,
+                positions: Vec::new(),
+            }));
+
+            for posting in &mut postings[start..] {
+                posting.positions = reader.read_vbyte_gamma_gap_vector()?;
+            }
+        }
+
+        Ok(postings)
+    }
+
+    pub fn get_postings_len(&self, index: usize) -> Result<usize> {
+        Ok(self.get_reader(index)?.read_vbyte()? as usize)
+    }
+
+    pub fn load_doc_ids_list(&self, index: usize) -> Result<DocumentIdsList> {
+        let mut reader = self.get_reader(index)?;
+        let n = reader.read_vbyte()? as usize;
+        let headers = Self::read_block_headers(&mut reader, n)?;
+
+        // positions are jumped over, only the ids of each block are decoded
+        let mut doc_ids = DocumentIdsList::with_capacity(n);
+        let mut block_offset = reader.get_position();
+        let mut prev_doc_id = 0;
+        for (i, header) in headers.iter().enumerate() {
+            reader.seek(block_offset)?;
+            self.codec.read_block(
+                &mut reader,
+                Self::get_block_postings(i, n),
+                prev_doc_id,
+                &mut doc_ids,
+                &mut Vec::new(),
+            )?;
+
+            block_offset += header.ids_len + header.positions_len;
+            prev_doc_id = header.last_document_id;
+        }
+
+        Ok(doc_ids)
+    }
+
+    pub fn filter_doc_ids(&self, index: usize, doc_ids: &[u32]) -> Result<DocumentIdsList> {
+        let mut reader = self.get_reader(index)?;
+        let n = reader.read_vbyte()? as usize;
+        let headers = Self::read_block_headers(&mut reader, n)?;
+
+        let mut result = DocumentIdsList::new();
+        let mut block_offset = reader.get_position();
+        let mut block = 0;
+        let mut prev_doc_id = 0;
+        let mut decoded: Option<(usize, DocumentIdsList)> = None;
+
+        for &doc_id in doc_ids {
+            // blocks ending before the document are skipped without decoding them
+            while block < headers.len() && headers[block].last_document_id < doc_id {
+                block_offset += headers[block].ids_len + headers[block].positions_len;
+                prev_doc_id = headers[block].last_document_id;
+                block += 1;
+            }
+            if block == headers.len() {
+                break;
+            }
 
-                document_id += doc_id_delta;
+            if !matches!(&decoded, Some((b, _)) if *b == block) {
+                let mut block_doc_ids = DocumentIdsList::new();
+                reader.seek(block_offset)?;
+                self.codec.read_block(
+                    &mut reader,
+                    Self::get_block_postings(block, n),
+                    prev_doc_id,
+                    &mut block_doc_ids,
+                    &mut Vec::new(),
+                )?;
+                decoded = Some((block, block_doc_ids));
+            }
 
-                Posting {
-                    document_id,
-                    document_frequency,
-                    positions: self.reader.read_vbyte_gamma_gap_vector(),
+            if let Some((_, block_doc_ids)) = &decoded {
+                if block_doc_ids.binary_search(&doc_id).is_ok() {
+                    result.push(doc_id);
                 }
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn get_cursor(&self, index: usize) -> Result<PostingsCursor> {
+        let mut reader = self.get_reader(index)?;
+        let n = reader.read_vbyte()? as usize;
+        let headers = Self::read_block_headers(&mut reader, n)?;
+
+        let mut block_offset = reader.get_position();
+        let block_offsets = headers
+            .iter()
+            .map(|h| {
+                let offset = block_offset;
+                block_offset += h.ids_len + h.positions_len;
+                offset
             })
             .collect();
 
-        documents
+        let mut cursor = PostingsCursor {
+            reader,
+            headers,
+            block_offsets,
+            codec: self.codec,
+            n,
+            block: 0,
+            index: 0,
+            doc_ids: DocumentIdsList::new(),
+            frequencies: Vec::new(),
+            positions: None,
+        };
+        cursor.load_block(0)?;
+        Ok(cursor)
     }
 
-    pub fn load_doc_ids_list(&mut self, index: usize) -> DocumentIdsList {
-        self.load_postings_list(index)
-            .iter()
-            .map(|e| e.document_id)
+    fn get_reader(&self, index: usize) -> Result<BitsReader> {
+        let offset = self
+            .offsets
+            .get(index)
+            .ok_or_else(|| Error::corrupted("postings list out of range"))?;
+        BitsReader::from_source(self.source.clone(), *offset)
+    }
+
+    fn get_block_postings(block: usize, n: usize) -> usize {
+        min(POSTINGS_BLOCK_SIZE, n - block * POSTINGS_BLOCK_SIZE)
+    }
+
+    fn read_block_headers(reader: &mut BitsReader, n: usize) -> Result<Vec<BlockHeader>> {
+        let mut last_document_id = 0;
+        (0..n.div_ceil(POSTINGS_BLOCK_SIZE))
+            .map(|_| {
+                last_document_id = add_gap(last_document_id, reader.read_gamma()?)?;
+                Ok(BlockHeader {
+                    last_document_id,
+                    ids_len: reader.read_gamma()? as u64,
+                    positions_len: reader.read_gamma()? as u64,
+                    max_frequency: reader.read_gamma()?,
+                    min_length: reader.read_gamma()?,
+                })
+            })
             .collect()
     }
 
@@ -175,9 +600,155 @@ impl Postings {
         result
     }
 
+    pub fn union_operator(p1: PostingsList, p2: PostingsList) -> PostingsList {
+        // documents in both lists keep the positions and frequency of both
+        let mut result = Vec::with_capacity(p1.len() + p2.len());
+
+        let mut iter1 = p1.into_iter();
+        let mut iter2 = p2.into_iter();
+
+        let (mut e1, mut e2) = (iter1.next(), iter2.next());
+
+        while let (Some(v1), Some(v2)) = (&e1, &e2) {
+            match v1.document_id.cmp(&v2.document_id) {
+                Equal => {
+                    let mut positions = v1.positions.clone();
+                    positions.extend(&v2.positions);
+                    positions.sort_unstable();
+                    result.push(Posting {
+                        document_id: v1.document_id,
+                        document_frequency: v1.document_frequency + v2.document_frequency,
+                        positions,
+                    });
+                    e1 = iter1.next();
+                    e2 = iter2.next();
+                }
+                Less => {
+                    result.push(e1.take().unwrap());
+                    e1 = iter1.next();
+                }
+                Greater => {
+                    result.push(e2.take().unwrap());
+                    e2 = iter2.next();
+                }
+            }
+        }
+
+        result.extend(e1);
+        result.extend(e2);
+        result.extend(iter1);
+        result.extend(iter2);
+
+        result
+    }
+
+    pub fn phrase_operator(lists: Vec<(u32, PostingsList)>) -> PostingsList {
+        // shift every position back by the term offset inside the phrase,
+        // so that a match is a position shared by all the lists
+        let mut lists = lists
+            .into_iter()
+            .map(|(offset, list)| Self::shift_positions(list, offset));
+
+        let Some(first) = lists.next() else {
+            return PostingsList::default();
+        };
+
+        lists.fold(first, |acc, list| {
+            Self::positional_and_operator(acc, list, Self::intersect_positions)
+        })
+    }
+
+    pub fn near_operator(p1: PostingsList, p2: PostingsList, k: u32) -> PostingsList {
+        Self::positional_and_operator(p1, p2, |pos1, pos2| {
+            let mut result: Vec<u32> = Self::positions_within(pos1, pos2, k)
+                .chain(Self::positions_within(pos2, pos1, k))
+                .collect();
+            result.sort_unstable();
+            result.dedup();
+            result
+        })
+    }
+
+    fn positions_within<'a>(
+        positions: &'a [u32],
+        others: &'a [u32],
+        k: u32,
+    ) -> impl Iterator<Item = u32> + 'a {
+        // positions having at least one of the others at distance at most k
+        positions.iter().copied().filter(move |p| {
+            let i = others.partition_point(|o| *o < p.saturating_sub(k));
+            i < others.len() && others[i] <= p.saturating_add(k)
+        })
+    }
+
+    fn positional_and_operator<F>(p1: PostingsList, p2: PostingsList, matches: F) -> PostingsList
+    where
+        F: Fn(&[u32], &[u32]) -> Vec<u32>,
+    {
+        let mut result = Vec::new();
+
+        let mut iter1 = p1.into_iter();
+        let mut iter2 = p2.into_iter();
+
+        let (mut e1, mut e2) = (iter1.next(), iter2.next());
+
+        while let (Some(v1), Some(v2)) = (&e1, &e2) {
+            match v1.document_id.cmp(&v2.document_id) {
+                Equal => {
+                    let positions = matches(&v1.positions, &v2.positions);
+                    if !positions.is_empty() {
+                        result.push(Posting {
+                            document_id: v1.document_id,
+                            document_frequency: positions.len() as u32,
+                            positions,
+                        });
+                    }
+                    e1 = iter1.next();
+                    e2 = iter2.next();
+                }
+                Less => e1 = iter1.next(),
+                Greater => e2 = iter2.next(),
+            }
+        }
+
+        result
+    }
+
+    fn shift_positions(list: PostingsList, offset: u32) -> PostingsList {
+        list.into_iter()
+            .filter_map(|mut p| {
+                p.positions = p
+                    .positions
+                    .iter()
+                    .filter_map(|pos| pos.checked_sub(offset))
+                    .collect();
+                (!p.positions.is_empty()).then_some(p)
+            })
+            .collect()
+    }
+
+    fn intersect_positions(p1: &[u32], p2: &[u32]) -> Vec<u32> {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < p1.len() && j < p2.len() {
+            match p1[i].cmp(&p2[j]) {
+                Equal => {
+                    result.push(p1[i]);
+                    i += 1;
+                    j += 1;
+                }
+                Less => i += 1,
+                Greater => j += 1,
+            }
+        }
+
+        result
+    }
+
     pub fn not_operator(mut p: DocumentIdsList, n: u32) -> DocumentIdsList {
         if p.is_empty() {
-            return (1..=n).collect();
+            return (0..n).collect();
         }
 
         let mut result = Vec::with_capacity((n - p.len() as u32) as usize);
@@ -200,9 +771,219 @@ impl Postings {
     }
 }
 
+impl PostingsCursor {
+    // none once the list is over
+    pub fn get_document_id(&self) -> Option<u32> {
+        self.doc_ids.get(self.index).copied()
+    }
+
+    pub fn get_frequency(&self) -> u32 {
+        self.frequencies[self.index]
+    }
+
+    pub fn get_positions(&mut self) -> Result<&[u32]> {
+        if self.positions.is_none() {
+            let header = &self.headers[self.block];
+            self.reader
+                .seek(self.block_offsets[self.block] + header.ids_len)?;
+            self.positions = Some(
+                (0..self.doc_ids.len())
+                    .map(|_| self.reader.read_vbyte_gamma_gap_vector())
+                    .collect::<Result<_>>()?,
+            );
+        }
+
+        Ok(self.positions.as_ref().map_or(&[], |p| &p[self.index]))
+    }
+
+    pub fn get_block_headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    // header of the block a document would be in, none past the end of the list
+    pub fn get_block_header(&self, document_id: u32) -> Option<&BlockHeader> {
+        self.headers.get(self.get_block(document_id))
+    }
+
+    // moves to the first document not before the given one
+    pub fn seek_document(&mut self, document_id: u32) -> Result<()> {
+        let block = self.get_block(document_id);
+        if block != self.block {
+            self.load_block(block)?;
+        }
+
+        self.index += self.doc_ids[self.index..].partition_point(|id| *id < document_id);
+        Ok(())
+    }
+
+    fn get_block(&self, document_id: u32) -> usize {
+        // blocks ending before the document are skipped on their headers alone
+        self.block
+            + self.headers[self.block..].partition_point(|h| h.last_document_id < document_id)
+    }
+
+    fn load_block(&mut self, block: usize) -> Result<()> {
+        self.block = block;
+        self.index = 0;
+        self.doc_ids.clear();
+        self.frequencies.clear();
+        self.positions = None;
+
+        if block == self.headers.len() {
+            return Ok(());
+        }
+
+        self.reader.seek(self.block_offsets[block])?;
+        let prev_doc_id = match block {
+            0 => 0,
+            _ => self.headers[block - 1].last_document_id,
+        };
+        self.codec.read_block(
+            &mut self.reader,
+            Postings::get_block_postings(block, self.n),
+            prev_doc_id,
+            &mut self.doc_ids,
+            &mut self.frequencies,
+        )
+    }
+}
+
+fn add_gap(document_id: u32, gap: u32) -> Result<u32> {
+    document_id
+        .checked_add(gap)
+        .ok_or_else(|| Error::corrupted("document id out of range"))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::engine::postings::Postings;
+    use crate::engine::postings::{
+        Posting, Postings, PostingsCodec, PostingsList, PostingsWriter, CODECS, POSTINGS_BLOCK_SIZE,
+    };
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    fn build_postings_list(entries: &[(u32, &[u32])]) -> PostingsList {
+        entries
+            .iter()
+            .map(|(document_id, positions)| Posting {
+                document_id: *document_id,
+                document_frequency: positions.len() as u32,
+                positions: positions.to_vec(),
+            })
+            .collect()
+    }
+
+    fn doc_ids(list: &PostingsList) -> Vec<u32> {
+        list.iter().map(|p| p.document_id).collect()
+    }
+
+    #[test]
+    fn test_postings_blocks() {
+        // every codec reads back the same postings, blocks and headers
+        for (codec, name) in CODECS {
+            check_postings_blocks(codec, &format!("postings_blocks_{name}"));
+        }
+    }
+
+    fn check_postings_blocks(codec: PostingsCodec, name: &str) {
+        let path = create_temporary_file_path(name);
+
+        // lists spanning several blocks, and an empty one, next to each other
+        let long: PostingsList = (0..POSTINGS_BLOCK_SIZE as u32 * 3 + 5)
+            .map(|i| Posting {
+                document_id: i * 3,
+                document_frequency: i % 4 + 1,
+                positions: (0..i % 4 + 1).map(|p| p * 7 + i).collect(),
+            })
+            .collect();
+        let short = build_postings_list(&[(2, &[0, 4]), (9, &[1])]);
+
+        let mut writer = PostingsWriter::new(&path, codec).unwrap();
+        let lengths: Vec<u32> = (0..2000).map(|i| i % 50 + 10).collect();
+        writer.write_postings_list(&long, &lengths);
+        writer.write_postings_list(&PostingsList::new(), &lengths);
+        writer.write_postings_list(&short, &[]);
+        writer.flush().unwrap();
+
+        let postings = Postings::load_postings_reader(&path, codec).unwrap();
+
+        let loaded = postings.load_postings_list(0).unwrap();
+        assert_eq!(doc_ids(&loaded), doc_ids(&long));
+        assert!(loaded
+            .iter()
+            .zip(&long)
+            .all(|(p1, p2)| p1.positions == p2.positions
+                && p1.document_frequency == p2.document_frequency));
+        assert_eq!(postings.get_postings_len(0).unwrap(), long.len());
+        assert_eq!(postings.load_doc_ids_list(0).unwrap(), doc_ids(&long));
+
+        assert!(postings.load_postings_list(1).unwrap().is_empty());
+        assert!(postings.load_doc_ids_list(1).unwrap().is_empty());
+        assert_eq!(postings.load_doc_ids_list(2).unwrap(), [2, 9]);
+        assert_eq!(postings.load_postings_list(2).unwrap()[0].positions, [0, 4]);
+
+        // ids are looked up across blocks, the ones past the end are not found
+        let candidates = [0, 1, 3, 383, 384, 385, 387, 1000, 1170];
+        assert_eq!(
+            postings.filter_doc_ids(0, &candidates).unwrap(),
+            [0, 3, 384, 387]
+        );
+        assert!(postings.filter_doc_ids(1, &candidates).unwrap().is_empty());
+        assert_eq!(
+            postings.filter_doc_ids(2, &candidates).unwrap(),
+            Vec::<u32>::new()
+        );
+
+        // cursors stop on the first document not before the sought one
+        let mut cursor = postings.get_cursor(0).unwrap();
+        assert_eq!(cursor.get_document_id(), Some(0));
+        cursor.seek_document(700).unwrap();
+        assert_eq!(cursor.get_document_id(), Some(702));
+        assert_eq!(cursor.get_frequency(), long[234].document_frequency);
+        assert_eq!(cursor.get_positions().unwrap(), long[234].positions);
+        cursor.seek_document(10).unwrap();
+        assert_eq!(cursor.get_document_id(), Some(702));
+
+        // headers bound the frequencies and lengths of their block
+        let header = cursor.get_block_header(702).unwrap();
+        assert_eq!(header.last_document_id, 765);
+        assert_eq!((header.max_frequency, header.min_length), (4, 10));
+        assert_eq!(cursor.get_block_headers().len(), 4);
+
+        cursor.seek_document(1164).unwrap();
+        assert_eq!(cursor.get_document_id(), Some(1164));
+        cursor.seek_document(1165).unwrap();
+        assert_eq!(cursor.get_document_id(), None);
+        assert!(cursor.get_block_header(1165).is_none());
+
+        let mut cursor = postings.get_cursor(2).unwrap();
+        assert_eq!(cursor.get_block_header(0).unwrap().min_length, 0);
+        cursor.seek_document(3).unwrap();
+        assert_eq!(cursor.get_positions().unwrap(), [1]);
+        assert_eq!(postings.get_cursor(1).unwrap().get_document_id(), None);
+    }
+
+    #[test]
+    fn test_write_and_load_codec() {
+        let index_path = create_temporary_file_path("postings_codec");
+
+        // indexes without a codec file use gamma codes
+        assert_eq!(
+            PostingsCodec::load_codec(&index_path).unwrap(),
+            PostingsCodec::Gamma
+        );
+        PostingsCodec::EliasFano.write_codec(&index_path).unwrap();
+        assert_eq!(
+            PostingsCodec::load_codec(&index_path).unwrap(),
+            PostingsCodec::EliasFano
+        );
+
+        assert_eq!(
+            PostingsCodec::from_name("pfor"),
+            Some(PostingsCodec::PForDelta)
+        );
+        assert_eq!(PostingsCodec::BitPacking.get_name(), "bitpack");
+        assert_eq!(PostingsCodec::from_name("zstd"), None);
+    }
 
     #[test]
     fn test_or_operator() {
@@ -213,7 +994,7 @@ mod tests {
         assert_eq!(result, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
 
         let result_empty = Postings::or_operator(vec![], vec![]);
-        assert_eq!(result_empty, vec![]);
+        assert_eq!(result_empty, Vec::<u32>::new());
     }
 
     #[test]
@@ -225,10 +1006,10 @@ mod tests {
         assert_eq!(result, vec![3, 7, 10]);
 
         let result_empty = Postings::and_operator(vec![1, 2, 3], vec![]);
-        assert_eq!(result_empty, vec![]);
+        assert_eq!(result_empty, Vec::<u32>::new());
 
         let result_both_empty = Postings::and_operator(vec![], vec![]);
-        assert_eq!(result_both_empty, vec![]);
+        assert_eq!(result_both_empty, Vec::<u32>::new());
     }
 
     #[test]
@@ -240,9 +1021,57 @@ mod tests {
         assert_eq!(result, vec![0, 1, 3, 5, 7, 9]);
 
         let result_empty = Postings::not_operator(vec![], n);
-        assert_eq!(result_empty, (1..=n).collect::<Vec<u32>>());
+        assert_eq!(result_empty, (0..n).collect::<Vec<u32>>());
 
         let result_full = Postings::not_operator(vec![0, 1, 2], 3);
-        assert_eq!(result_full, []);
+        assert!(result_full.is_empty());
+    }
+
+    #[test]
+    fn test_union_operator() {
+        let p1 = build_postings_list(&[(1, &[0, 5]), (3, &[2])]);
+        let p2 = build_postings_list(&[(1, &[3]), (2, &[1]), (4, &[6])]);
+
+        let result = Postings::union_operator(p1, p2);
+        assert_eq!(doc_ids(&result), [1, 2, 3, 4]);
+        assert_eq!(result[0].positions, [0, 3, 5]);
+        assert_eq!(result[0].document_frequency, 3);
+
+        assert!(Postings::union_operator(vec![], vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_phrase_operator() {
+        let p1 = build_postings_list(&[(1, &[0, 5]), (2, &[3]), (4, &[7])]);
+        let p2 = build_postings_list(&[(1, &[6]), (2, &[1]), (3, &[0]), (4, &[8, 9])]);
+        let p3 = build_postings_list(&[(1, &[7]), (4, &[10])]);
+
+        let result = Postings::phrase_operator(vec![(0, p1.clone()), (1, p2.clone())]);
+        assert_eq!(doc_ids(&result), [1, 4]);
+        assert_eq!(result[0].positions, [5]);
+        assert_eq!(result[1].positions, [7]);
+
+        let result = Postings::phrase_operator(vec![(0, p1), (1, p2), (2, p3.clone())]);
+        assert_eq!(doc_ids(&result), [1]);
+
+        // gaps left by terms missing from the vocabulary are preserved
+        let p1 = build_postings_list(&[(1, &[0, 5]), (2, &[3])]);
+        let result = Postings::phrase_operator(vec![(0, p1), (2, p3)]);
+        assert_eq!(doc_ids(&result), [1]);
+
+        assert!(Postings::phrase_operator(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_near_operator() {
+        let p1 = build_postings_list(&[(1, &[0, 10]), (2, &[3]), (3, &[20])]);
+        let p2 = build_postings_list(&[(1, &[12]), (2, &[9]), (3, &[17])]);
+
+        let result = Postings::near_operator(p1.clone(), p2.clone(), 2);
+        assert_eq!(doc_ids(&result), [1]);
+        assert_eq!(result[0].positions, [10, 12]);
+
+        let result = Postings::near_operator(p1, p2, 3);
+        assert_eq!(doc_ids(&result), [1, 3]);
     }
 }
@@ -0,0 +1,697 @@
+use crate::{Error, Result};
+use std::fmt;
+
+// fuzzy terms are expanded to every term within this number of edits at most
+pub const MAX_FUZZY_DISTANCE: u32 = 2;
+
+// a parsed boolean query, operands hold the raw text of the query,
+// they are analyzed when the query is run
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryNode {
+    Term {
+        field: Option<usize>,
+        text: String,
+    },
+    Phrase {
+        field: Option<usize>,
+        text: String,
+    },
+    // `*` matches any sequence of chars and `?` a single one
+    Wildcard {
+        field: Option<usize>,
+        pattern: String,
+    },
+    // the terms within a number of edits of the text
+    Fuzzy {
+        field: Option<usize>,
+        text: String,
+        distance: u32,
+    },
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+    Near {
+        left: Box<QueryNode>,
+        right: Box<QueryNode>,
+        distance: u32,
+    },
+}
+
+// positions are counted in chars from the start of the query
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    pub position: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    Near(u32),
+    Minus,
+    Plus,
+    Word(String),
+    Phrase(Option<String>, String),
+}
+
+#[derive(Debug)]
+struct QueryToken {
+    kind: TokenKind,
+    position: usize,
+}
+
+struct QueryParser<'a> {
+    tokens: Vec<QueryToken>,
+    current: usize,
+    end: usize,
+    get_field_id: &'a dyn Fn(&str) -> Option<usize>,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::LeftParen => write!(f, "("),
+            TokenKind::RightParen => write!(f, ")"),
+            TokenKind::And => write!(f, "AND"),
+            TokenKind::Or => write!(f, "OR"),
+            TokenKind::Not => write!(f, "NOT"),
+            TokenKind::Near(k) => write!(f, "NEAR/{k}"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Word(w) => write!(f, "{w}"),
+            TokenKind::Phrase(_, p) => write!(f, "\"{p}\""),
+        }
+    }
+}
+
+impl QueryNode {
+    // operators from the weakest to the strongest: OR, AND (also implicit
+    // between operands), NOT, NEAR/k, then the `-` and `+` prefixes
+    pub fn parse(query: &str, get_field_id: &dyn Fn(&str) -> Option<usize>) -> Result<QueryNode> {
+        let tokens = tokenize(query, false)?;
+        if tokens.is_empty() {
+            return Err(query_error("empty query", 0));
+        }
+
+        let mut parser = QueryParser {
+            tokens,
+            current: 0,
+            end: query.chars().count(),
+            get_field_id,
+        };
+        let node = parser.parse_or()?;
+
+        // operands are always consumed, only a closing paren can be left
+        match parser.peek() {
+            Some(token) => Err(query_error("unmatched )", token.position)),
+            None => Ok(node),
+        }
+    }
+
+    // free queries are made of terms and phrases alone, operators are plain
+    // words there, and nothing is rejected: a phrase left open runs to the end
+    pub fn parse_free(
+        query: &str,
+        get_field_id: &dyn Fn(&str) -> Option<usize>,
+    ) -> Result<Vec<QueryNode>> {
+        let mut nodes = Vec::new();
+
+        for token in tokenize(query, true)? {
+            match token.kind {
+                TokenKind::Word(word) => {
+                    let (field, text) = word
+                        .split_once(':')
+                        .and_then(|(name, rest)| get_field_id(name).map(|f| (Some(f), rest)))
+                        .unwrap_or((None, word.as_str()));
+                    nodes.push(QueryNode::Term {
+                        field,
+                        text: text.to_string(),
+                    });
+                }
+                TokenKind::Phrase(name, text) => {
+                    // unknown field names are left to the analyzer, as words
+                    let field = name.as_deref().and_then(get_field_id);
+                    if let (Some(name), None) = (name, field) {
+                        nodes.push(QueryNode::Term {
+                            field: None,
+                            text: name,
+                        });
+                    }
+                    nodes.push(QueryNode::Phrase { field, text });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(nodes)
+    }
+}
+
+impl QueryParser<'_> {
+    fn peek(&self) -> Option<&QueryToken> {
+        self.tokens.get(self.current)
+    }
+
+    fn next_if(&mut self, kind: &TokenKind) -> bool {
+        let found = self.peek().is_some_and(|t| t.kind == *kind);
+        if found {
+            self.current += 1;
+        }
+        found
+    }
+
+    fn starts_operand(&self) -> bool {
+        self.peek().is_some_and(|t| {
+            matches!(
+                t.kind,
+                TokenKind::LeftParen
+                    | TokenKind::Not
+                    | TokenKind::Minus
+                    | TokenKind::Plus
+                    | TokenKind::Word(_)
+                    | TokenKind::Phrase(..)
+            )
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<QueryNode> {
+        let mut nodes = vec![self.parse_and()?];
+        while self.next_if(&TokenKind::Or) {
+            nodes.push(self.parse_and()?);
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => QueryNode::Or(nodes),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<QueryNode> {
+        let mut nodes = vec![self.parse_not()?];
+        while self.next_if(&TokenKind::And) || self.starts_operand() {
+            nodes.push(self.parse_not()?);
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => QueryNode::And(nodes),
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<QueryNode> {
+        if self.next_if(&TokenKind::Not) {
+            return Ok(QueryNode::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_near()
+    }
+
+    fn parse_near(&mut self) -> Result<QueryNode> {
+        let position = self.peek().map_or(self.end, |t| t.position);
+        let mut left = self.parse_unary()?;
+        while let Some(TokenKind::Near(distance)) = self.peek().map(|t| &t.kind) {
+            let distance = *distance;
+            self.current += 1;
+            check_positional(&left, position)?;
+
+            let position = self.peek().map_or(self.end, |t| t.position);
+            let right = self.parse_unary()?;
+            check_positional(&right, position)?;
+
+            left = QueryNode::Near {
+                left: Box::new(left),
+                right: Box::new(right),
+                distance,
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryNode> {
+        let Some(token) = self.tokens.get(self.current) else {
+            return Err(query_error("expected an operand", self.end));
+        };
+        let position = token.position;
+        self.current += 1;
+
+        match &token.kind {
+            TokenKind::Minus => Ok(QueryNode::Not(Box::new(self.parse_unary()?))),
+            // operands are already required, `+` is accepted for familiarity
+            TokenKind::Plus => self.parse_unary(),
+            TokenKind::LeftParen => {
+                let node = self.parse_or()?;
+                if !self.next_if(&TokenKind::RightParen) {
+                    return Err(query_error("unmatched (", position));
+                }
+                Ok(node)
+            }
+            TokenKind::Word(word) => {
+                // only known field names are prefixes, anything else is left to the analyzer
+                let field = word
+                    .split_once(':')
+                    .and_then(|(name, rest)| (self.get_field_id)(name).map(|f| (f, rest)));
+                let (field, text) = match field {
+                    Some((_, "")) => {
+                        return Err(query_error(
+                            format!("expected a term or a phrase after {word}"),
+                            position,
+                        ))
+                    }
+                    Some((field, rest)) => (Some(field), rest),
+                    None => (None, word.as_str()),
+                };
+
+                if let Some((text, distance)) = split_fuzzy(text) {
+                    if distance > MAX_FUZZY_DISTANCE {
+                        return Err(query_error(
+                            format!("fuzzy distance in {word} is above {MAX_FUZZY_DISTANCE}"),
+                            position,
+                        ));
+                    }
+                    return Ok(QueryNode::Fuzzy {
+                        field,
+                        text: text.to_string(),
+                        distance,
+                    });
+                }
+
+                if !is_wildcard(text) {
+                    return Ok(QueryNode::Term {
+                        field,
+                        text: text.to_string(),
+                    });
+                }
+                match text.chars().all(|c| matches!(c, '*' | '?')) {
+                    true => Err(query_error(
+                        format!("wildcard {word} has no chars to match"),
+                        position,
+                    )),
+                    false => Ok(QueryNode::Wildcard {
+                        field,
+                        pattern: text.to_string(),
+                    }),
+                }
+            }
+            TokenKind::Phrase(name, text) => {
+                let field =
+                    match name {
+                        Some(name) => Some((self.get_field_id)(name).ok_or_else(|| {
+                            query_error(format!("unknown field {name}"), position)
+                        })?),
+                        None => None,
+                    };
+                Ok(QueryNode::Phrase {
+                    field,
+                    text: text.clone(),
+                })
+            }
+            kind => Err(query_error(
+                format!("expected an operand, found {kind}"),
+                position,
+            )),
+        }
+    }
+}
+
+pub fn is_wildcard(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+// free queries are natural language, where `?` ends questions, so only `*` makes a wildcard
+pub fn is_free_wildcard(text: &str) -> bool {
+    text.contains('*')
+}
+
+// `term~k` is within k edits of the term, and `term~` within the largest distance
+pub fn split_fuzzy(text: &str) -> Option<(&str, u32)> {
+    let (text, distance) = text.rsplit_once('~')?;
+    if text.is_empty() || !distance.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    match distance {
+        "" => Some((text, MAX_FUZZY_DISTANCE)),
+        d => Some((text, d.parse().unwrap_or(u32::MAX))),
+    }
+}
+
+// positions are lost after AND, OR and NOT, so nothing can be near them
+fn check_positional(node: &QueryNode, position: usize) -> Result<()> {
+    match node {
+        QueryNode::And(_) | QueryNode::Or(_) | QueryNode::Not(_) => Err(query_error(
+            "NEAR/k only applies to terms, phrases and NEAR/k",
+            position,
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn query_error(message: impl Into<String>, position: usize) -> Error {
+    Error::InvalidQuery(QueryError {
+        message: message.into(),
+        position,
+    })
+}
+
+// free queries only get words and phrases, with parens and prefixes as separators
+fn tokenize(query: &str, free: bool) -> Result<Vec<QueryToken>> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let read_phrase = |start: usize| -> Result<(String, usize)> {
+        // `start` is the opening quote, the phrase ends at the closing one
+        match chars[start + 1..].iter().position(|c| *c == '"') {
+            Some(len) => Ok((
+                chars[start + 1..start + 1 + len].iter().collect(),
+                start + len + 2,
+            )),
+            None if free => Ok((chars[start + 1..].iter().collect(), chars.len())),
+            None => Err(query_error("unterminated phrase", start)),
+        }
+    };
+
+    while i < chars.len() {
+        let position = i;
+        let kind = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LeftParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RightParen
+            }
+            '-' => {
+                i += 1;
+                TokenKind::Minus
+            }
+            '+' => {
+                i += 1;
+                TokenKind::Plus
+            }
+            '"' => {
+                let (phrase, next) = read_phrase(i)?;
+                i = next;
+                TokenKind::Phrase(None, phrase)
+            }
+            _ => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|c| c.is_whitespace() || matches!(c, '(' | ')' | '"'))
+                    .unwrap_or(chars.len() - i);
+                let word: String = chars[i..i + len].iter().collect();
+                i += len;
+
+                match word.as_str() {
+                    "AND" if !free => TokenKind::And,
+                    "OR" if !free => TokenKind::Or,
+                    "NOT" if !free => TokenKind::Not,
+                    // a field prefix right before a phrase is attached to it, as in `title:"a b"`
+                    w if w.ends_with(':') && chars.get(i) == Some(&'"') => {
+                        let (phrase, next) = read_phrase(i)?;
+                        i = next;
+                        TokenKind::Phrase(Some(w.trim_end_matches(':').to_string()), phrase)
+                    }
+                    w if free => TokenKind::Word(w.to_string()),
+                    w => match w.strip_prefix("NEAR/") {
+                        Some(k) => TokenKind::Near(k.parse().map_err(|_| {
+                            query_error(format!("invalid distance in {w}"), position)
+                        })?),
+                        None => TokenKind::Word(word),
+                    },
+                }
+            }
+        };
+
+        tokens.push(QueryToken { kind, position });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_field_id(name: &str) -> Option<usize> {
+        ["title", "body"].iter().position(|f| *f == name)
+    }
+
+    fn parse(query: &str) -> QueryNode {
+        QueryNode::parse(query, &get_field_id).unwrap()
+    }
+
+    fn parse_error(query: &str) -> QueryError {
+        match QueryNode::parse(query, &get_field_id) {
+            Err(Error::InvalidQuery(err)) => err,
+            _ => panic!("{query} should not be parsed"),
+        }
+    }
+
+    fn term(text: &str) -> QueryNode {
+        QueryNode::Term {
+            field: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn not(node: QueryNode) -> QueryNode {
+        QueryNode::Not(Box::new(node))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("a AND (b OR NOT c)"),
+            QueryNode::And(vec![
+                term("a"),
+                QueryNode::Or(vec![term("b"), not(term("c"))])
+            ])
+        );
+
+        assert_eq!(
+            parse("a AND b OR NOT c"),
+            QueryNode::Or(vec![
+                QueryNode::And(vec![term("a"), term("b")]),
+                not(term("c"))
+            ])
+        );
+
+        assert_eq!(
+            parse("a AND b AND c OR d OR e"),
+            QueryNode::Or(vec![
+                QueryNode::And(vec![term("a"), term("b"), term("c")]),
+                term("d"),
+                term("e")
+            ])
+        );
+
+        assert_eq!(
+            parse("a NEAR/3 b AND NOT c NEAR/1 d"),
+            QueryNode::And(vec![
+                QueryNode::Near {
+                    left: Box::new(term("a")),
+                    right: Box::new(term("b")),
+                    distance: 3
+                },
+                not(QueryNode::Near {
+                    left: Box::new(term("c")),
+                    right: Box::new(term("d")),
+                    distance: 1
+                })
+            ])
+        );
+    }
+
+    #[test]
+    fn test_implicit_and_and_shorthands() {
+        assert_eq!(parse("a b"), parse("a AND b"));
+        assert_eq!(parse("a b OR c"), parse("(a AND b) OR c"));
+        assert_eq!(parse("a -b +c"), parse("a AND NOT b AND c"));
+        assert_eq!(parse("-(a OR b)"), parse("NOT (a OR b)"));
+
+        // dashes inside words are left to the analyzer
+        assert_eq!(parse("e-mail"), term("e-mail"));
+    }
+
+    #[test]
+    fn test_phrases_and_fields() {
+        assert_eq!(
+            parse("\"a b\" (c OR \"d e\")"),
+            QueryNode::And(vec![
+                QueryNode::Phrase {
+                    field: None,
+                    text: "a b".to_string()
+                },
+                QueryNode::Or(vec![
+                    term("c"),
+                    QueryNode::Phrase {
+                        field: None,
+                        text: "d e".to_string()
+                    }
+                ])
+            ])
+        );
+
+        assert_eq!(
+            parse("title:\"a b\" AND -body:c"),
+            QueryNode::And(vec![
+                QueryNode::Phrase {
+                    field: Some(0),
+                    text: "a b".to_string()
+                },
+                not(QueryNode::Term {
+                    field: Some(1),
+                    text: "c".to_string()
+                })
+            ])
+        );
+
+        // unknown prefixes are part of the term
+        assert_eq!(parse("author:a"), term("author:a"));
+    }
+
+    #[test]
+    fn test_wildcards_and_fuzzy_terms() {
+        assert_eq!(
+            parse("comput* OR title:c?t"),
+            QueryNode::Or(vec![
+                QueryNode::Wildcard {
+                    field: None,
+                    pattern: "comput*".to_string()
+                },
+                QueryNode::Wildcard {
+                    field: Some(0),
+                    pattern: "c?t".to_string()
+                }
+            ])
+        );
+        assert_eq!(
+            parse("-*ization"),
+            not(QueryNode::Wildcard {
+                field: None,
+                pattern: "*ization".to_string()
+            })
+        );
+
+        assert_eq!(
+            parse("colr~1 body:colour~"),
+            QueryNode::And(vec![
+                QueryNode::Fuzzy {
+                    field: None,
+                    text: "colr".to_string(),
+                    distance: 1
+                },
+                QueryNode::Fuzzy {
+                    field: Some(1),
+                    text: "colour".to_string(),
+                    distance: 2
+                }
+            ])
+        );
+        // only a distance can follow `~`
+        assert_eq!(parse("a~b"), term("a~b"));
+
+        // wildcards are not expanded inside phrases
+        assert_eq!(
+            parse("\"comput* science\""),
+            QueryNode::Phrase {
+                field: None,
+                text: "comput* science".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("", "empty query", 0),
+            ("AND", "expected an operand, found AND", 0),
+            ("a AND", "expected an operand", 5),
+            ("a OR OR b", "expected an operand, found OR", 5),
+            ("NOT", "expected an operand", 3),
+            ("(a OR b", "unmatched (", 0),
+            ("a OR b)", "unmatched )", 6),
+            ("a ()", "expected an operand, found )", 3),
+            ("a \"b c", "unterminated phrase", 2),
+            ("a NEAR/x b", "invalid distance in NEAR/x", 2),
+            ("NEAR/2 b", "expected an operand, found NEAR/2", 0),
+            (
+                "a NEAR/2 (b OR c)",
+                "NEAR/k only applies to terms, phrases and NEAR/k",
+                9,
+            ),
+            (
+                "-a NEAR/2 b",
+                "NEAR/k only applies to terms, phrases and NEAR/k",
+                0,
+            ),
+            ("title: a", "expected a term or a phrase after title:", 0),
+            ("author:\"a b\"", "unknown field author", 0),
+            (
+                "a OR title:*?",
+                "wildcard title:*? has no chars to match",
+                5,
+            ),
+            ("color~3", "fuzzy distance in color~3 is above 2", 0),
+            ("é AND", "expected an operand", 5),
+        ];
+
+        for (query, message, position) in cases {
+            assert_eq!(
+                parse_error(query),
+                QueryError {
+                    message: message.to_string(),
+                    position
+                },
+                "{query}"
+            );
+        }
+
+        assert_eq!(
+            Error::InvalidQuery(parse_error("a AND")).to_string(),
+            "invalid query, expected an operand at position 5"
+        );
+    }
+
+    #[test]
+    fn test_free_queries() {
+        let phrase = |field: Option<usize>, text: &str| QueryNode::Phrase {
+            field,
+            text: text.to_string(),
+        };
+
+        // quotes and field prefixes are read as in boolean queries, operators are words
+        assert_eq!(
+            QueryNode::parse_free("title:\"a b\" c AND (d) NEAR/x -e", &get_field_id).unwrap(),
+            [
+                phrase(Some(0), "a b"),
+                term("c"),
+                term("AND"),
+                term("d"),
+                term("NEAR/x"),
+                term("e"),
+            ]
+        );
+
+        // open phrases run to the end, and unknown fields are words
+        assert_eq!(
+            QueryNode::parse_free("author:\"a b\" \"c d", &get_field_id).unwrap(),
+            [term("author"), phrase(None, "a b"), phrase(None, "c d")]
+        );
+    }
+}
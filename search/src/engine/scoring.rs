@@ -0,0 +1,299 @@
+use std::sync::Arc;
+
+// statistics of a query term over the whole index
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TermStats {
+    pub num_documents: f64,
+    // documents holding the term, and its occurrences in all of them
+    pub document_count: f64,
+    pub collection_frequency: f64,
+    // lengths of every document summed
+    pub collection_len: f64,
+}
+
+// relevance of documents to the terms of a query, the frequency of a term in a document is
+// the sum of its frequency in each field, normalized by the field and weighted, and
+// pruning needs scores that never decrease with the frequency nor grow with the length
+pub trait Scorer: Send + Sync {
+    fn get_field_frequency(&self, frequency: f64, _field_len: f64, _avg_field_len: f64) -> f64 {
+        frequency
+    }
+
+    // zero when the frequency is
+    fn get_term_score(&self, frequency: f64, doc_len: f64, stats: &TermStats) -> f64;
+
+    // added once per document holding any of the terms, never positive
+    fn get_document_score(&self, _doc_len: f64, _num_terms: usize) -> f64 {
+        0.0
+    }
+
+    // occurrences of terms are only counted when needed, as it takes decoding whole lists
+    fn needs_collection_frequency(&self) -> bool {
+        false
+    }
+}
+
+// BM25F: frequencies are normalized by the length of their field before being saturated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    pub k1: f64,
+    pub b: f64,
+}
+
+// BM25 with a lower bound on the score of a term found in a document,
+// so that long documents are not ranked below the ones lacking the term
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25Plus {
+    pub k1: f64,
+    pub b: f64,
+    pub delta: f64,
+}
+
+// logarithmic frequencies weighted by the inverse document frequency
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TfIdf;
+
+// query likelihood with Dirichlet smoothing, ranked as the log probability of the query
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dirichlet {
+    pub mu: f64,
+}
+
+// divergence from randomness InL2: inverse document frequency model,
+// Laplace after effect and frequencies normalized by the document length
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dfr {
+    pub c: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Bm25 {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Default for Bm25Plus {
+    fn default() -> Bm25Plus {
+        Bm25Plus {
+            k1: 1.2,
+            b: 0.75,
+            delta: 1.0,
+        }
+    }
+}
+
+impl Default for Dirichlet {
+    fn default() -> Dirichlet {
+        Dirichlet { mu: 2000.0 }
+    }
+}
+
+impl Default for Dfr {
+    fn default() -> Dfr {
+        Dfr { c: 1.0 }
+    }
+}
+
+impl TermStats {
+    fn get_avg_doc_len(&self) -> f64 {
+        match self.num_documents {
+            0.0 => 0.0,
+            n => self.collection_len / n,
+        }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn get_field_frequency(&self, frequency: f64, field_len: f64, avg_field_len: f64) -> f64 {
+        frequency / get_bm25_norm(field_len, avg_field_len, self.b)
+    }
+
+    fn get_term_score(&self, frequency: f64, _doc_len: f64, stats: &TermStats) -> f64 {
+        let (n, nq) = (stats.num_documents, stats.document_count);
+        let idf = ((n - nq + 0.5) / (nq + 0.5) + 1.0).ln();
+        idf * get_bm25_saturation(frequency, self.k1)
+    }
+}
+
+impl Scorer for Bm25Plus {
+    fn get_field_frequency(&self, frequency: f64, field_len: f64, avg_field_len: f64) -> f64 {
+        frequency / get_bm25_norm(field_len, avg_field_len, self.b)
+    }
+
+    fn get_term_score(&self, frequency: f64, _doc_len: f64, stats: &TermStats) -> f64 {
+        if frequency == 0.0 {
+            return 0.0;
+        }
+        let idf = ((stats.num_documents + 1.0) / stats.document_count.max(1.0)).ln();
+        idf * (get_bm25_saturation(frequency, self.k1) + self.delta)
+    }
+}
+
+impl Scorer for TfIdf {
+    fn get_term_score(&self, frequency: f64, _doc_len: f64, stats: &TermStats) -> f64 {
+        let idf = (1.0 + stats.num_documents / stats.document_count.max(1.0)).ln();
+        (1.0 + frequency).ln() * idf
+    }
+}
+
+impl Scorer for Dirichlet {
+    fn get_term_score(&self, frequency: f64, _doc_len: f64, stats: &TermStats) -> f64 {
+        // the probability of the term in the whole collection
+        let p = stats.collection_frequency.max(1.0) / stats.collection_len.max(1.0);
+        (1.0 + frequency / (self.mu * p)).ln()
+    }
+
+    fn get_document_score(&self, doc_len: f64, num_terms: usize) -> f64 {
+        num_terms as f64 * (self.mu / (doc_len + self.mu)).ln()
+    }
+
+    fn needs_collection_frequency(&self) -> bool {
+        true
+    }
+}
+
+impl Scorer for Dfr {
+    fn get_term_score(&self, frequency: f64, doc_len: f64, stats: &TermStats) -> f64 {
+        let avg_doc_len = stats.get_avg_doc_len();
+        let tfn = frequency * (1.0 + self.c * avg_doc_len / doc_len.max(1.0)).log2();
+        let idf = ((stats.num_documents + 1.0) / (stats.document_count + 0.5)).log2();
+        idf * tfn / (tfn + 1.0)
+    }
+}
+
+// `bm25[:k1:b]`, `bm25+[:k1:b:delta]`, `tfidf`, `dirichlet[:mu]` or `dfr[:c]`
+pub fn parse_scorer(spec: &str) -> Result<Arc<dyn Scorer>, String> {
+    let args: Vec<&str> = spec.trim().split(':').map(str::trim).collect();
+    let params: Vec<f64> = args[1..]
+        .iter()
+        .map(|a| a.parse().ok().filter(|p: &f64| p.is_finite() && *p >= 0.0))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("invalid parameters in `{spec}`"))?;
+
+    match (args[0], &params[..]) {
+        ("bm25", &[]) => Ok(Arc::new(Bm25::default())),
+        ("bm25", &[k1, b]) if k1 > 0.0 && b <= 1.0 => Ok(Arc::new(Bm25 { k1, b })),
+        ("bm25+", &[]) => Ok(Arc::new(Bm25Plus::default())),
+        ("bm25+", &[k1, b, delta]) if k1 > 0.0 && b <= 1.0 => {
+            Ok(Arc::new(Bm25Plus { k1, b, delta }))
+        }
+        ("tfidf", &[]) => Ok(Arc::new(TfIdf)),
+        ("dirichlet", &[]) => Ok(Arc::new(Dirichlet::default())),
+        ("dirichlet", &[mu]) if mu > 0.0 => Ok(Arc::new(Dirichlet { mu })),
+        ("dfr", &[]) => Ok(Arc::new(Dfr::default())),
+        ("dfr", &[c]) => Ok(Arc::new(Dfr { c })),
+        ("bm25" | "bm25+" | "tfidf" | "dirichlet" | "dfr", _) => {
+            Err(format!("invalid parameters in `{spec}`"))
+        }
+        (name, _) => Err(format!("unknown scorer `{name}`")),
+    }
+}
+
+fn get_bm25_norm(field_len: f64, avg_field_len: f64, b: f64) -> f64 {
+    match avg_field_len {
+        0.0 => 1.0,
+        avgdl => 1.0 - b + b * (field_len / avgdl),
+    }
+}
+
+fn get_bm25_saturation(tf: f64, k1: f64) -> f64 {
+    (tf * (k1 + 1.0)) / (tf + k1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_stats() -> TermStats {
+        TermStats {
+            num_documents: 1000.0,
+            document_count: 50.0,
+            collection_frequency: 120.0,
+            collection_len: 100_000.0,
+        }
+    }
+
+    #[test]
+    fn test_scores_are_bounded() {
+        let stats = get_stats();
+        let scorers: Vec<Arc<dyn Scorer>> = ["bm25", "bm25+", "tfidf", "dirichlet", "dfr"]
+            .iter()
+            .map(|s| parse_scorer(s).unwrap())
+            .collect();
+
+        // pruning relies on scores growing with frequencies and shrinking with lengths
+        for scorer in scorers {
+            assert_eq!(scorer.get_term_score(0.0, 100.0, &stats), 0.0);
+            let mut prev = 0.0;
+            for tf in 1..20 {
+                let field_tf = scorer.get_field_frequency(tf as f64, 100.0, 100.0);
+                assert!(scorer.get_field_frequency(tf as f64, 200.0, 100.0) <= field_tf);
+
+                let score = scorer.get_term_score(field_tf, 100.0, &stats);
+                assert!(score > prev);
+                assert!(scorer.get_term_score(field_tf, 200.0, &stats) <= score);
+                prev = score;
+            }
+            assert!(scorer.get_document_score(100.0, 2) <= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_bm25() {
+        let stats = get_stats();
+        let bm25 = Bm25::default();
+
+        // frequencies of average length fields are left as they are
+        assert_eq!(bm25.get_field_frequency(2.0, 100.0, 100.0), 2.0);
+        assert!(bm25.get_field_frequency(2.0, 200.0, 100.0) < 2.0);
+
+        let idf = (950.5f64 / 50.5 + 1.0).ln();
+        let score = bm25.get_term_score(2.0, 100.0, &stats);
+        assert!((score - idf * 2.0 * 2.2 / 3.2).abs() < 1e-12);
+
+        // without length normalization, lengths do not matter
+        let flat = Bm25 { k1: 1.2, b: 0.0 };
+        assert_eq!(flat.get_field_frequency(2.0, 500.0, 100.0), 2.0);
+
+        // BM25+ adds delta to any term found
+        let plus = Bm25Plus::default();
+        let idf = (1001.0f64 / 50.0).ln();
+        assert!(plus.get_term_score(0.01, 100.0, &stats) > idf * plus.delta);
+    }
+
+    #[test]
+    fn test_dirichlet() {
+        let stats = get_stats();
+        let dirichlet = Dirichlet { mu: 1000.0 };
+
+        // the log probability of the query, up to a constant of the collection
+        let p: f64 = 120.0 / 100_000.0;
+        let probability = |tf: f64, dl: f64| ((tf + 1000.0 * p) / (dl + 1000.0)).ln() - p.ln();
+        let score = |tf: f64, dl: f64| {
+            dirichlet.get_term_score(tf, dl, &stats) + dirichlet.get_document_score(dl, 1)
+        };
+        assert!((score(3.0, 250.0) - probability(3.0, 250.0)).abs() < 1e-12);
+        assert!((score(1.0, 40.0) - probability(1.0, 40.0)).abs() < 1e-12);
+        assert!(dirichlet.needs_collection_frequency());
+    }
+
+    #[test]
+    fn test_parse_scorer() {
+        assert!(parse_scorer("bm25:0.9:0.4").is_ok());
+        assert!(parse_scorer("bm25+:1.2:0.75:0.5").is_ok());
+        assert!(parse_scorer(" dirichlet:1500 ").is_ok());
+        assert!(parse_scorer("dfr:2").is_ok());
+
+        assert_eq!(
+            parse_scorer("bm25:1.2").err().unwrap(),
+            "invalid parameters in `bm25:1.2`"
+        );
+        assert!(parse_scorer("bm25:1.2:1.5").is_err());
+        assert!(parse_scorer("dirichlet:-3").is_err());
+        assert!(parse_scorer("tfidf:1").is_err());
+        assert_eq!(
+            parse_scorer("pagerank").err().unwrap(),
+            "unknown scorer `pagerank`"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use super::{
+    documents::Documents,
+    fields::FieldIndex,
+    postings::{DocumentIdsList, PostingsCodec, PostingsCursor, PostingsList},
+    store::DocumentStore,
+    SEGMENTS_EXTENSION, TOMBSTONES_EXTENSION,
+};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Error, Result,
+};
+use fxhash::FxHashSet;
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentInfo {
+    pub id: u32,
+    pub timestamp: u32,
+}
+
+pub struct Segment {
+    pub base_doc_id: u32,
+    // indexed by field id, none when the segment predates the field
+    pub fields: Vec<Option<FieldIndex>>,
+    pub documents: Documents,
+    // none when the index keeps no stored fields
+    pub store: Option<DocumentStore>,
+    pub deleted: FxHashSet<u32>,
+}
+
+impl SegmentInfo {
+    pub fn new(id: u32) -> SegmentInfo {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+
+        SegmentInfo { id, timestamp }
+    }
+
+    pub fn get_path(&self, index_path: &str) -> String {
+        // the first segment keeps the plain index path, so that
+        // indexes built before segments existed are still valid
+        match self.id {
+            0 => index_path.to_string(),
+            id => format!("{index_path}.{id}"),
+        }
+    }
+}
+
+impl Segment {
+    pub fn load_segment(
+        index_path: &str,
+        info: SegmentInfo,
+        base_doc_id: u32,
+        num_fields: usize,
+        codec: PostingsCodec,
+    ) -> Result<Segment> {
+        let path = info.get_path(index_path);
+        let documents = Documents::load_documents(&path)?;
+
+        Ok(Segment {
+            base_doc_id,
+            fields: (0..num_fields)
+                .map(|f| FieldIndex::load_field_index(&path, f, &documents, codec))
+                .collect::<Result<_>>()?,
+            documents,
+            store: DocumentStore::load_document_store(&path)?,
+            deleted: Self::load_tombstones(&path)?.into_iter().collect(),
+        })
+    }
+
+    pub fn write_manifest(segments: &[SegmentInfo], index_path: &str) -> Result<()> {
+        let path = index_path.to_string() + SEGMENTS_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(segments.len() as u32);
+        for s in segments {
+            writer.write_vbyte(s.id);
+            writer.write_vbyte(s.timestamp);
+        }
+
+        writer.flush()
+    }
+
+    pub fn load_manifest(index_path: &str) -> Result<Vec<SegmentInfo>> {
+        let path = index_path.to_string() + SEGMENTS_EXTENSION;
+
+        // indexes without a manifest are made of a single segment
+        if !Path::new(&path).exists() {
+            return Ok(vec![SegmentInfo {
+                id: 0,
+                timestamp: 0,
+            }]);
+        }
+
+        let mut reader = BitsReader::new(&path)?;
+        (0..reader.read_vbyte()?)
+            .map(|_| {
+                Ok(SegmentInfo {
+                    id: reader.read_vbyte()?,
+                    timestamp: reader.read_vbyte()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn write_tombstones(deleted: &FxHashSet<u32>, segment_path: &str) -> Result<()> {
+        let path = segment_path.to_string() + TOMBSTONES_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        let mut deleted: Vec<u32> = deleted.iter().copied().collect();
+        deleted.sort_unstable();
+
+        writer.write_vbyte(deleted.len() as u32);
+        let mut prev = 0;
+        for id in deleted {
+            writer.write_gamma(id - prev);
+            prev = id;
+        }
+
+        writer.flush()
+    }
+
+    pub fn load_tombstones(segment_path: &str) -> Result<Vec<u32>> {
+        let path = segment_path.to_string() + TOMBSTONES_EXTENSION;
+
+        if !Path::new(&path).exists() {
+            return Ok(Vec::new());
+        }
+
+        BitsReader::new(&path)?.read_vbyte_gamma_gap_vector()
+    }
+
+    pub fn is_live(&self, local_doc_id: u32) -> bool {
+        !self.deleted.contains(&local_doc_id)
+    }
+
+    pub fn contains_doc_id(&self, doc_id: u32) -> bool {
+        doc_id >= self.base_doc_id && doc_id < self.base_doc_id + self.documents.get_num_documents()
+    }
+
+    pub fn get_num_live_documents(&self) -> u32 {
+        self.documents
+            .get_num_documents()
+            .saturating_sub(self.deleted.len() as u32)
+    }
+
+    // the lengths of a field over the documents that were not deleted
+    pub fn get_live_lengths_sum(&self, field_id: usize) -> u64 {
+        let Some(field) = self.get_field(field_id) else {
+            return 0;
+        };
+        let deleted_len: u64 = self
+            .deleted
+            .iter()
+            .map(|id| field.get_len(*id) as u64)
+            .sum();
+        field.get_lengths_sum().saturating_sub(deleted_len)
+    }
+
+    pub fn get_field(&self, field_id: usize) -> Option<&FieldIndex> {
+        self.fields.get(field_id).and_then(Option::as_ref)
+    }
+
+    pub fn get_field_len(&self, field_id: usize, doc_id: u32) -> u32 {
+        self.get_field(field_id)
+            .map(|f| f.get_len(doc_id - self.base_doc_id))
+            .unwrap_or_default()
+    }
+
+    pub fn get_stored_fields(&self, local_doc_id: u32) -> Result<Vec<(String, String)>> {
+        match &self.store {
+            Some(store) => store.get_fields(local_doc_id),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn get_term_postings(&self, field_id: usize, term: &str) -> Result<Option<PostingsList>> {
+        let Some((field, index)) = self.get_term_index(field_id, term) else {
+            return Ok(None);
+        };
+        let postings = field.postings.load_postings_list(index)?;
+
+        // ids past the last document can only come from damaged postings
+        self.check_doc_id(postings.last().map(|p| p.document_id))?;
+
+        let postings = postings
+            .into_iter()
+            .filter(|p| self.is_live(p.document_id))
+            .map(|mut p| {
+                p.document_id += self.base_doc_id;
+                p
+            })
+            .collect();
+
+        Ok(Some(postings))
+    }
+
+    pub fn get_term_doc_ids(&self, field_id: usize, term: &str) -> Result<DocumentIdsList> {
+        let Some((field, index)) = self.get_term_index(field_id, term) else {
+            return Ok(DocumentIdsList::new());
+        };
+        let doc_ids = field.postings.load_doc_ids_list(index)?;
+        self.check_doc_id(doc_ids.last().copied())?;
+
+        Ok(doc_ids
+            .into_iter()
+            .filter(|id| self.is_live(*id))
+            .map(|id| id + self.base_doc_id)
+            .collect())
+    }
+
+    pub fn get_term_postings_len(&self, field_id: usize, term: &str) -> Result<usize> {
+        match self.get_term_index(field_id, term) {
+            Some((field, index)) => field.postings.get_postings_len(index),
+            None => Ok(0),
+        }
+    }
+
+    pub fn get_term_cursor(&self, field_id: usize, term: &str) -> Result<Option<PostingsCursor>> {
+        // cursors walk local ids, deleted documents included
+        let Some((field, index)) = self.get_term_index(field_id, term) else {
+            return Ok(None);
+        };
+        let cursor = field.postings.get_cursor(index)?;
+        self.check_doc_id(
+            cursor
+                .get_block_headers()
+                .last()
+                .map(|h| h.last_document_id),
+        )?;
+
+        Ok(Some(cursor))
+    }
+
+    pub fn filter_term_doc_ids(
+        &self,
+        field_id: usize,
+        term: &str,
+        doc_ids: &[u32],
+    ) -> Result<DocumentIdsList> {
+        // only the ids of this segment are looked up, in the postings of the term
+        let Some((field, index)) = self.get_term_index(field_id, term) else {
+            return Ok(DocumentIdsList::new());
+        };
+        let local_doc_ids: Vec<u32> = doc_ids
+            .iter()
+            .filter(|id| self.contains_doc_id(**id))
+            .map(|id| id - self.base_doc_id)
+            .collect();
+
+        Ok(field
+            .postings
+            .filter_doc_ids(index, &local_doc_ids)?
+            .into_iter()
+            .map(|id| id + self.base_doc_id)
+            .collect())
+    }
+
+    fn get_term_index(&self, field_id: usize, term: &str) -> Option<(&FieldIndex, usize)> {
+        let field = self.get_field(field_id)?;
+        field.vocabulary.get_term_index(term).map(|i| (field, i))
+    }
+
+    fn check_doc_id(&self, last_doc_id: Option<u32>) -> Result<()> {
+        match last_doc_id {
+            Some(id) if id >= self.documents.get_num_documents() => {
+                Err(Error::corrupted("document id out of range"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    #[test]
+    fn test_write_and_load_manifest() {
+        let path = create_temporary_file_path("segments_unit");
+
+        assert_eq!(
+            Segment::load_manifest(&path).unwrap(),
+            [SegmentInfo {
+                id: 0,
+                timestamp: 0
+            }]
+        );
+
+        let segments = [
+            SegmentInfo {
+                id: 0,
+                timestamp: 10,
+            },
+            SegmentInfo {
+                id: 3,
+                timestamp: 20,
+            },
+        ];
+        Segment::write_manifest(&segments, &path).unwrap();
+
+        assert_eq!(Segment::load_manifest(&path).unwrap(), segments);
+        assert_eq!(segments[0].get_path("idx"), "idx");
+        assert_eq!(segments[1].get_path("idx"), "idx.3");
+    }
+
+    #[test]
+    fn test_write_and_load_tombstones() {
+        let path = create_temporary_file_path("tombstones_unit");
+
+        assert!(Segment::load_tombstones(&path).unwrap().is_empty());
+
+        let deleted = [7, 1, 3].into_iter().collect();
+        Segment::write_tombstones(&deleted, &path).unwrap();
+
+        assert_eq!(Segment::load_tombstones(&path).unwrap(), [1, 3, 7]);
+    }
+}
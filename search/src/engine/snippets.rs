@@ -0,0 +1,202 @@
+use super::analysis::Token;
+use fxhash::FxHashMap;
+use std::ops::Range;
+
+const FRAGMENT_SEPARATOR: &str = " ... ";
+
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    pub fn build_snippet(
+        text: &str,
+        tokens: &[Token],
+        positions: &[u32],
+        window_len: usize,
+        max_fragments: usize,
+    ) -> Snippet {
+        let mut positions: Vec<usize> = positions
+            .iter()
+            .map(|p| *p as usize)
+            .filter(|p| *p < tokens.len())
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        let mut fragments = Self::select_fragments(tokens, &positions, window_len, max_fragments);
+
+        if fragments.is_empty() && !tokens.is_empty() {
+            fragments.push(0..window_len.min(tokens.len()));
+        }
+
+        fragments.sort_unstable_by_key(|f| f.start);
+
+        let mut snippet = Snippet {
+            text: String::new(),
+            highlights: Vec::new(),
+        };
+
+        for (i, fragment) in fragments.iter().enumerate() {
+            if i > 0 || fragment.start > 0 {
+                snippet.text.push_str(FRAGMENT_SEPARATOR.trim_start());
+            }
+
+            // offsets of the fragment in the original text
+            let base = tokens[fragment.start].start;
+            let end = tokens[fragment.end - 1].end;
+            let offset = snippet.text.len();
+
+            snippet.text.push_str(&text[base..end]);
+
+            positions
+                .iter()
+                .filter(|p| fragment.contains(p))
+                .for_each(|p| {
+                    let t = &tokens[*p];
+                    snippet
+                        .highlights
+                        .push(offset + t.start - base..offset + t.end - base);
+                });
+
+            if i + 1 < fragments.len() {
+                snippet.text.push(' ');
+            } else if fragment.end < tokens.len() {
+                snippet.text.push_str(FRAGMENT_SEPARATOR.trim_end());
+            }
+        }
+
+        snippet
+    }
+
+    pub fn get_parts(&self) -> Vec<(&str, bool)> {
+        let mut parts = Vec::new();
+        let mut last = 0;
+
+        for h in &self.highlights {
+            if h.start > last {
+                parts.push((&self.text[last..h.start], false));
+            }
+            parts.push((&self.text[h.clone()], true));
+            last = h.end;
+        }
+
+        if last < self.text.len() {
+            parts.push((&self.text[last..], false));
+        }
+
+        parts
+    }
+
+    fn select_fragments(
+        tokens: &[Token],
+        positions: &[usize],
+        window_len: usize,
+        max_fragments: usize,
+    ) -> Vec<Range<usize>> {
+        let mut fragments: Vec<Range<usize>> = Vec::new();
+
+        // greedily pick the windows with the most distinct query terms,
+        // breaking ties with the number of matches
+        while fragments.len() < max_fragments {
+            match Self::select_window(tokens, positions, window_len, &fragments) {
+                Some(w) => fragments.push(w),
+                None => break,
+            }
+        }
+
+        fragments
+    }
+
+    fn select_window(
+        tokens: &[Token],
+        positions: &[usize],
+        window_len: usize,
+        fragments: &[Range<usize>],
+    ) -> Option<Range<usize>> {
+        // windows start a bit before each match, so that both their ends only move
+        // forward, and the matches between them are tracked with two pointers
+        let mut counts: FxHashMap<&str, usize> = FxHashMap::default();
+        let (mut lo, mut hi) = (0, 0);
+        let mut best: Option<((usize, usize), Range<usize>)> = None;
+
+        for p in positions {
+            // leave some context before the first match
+            let start = p.saturating_sub(window_len / 4);
+            let w = start..(start + window_len).min(tokens.len());
+
+            while hi < positions.len() && positions[hi] < w.end {
+                *counts
+                    .entry(tokens[positions[hi]].text.as_str())
+                    .or_default() += 1;
+                hi += 1;
+            }
+            while lo < hi && positions[lo] < w.start {
+                let text = tokens[positions[lo]].text.as_str();
+                if let Some(count) = counts.get_mut(text) {
+                    *count -= 1;
+                    if *count == 0 {
+                        counts.remove(text);
+                    }
+                }
+                lo += 1;
+            }
+
+            if fragments.iter().any(|f| Self::overlaps(f, &w)) {
+                continue;
+            }
+
+            // the earliest window wins ties
+            let score = (counts.len(), hi - lo);
+            if best.as_ref().is_none_or(|(s, _)| score > *s) {
+                best = Some((score, w));
+            }
+        }
+
+        best.map(|(_, w)| w)
+    }
+
+    fn overlaps(r1: &Range<usize>, r2: &Range<usize>) -> bool {
+        r1.start < r2.end && r2.start < r1.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::analysis::AnalyzerConfig;
+
+    fn highlighted(snippet: &Snippet) -> String {
+        snippet
+            .get_parts()
+            .iter()
+            .map(|(t, h)| if *h { format!("[{t}]") } else { t.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_snippet() {
+        let text = "one two three four five six seven eight nine ten";
+        let tokens = AnalyzerConfig::default().build_analyzer().analyze(text);
+
+        let snippet = Snippet::build_snippet(text, &tokens, &[4, 5], 4, 1);
+        assert_eq!(highlighted(&snippet), "... four [five] [six] seven ...");
+
+        let snippet = Snippet::build_snippet(text, &tokens, &[0, 9], 4, 2);
+        assert_eq!(highlighted(&snippet), "[one] two three four ... nine [ten]");
+
+        let snippet = Snippet::build_snippet(text, &tokens, &[], 3, 1);
+        assert_eq!(highlighted(&snippet), "one two three ...");
+    }
+
+    #[test]
+    fn test_best_window() {
+        let text = "a b c a x x x x x a b c x x";
+        let tokens = AnalyzerConfig::default().build_analyzer().analyze(text);
+
+        // the second window holds both distinct terms
+        let snippet = Snippet::build_snippet(text, &tokens, &[0, 3, 9, 10], 4, 1);
+        assert_eq!(highlighted(&snippet), "... x [a] [b] c ...");
+    }
+}
@@ -0,0 +1,471 @@
+use super::{fields::Record, SOURCE_EXTENSION};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Error, Result,
+};
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::Path,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourceFormat {
+    JsonLines,
+    Csv,
+}
+
+// how records are streamed out of dump files, recorded in the index for updates
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceConfig {
+    pub format: SourceFormat,
+    pub id_field: String,
+    pub text_fields: Vec<String>,
+}
+
+// why a record could not be read, only an open quote stops reading the file
+enum RecordError {
+    Malformed(String),
+    // the end of the file was reached inside a quoted field
+    Unterminated,
+}
+
+// csv records, which can span several lines when a quoted field holds a newline
+struct CsvReader<R> {
+    lines: Lines<R>,
+    line: usize,
+}
+
+impl SourceFormat {
+    fn get_extension(self) -> &'static str {
+        match self {
+            SourceFormat::JsonLines => "jsonl",
+            SourceFormat::Csv => "csv",
+        }
+    }
+
+    fn get_id(self) -> u32 {
+        match self {
+            SourceFormat::JsonLines => 0,
+            SourceFormat::Csv => 1,
+        }
+    }
+
+    fn from_id(id: u32) -> Result<SourceFormat> {
+        match id {
+            0 => Ok(SourceFormat::JsonLines),
+            1 => Ok(SourceFormat::Csv),
+            _ => Err(Error::Corrupted(format!("unknown source format {id}"))),
+        }
+    }
+}
+
+impl SourceConfig {
+    pub fn parse(spec: &str) -> Result<SourceConfig, String> {
+        // the spec is `format:id_field:field,field`, as in `jsonl:id:title,body`
+        let parts: Vec<&str> = spec.split(':').map(str::trim).collect();
+        let [format, id_field, text_fields] = parts[..] else {
+            return Err(format!("expected `format:id_field:fields`, found `{spec}`"));
+        };
+
+        let format = match format {
+            "jsonl" => SourceFormat::JsonLines,
+            "csv" => SourceFormat::Csv,
+            f => return Err(format!("unknown source format `{f}`")),
+        };
+
+        if id_field.is_empty() {
+            return Err("missing id field".to_string());
+        }
+
+        let text_fields: Vec<String> = text_fields
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if text_fields.is_empty() {
+            return Err("missing text fields".to_string());
+        }
+
+        Ok(SourceConfig {
+            format,
+            id_field: id_field.to_string(),
+            text_fields,
+        })
+    }
+
+    pub fn write_source(&self, index_path: &str) -> Result<()> {
+        let path = index_path.to_string() + SOURCE_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(self.format.get_id());
+        writer.write_str(&self.id_field);
+        writer.write_vbyte(self.text_fields.len() as u32);
+        self.text_fields.iter().for_each(|f| {
+            writer.write_str(f);
+        });
+
+        writer.flush()
+    }
+
+    pub fn load_source(index_path: &str) -> Result<Option<SourceConfig>> {
+        let path = index_path.to_string() + SOURCE_EXTENSION;
+
+        // indexes built from plain files have no source
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+
+        let mut reader = BitsReader::new(&path)?;
+        let format = SourceFormat::from_id(reader.read_vbyte()?)?;
+        let id_field = reader.read_str()?;
+        let text_fields = (0..reader.read_vbyte()?)
+            .map(|_| reader.read_str())
+            .collect::<Result<_>>()?;
+
+        Ok(Some(SourceConfig {
+            format,
+            id_field,
+            text_fields,
+        }))
+    }
+
+    pub fn is_source_file(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|e| e == self.format.get_extension())
+    }
+
+    pub fn get_file_path(doc_path: &str) -> &str {
+        // records are named after their file and the line they start on
+        doc_path
+            .rsplit_once(':')
+            .filter(|(_, line)| !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit()))
+            .map_or(doc_path, |(file, _)| file)
+    }
+
+    // malformed records are skipped, but a quoted field left open swallows every
+    // record after it, and fails the whole file instead
+    pub fn read_records<'a>(&'a self, path: &str) -> Box<dyn Iterator<Item = Result<Record>> + 'a> {
+        let file = match File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(err) => {
+                eprintln!("Error reading file {:?}: {}", path, err);
+                return Box::new(std::iter::empty());
+            }
+        };
+
+        let path = path.to_string();
+        let records: Box<dyn Iterator<Item = (usize, Result<Record, RecordError>)>> =
+            match self.format {
+                SourceFormat::JsonLines => Box::new(
+                    file.lines()
+                        .enumerate()
+                        .map(|(i, l)| (i + 1, l.map_err(|e| e.to_string())))
+                        .filter(|(_, l)| l.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                        .map(|(line, l)| {
+                            let record = l.and_then(|l| self.parse_json_record(&l));
+                            (line, record.map_err(RecordError::Malformed))
+                        }),
+                ),
+                SourceFormat::Csv => self.read_csv_records(file),
+            };
+
+        Box::new(records.filter_map(move |(line, record)| match record {
+            Ok(mut record) => {
+                record.path = format!("{path}:{line}");
+                Some(Ok(record))
+            }
+            Err(RecordError::Malformed(err)) => {
+                eprintln!("Skipping record {path}:{line}, {err}");
+                None
+            }
+            Err(RecordError::Unterminated) => Some(Err(Error::Corrupted(format!(
+                "unterminated quoted field in record {path}:{line}"
+            )))),
+        }))
+    }
+
+    fn parse_json_record(&self, line: &str) -> Result<Record, String> {
+        let value: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+
+        let id = match value.get(&self.id_field) {
+            Some(Value::String(id)) if !id.is_empty() => id.clone(),
+            Some(Value::Number(id)) => id.to_string(),
+            _ => return Err(format!("missing id field `{}`", self.id_field)),
+        };
+
+        let mut record = Record::new("").with_id(&id);
+
+        for name in &self.text_fields {
+            // arrays are repeated fields, and missing fields are just empty
+            let values = match value.get(name) {
+                None | Some(Value::Null) => Vec::new(),
+                Some(Value::Array(values)) => values.iter().collect(),
+                Some(v) => vec![v],
+            };
+
+            for v in values {
+                match v {
+                    Value::String(s) => record = record.with_field(name, s),
+                    Value::Null => {}
+                    v => record = record.with_field(name, &v.to_string()),
+                }
+            }
+        }
+
+        Ok(record)
+    }
+
+    fn read_csv_records<'a, R: BufRead + 'a>(
+        &'a self,
+        reader: R,
+    ) -> Box<dyn Iterator<Item = (usize, Result<Record, RecordError>)> + 'a> {
+        let mut reader = CsvReader {
+            lines: reader.lines(),
+            line: 0,
+        };
+
+        // columns are named by the header
+        let header = match reader.next() {
+            Some((_, Ok(header))) => header,
+            Some((line, Err(err))) => return Box::new(std::iter::once((line, Err(err)))),
+            None => return Box::new(std::iter::empty()),
+        };
+
+        let column = |name: &str| header.iter().position(|h| h.trim() == name);
+        let Some(id_column) = column(&self.id_field) else {
+            let err = format!("missing id column `{}`", self.id_field);
+            return Box::new(std::iter::once((1, Err(RecordError::Malformed(err)))));
+        };
+        let text_columns: Vec<(&'a str, usize)> = self
+            .text_fields
+            .iter()
+            .filter_map(|f| column(f).map(|c| (f.as_str(), c)))
+            .collect();
+
+        Box::new(reader.map(move |(line, fields)| {
+            let record = fields.and_then(|fields| {
+                let id = fields
+                    .get(id_column)
+                    .filter(|id| !id.is_empty())
+                    .ok_or_else(|| {
+                        RecordError::Malformed(format!("missing id field `{}`", self.id_field))
+                    })?;
+
+                let record = text_columns
+                    .iter()
+                    .filter_map(|(name, c)| fields.get(*c).map(|text| (*name, text)))
+                    .fold(Record::new("").with_id(id), |r, (name, text)| {
+                        r.with_field(name, text)
+                    });
+
+                Ok(record)
+            });
+
+            (line, record)
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    // the line the record starts on, with its fields
+    type Item = (usize, Result<Vec<String>, RecordError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // blank lines between records are skipped
+        let mut text = loop {
+            self.line += 1;
+            match self.lines.next()? {
+                Ok(l) if l.trim().is_empty() => continue,
+                Ok(l) => break l,
+                Err(err) => return Some((self.line, Err(RecordError::Malformed(err.to_string())))),
+            }
+        };
+        let start = self.line;
+
+        loop {
+            if let Some(fields) = parse_csv_record(&text) {
+                return Some((start, Ok(fields)));
+            }
+
+            // a quoted field is still open, it continues on the next line
+            self.line += 1;
+            match self.lines.next() {
+                Some(Ok(l)) => {
+                    text.push('\n');
+                    text.push_str(&l);
+                }
+                Some(Err(err)) => {
+                    return Some((start, Err(RecordError::Malformed(err.to_string()))))
+                }
+                None => return Some((start, Err(RecordError::Unterminated))),
+            }
+        }
+    }
+}
+
+fn parse_csv_record(text: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            // quotes inside quoted fields are escaped by doubling them
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (_, c) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return None;
+    }
+
+    fields.push(field);
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    fn fields(record: &Record) -> Vec<(&str, &str)> {
+        record
+            .fields
+            .iter()
+            .map(|(n, t)| (n.as_str(), t.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(
+            SourceConfig::parse("jsonl:id:title, body"),
+            Ok(SourceConfig {
+                format: SourceFormat::JsonLines,
+                id_field: "id".to_string(),
+                text_fields: vec!["title".to_string(), "body".to_string()],
+            })
+        );
+
+        assert!(SourceConfig::parse("xml:id:body").is_err());
+        assert!(SourceConfig::parse("csv:id").is_err());
+        assert!(SourceConfig::parse("csv::body").is_err());
+        assert!(SourceConfig::parse("csv:id:").is_err());
+    }
+
+    #[test]
+    fn test_write_and_load_source() {
+        let path = create_temporary_file_path("source_unit");
+        assert_eq!(SourceConfig::load_source(&path).unwrap(), None);
+
+        let source = SourceConfig::parse("csv:isbn:title,body").unwrap();
+        source.write_source(&path).unwrap();
+        assert_eq!(SourceConfig::load_source(&path).unwrap(), Some(source));
+    }
+
+    #[test]
+    fn test_read_json_records() {
+        let source = SourceConfig::parse("jsonl:id:title,body").unwrap();
+        let records: Vec<Record> = source
+            .read_records("test_data/records/books.jsonl")
+            .collect::<Result<_>>()
+            .unwrap();
+
+        // the record without an id and the malformed line are skipped
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].path, "test_data/records/books.jsonl:1");
+        assert_eq!(records[0].id.as_deref(), Some("b1"));
+        assert_eq!(
+            fields(&records[0]),
+            [
+                ("title", "The Rust Programming Language"),
+                ("body", "Ownership and borrowing explained")
+            ]
+        );
+
+        assert_eq!(records[1].id.as_deref(), Some("42"));
+        assert_eq!(
+            fields(&records[1]),
+            [("body", "Gardening basics"), ("body", "Soil and seeds")]
+        );
+        assert_eq!(records[2].path, "test_data/records/books.jsonl:6");
+    }
+
+    #[test]
+    fn test_read_csv_records() {
+        let source = SourceConfig::parse("csv:isbn:title,body").unwrap();
+        let records: Vec<Record> = source
+            .read_records("test_data/records/books.csv")
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].id.as_deref(), Some("978-1"));
+        assert_eq!(
+            fields(&records[0]),
+            [
+                ("title", "Async Rust"),
+                ("body", "Futures, executors and \"wakers\"")
+            ]
+        );
+
+        // quoted fields can hold newlines
+        assert_eq!(records[1].path, "test_data/records/books.csv:3");
+        assert_eq!(fields(&records[1])[1], ("body", "Cooking pasta\nat home"));
+        assert_eq!(records[2].path, "test_data/records/books.csv:5");
+    }
+
+    #[test]
+    fn test_unterminated_csv_record() {
+        let source = SourceConfig::parse("csv:isbn:title,body").unwrap();
+        let mut records = source.read_records("test_data/broken/unterminated.csv");
+
+        assert_eq!(
+            records.next().unwrap().unwrap().id.as_deref(),
+            Some("978-1")
+        );
+
+        // the open quote would swallow the rest of the file, so it fails on its record
+        let Some(Err(err)) = records.next() else {
+            panic!("expected an unterminated record");
+        };
+        assert_eq!(
+            err.to_string(),
+            "corrupted index, unterminated quoted field in record \
+             test_data/broken/unterminated.csv:3"
+        );
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_csv_record() {
+        assert_eq!(
+            parse_csv_record("a,,\"b,c\""),
+            Some(vec!["a".into(), "".into(), "b,c".into()])
+        );
+        assert_eq!(parse_csv_record("\"open"), None);
+        assert_eq!(
+            parse_csv_record("say \"hi\""),
+            Some(vec!["say \"hi\"".into()])
+        );
+    }
+
+    #[test]
+    fn test_get_file_path() {
+        assert_eq!(SourceConfig::get_file_path("dump.jsonl:12"), "dump.jsonl");
+        assert_eq!(SourceConfig::get_file_path("notes/a.txt"), "notes/a.txt");
+        assert_eq!(SourceConfig::get_file_path("c:/notes:x"), "c:/notes:x");
+    }
+}
@@ -0,0 +1,167 @@
+use super::postings::DocumentIdsList;
+use crate::Result;
+use std::cmp::Ordering;
+
+// an edit makes a query as likely as one held by e^3 times fewer documents
+const EDIT_COST: f64 = 3.0;
+// partial combinations of candidates kept after each term
+const BEAM_WIDTH: usize = 8;
+
+// a term of the query, along with the terms that may have been meant instead
+pub struct TermCandidates {
+    // negated terms are not expected in the documents holding the other ones
+    pub positive: bool,
+    // terms with their number of edits from the query term, and of documents holding them
+    pub candidates: Vec<(String, u32, usize)>,
+}
+
+// one candidate of each term, the documents holding all the positive ones and how likely it is
+#[derive(Clone, Debug)]
+pub struct Correction {
+    pub choices: Vec<usize>,
+    pub hits: usize,
+    pub score: f64,
+}
+
+struct PartialCorrection {
+    choices: Vec<usize>,
+    distance: u32,
+    // of the positive terms chosen so far, none until there is one
+    doc_ids: Option<DocumentIdsList>,
+    negated_score: f64,
+}
+
+impl PartialCorrection {
+    fn get_score(&self, num_documents: usize) -> f64 {
+        // the log probability of finding the positive terms together, and
+        // each negated one alone, lowered by every edit
+        let n = num_documents as f64 + 1.0;
+        let positive_score = match &self.doc_ids {
+            Some(ids) => ((ids.len() as f64 + 1.0) / n).ln(),
+            None => 0.0,
+        };
+        positive_score + self.negated_score - EDIT_COST * self.distance as f64
+    }
+}
+
+// combinations of candidates ranked from the most likely, `get_doc_ids` gives the documents
+// holding a candidate of a term, among the given ones if any
+pub fn rank_corrections<F>(
+    terms: &[TermCandidates],
+    num_documents: usize,
+    mut get_doc_ids: F,
+) -> Result<Vec<Correction>>
+where
+    F: FnMut(usize, &str, Option<&[u32]>) -> Result<DocumentIdsList>,
+{
+    let n = num_documents as f64 + 1.0;
+    let mut beam = vec![PartialCorrection {
+        choices: Vec::new(),
+        distance: 0,
+        doc_ids: None,
+        negated_score: 0.0,
+    }];
+
+    for (i, term) in terms.iter().enumerate() {
+        let mut next = Vec::with_capacity(beam.len() * term.candidates.len());
+        for partial in &beam {
+            for (c, (candidate, distance, document_count)) in term.candidates.iter().enumerate() {
+                let mut choices = partial.choices.clone();
+                choices.push(c);
+
+                let (doc_ids, negated_score) = match (term.positive, &partial.doc_ids) {
+                    (true, Some(ids)) if ids.is_empty() => (Some(Vec::new()), 0.0),
+                    (true, ids) => (Some(get_doc_ids(i, candidate, ids.as_deref())?), 0.0),
+                    (false, ids) => (ids.clone(), ((*document_count as f64 + 1.0) / n).ln()),
+                };
+
+                next.push(PartialCorrection {
+                    choices,
+                    distance: partial.distance + distance,
+                    doc_ids,
+                    negated_score: partial.negated_score + negated_score,
+                });
+            }
+        }
+
+        next.sort_by(|a, b| compare_partials(a, b, num_documents));
+        next.truncate(BEAM_WIDTH);
+        beam = next;
+    }
+
+    Ok(beam
+        .into_iter()
+        .map(|p| Correction {
+            hits: p.doc_ids.as_ref().map_or(0, |ids| ids.len()),
+            score: p.get_score(num_documents),
+            choices: p.choices,
+        })
+        .collect())
+}
+
+fn compare_partials(a: &PartialCorrection, b: &PartialCorrection, n: usize) -> Ordering {
+    // ties are broken by the candidates, closest and most frequent first
+    b.get_score(n)
+        .total_cmp(&a.get_score(n))
+        .then_with(|| a.choices.cmp(&b.choices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::postings::Postings;
+    use std::collections::HashMap;
+
+    fn candidates(positive: bool, candidates: &[(&str, u32, usize)]) -> TermCandidates {
+        TermCandidates {
+            positive,
+            candidates: candidates
+                .iter()
+                .map(|(t, d, c)| (t.to_string(), *d, *c))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_rank_corrections() {
+        // `apple` is the more frequent, but `ample` is found next to `pie`
+        let documents: HashMap<&str, DocumentIdsList> = [
+            ("apple", (0..50).collect()),
+            ("ample", (50..60).collect()),
+            ("pie", (55..70).collect()),
+            ("pies", (60..62).collect()),
+        ]
+        .into_iter()
+        .collect();
+        let get_doc_ids = |_: usize, term: &str, within: Option<&[u32]>| {
+            let ids = documents.get(term).cloned().unwrap_or_default();
+            Ok(match within {
+                Some(within) => Postings::and_operator(within.to_vec(), ids),
+                None => ids,
+            })
+        };
+
+        let terms = [
+            candidates(true, &[("apple", 1, 50), ("ample", 1, 10)]),
+            candidates(true, &[("pie", 0, 15), ("pies", 1, 2)]),
+        ];
+        let corrections = rank_corrections(&terms, 100, get_doc_ids).unwrap();
+        assert_eq!(corrections[0].choices, [1, 0]);
+        assert_eq!(corrections[0].hits, 5);
+        assert!(corrections.windows(2).all(|w| w[0].score >= w[1].score));
+
+        // alone, the most frequent is the most likely
+        let corrections = rank_corrections(&terms[..1], 100, get_doc_ids).unwrap();
+        assert_eq!(corrections[0].choices, [0]);
+        assert_eq!(corrections.len(), 2);
+
+        // negated terms count for their own documents only
+        let terms = [
+            candidates(true, &[("pie", 0, 15)]),
+            candidates(false, &[("apple", 1, 50), ("ample", 1, 10)]),
+        ];
+        let corrections = rank_corrections(&terms, 100, get_doc_ids).unwrap();
+        assert_eq!(corrections[0].choices, [0, 0]);
+        assert_eq!(corrections[0].hits, 15);
+    }
+}
@@ -0,0 +1,264 @@
+use super::{
+    fields::Fields,
+    utils::{read_varint, write_varint},
+    STORED_FIELDS_EXTENSION, STORE_EXTENSION, STORE_OFFSETS_EXTENSION,
+};
+use crate::{
+    disk::{
+        bits_reader::{BitsReader, BitsSource},
+        bits_writer::BitsWriter,
+        compression,
+    },
+    Result,
+};
+use std::path::Path;
+
+// documents are compressed together in blocks, trading access time for ratio
+const STORE_BLOCK_SIZE: usize = 16;
+const ALL_FIELDS: &str = "*";
+
+// fields kept in the document store, none by default
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StoreConfig {
+    pub fields: Vec<String>,
+}
+
+// stored fields of the documents of a segment, with random access by doc id
+pub struct DocumentStore {
+    source: BitsSource,
+    offsets: Vec<u64>,
+}
+
+// writes blocks of documents one at a time, keeping only their offsets in memory
+pub struct DocumentStoreWriter {
+    writer: BitsWriter,
+    offsets_path: String,
+    offsets: Vec<u64>,
+    offset: u64,
+    block: Vec<u8>,
+    block_len: usize,
+}
+
+impl StoreConfig {
+    pub fn all() -> StoreConfig {
+        StoreConfig {
+            fields: vec![ALL_FIELDS.to_string()],
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<StoreConfig, String> {
+        // either `*` or a comma separated list of field names
+        let fields: Vec<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        if let Some(f) = fields
+            .iter()
+            .find(|f| *f != ALL_FIELDS && !Fields::is_valid_name(f))
+        {
+            return Err(format!("invalid field name `{f}`"));
+        }
+
+        Ok(StoreConfig { fields })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.fields.is_empty()
+    }
+
+    pub fn is_stored(&self, field: &str) -> bool {
+        self.fields.iter().any(|f| f == ALL_FIELDS || f == field)
+    }
+
+    pub fn write_store_config(&self, index_path: &str) -> Result<()> {
+        let path = index_path.to_string() + STORED_FIELDS_EXTENSION;
+        let mut writer = BitsWriter::new(&path)?;
+
+        writer.write_vbyte(self.fields.len() as u32);
+        self.fields.iter().for_each(|f| {
+            writer.write_str(f);
+        });
+
+        writer.flush()
+    }
+
+    pub fn load_store_config(index_path: &str) -> Result<StoreConfig> {
+        let path = index_path.to_string() + STORED_FIELDS_EXTENSION;
+
+        // indexes without the file store nothing
+        if !Path::new(&path).exists() {
+            return Ok(StoreConfig::default());
+        }
+
+        let mut reader = BitsReader::new(&path)?;
+        let fields = (0..reader.read_vbyte()?)
+            .map(|_| reader.read_str())
+            .collect::<Result<_>>()?;
+
+        Ok(StoreConfig { fields })
+    }
+}
+
+impl DocumentStoreWriter {
+    pub fn new(output_path: &str) -> Result<DocumentStoreWriter> {
+        Ok(DocumentStoreWriter {
+            writer: BitsWriter::new(&(output_path.to_string() + STORE_EXTENSION))?,
+            offsets_path: output_path.to_string() + STORE_OFFSETS_EXTENSION,
+            offsets: Vec::new(),
+            offset: 0,
+            block: Vec::new(),
+            block_len: 0,
+        })
+    }
+
+    pub fn add_document(&mut self, fields: &[(String, String)]) {
+        // every document is written, even without stored fields, to keep doc ids aligned
+        write_varint(&mut self.block, fields.len());
+        for (name, text) in fields {
+            write_varint(&mut self.block, name.len());
+            self.block.extend_from_slice(name.as_bytes());
+            write_varint(&mut self.block, text.len());
+            self.block.extend_from_slice(text.as_bytes());
+        }
+
+        self.block_len += 1;
+        if self.block_len == STORE_BLOCK_SIZE {
+            self.write_block();
+        }
+    }
+
+    fn write_block(&mut self) {
+        self.offsets.push(self.offset);
+        self.offset += self.writer.write_bytes(&compression::compress(&self.block));
+
+        self.block.clear();
+        self.block_len = 0;
+    }
+
+    pub fn flush(mut self) -> Result<()> {
+        if self.block_len > 0 {
+            self.write_block();
+        }
+        self.writer.flush()?;
+
+        let mut offsets_writer = BitsWriter::new(&self.offsets_path)?;
+        offsets_writer.write_vbyte(self.offsets.len() as u32);
+
+        let mut prev_offset = 0;
+        for offset in self.offsets {
+            offsets_writer.write_gamma((offset - prev_offset) as u32);
+            prev_offset = offset;
+        }
+
+        offsets_writer.flush()
+    }
+}
+
+impl DocumentStore {
+    pub fn load_document_store(input_path: &str) -> Result<Option<DocumentStore>> {
+        let path = input_path.to_string() + STORE_OFFSETS_EXTENSION;
+
+        // segments written without a store
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+
+        let mut offsets_reader = BitsReader::new(&path)?;
+        let mut offset = 0;
+        let offsets = (0..offsets_reader.read_vbyte()?)
+            .map(|_| {
+                offset += offsets_reader.read_gamma()? as u64;
+                Ok(offset)
+            })
+            .collect::<Result<_>>()?;
+
+        let source = BitsReader::map_file(&(input_path.to_string() + STORE_EXTENSION))?;
+
+        Ok(Some(DocumentStore { source, offsets }))
+    }
+
+    pub fn get_fields(&self, doc_id: u32) -> Result<Vec<(String, String)>> {
+        let doc_id = doc_id as usize;
+        let Some(offset) = self.offsets.get(doc_id / STORE_BLOCK_SIZE) else {
+            return Ok(Vec::new());
+        };
+
+        let mut reader = BitsReader::from_source(self.source.clone(), *offset)?;
+        let block = compression::decompress(&reader.read_bytes()?);
+
+        // documents before the requested one in the block are skipped
+        let mut i = 0;
+        for _ in 0..doc_id % STORE_BLOCK_SIZE {
+            for _ in 0..read_varint(&block, &mut i) {
+                let name_len = read_varint(&block, &mut i);
+                i += name_len;
+                let text_len = read_varint(&block, &mut i);
+                i += text_len;
+            }
+        }
+
+        let read_string = |i: &mut usize| {
+            let len = read_varint(&block, i);
+            let s = String::from_utf8_lossy(&block[*i..*i + len]).into_owned();
+            *i += len;
+            s
+        };
+
+        Ok((0..read_varint(&block, &mut i))
+            .map(|_| (read_string(&mut i), read_string(&mut i)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::utils::create_temporary_file_path;
+
+    #[test]
+    fn test_write_and_load_store() {
+        let path = create_temporary_file_path("store_unit");
+
+        let documents: Vec<Vec<(String, String)>> = (0..40)
+            .map(|i| match i % 3 {
+                0 => Vec::new(),
+                _ => vec![
+                    ("title".to_string(), format!("Document {i}")),
+                    ("body".to_string(), "long text ".repeat(i * 50)),
+                ],
+            })
+            .collect();
+
+        let mut writer = DocumentStoreWriter::new(&path).unwrap();
+        documents.iter().for_each(|d| writer.add_document(d));
+        writer.flush().unwrap();
+
+        let store = DocumentStore::load_document_store(&path).unwrap().unwrap();
+        for (i, d) in documents.iter().enumerate() {
+            assert_eq!(&store.get_fields(i as u32).unwrap(), d);
+        }
+        assert!(store.get_fields(100).unwrap().is_empty());
+
+        assert!(DocumentStore::load_document_store(&(path + ".missing"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_store_config() {
+        let path = create_temporary_file_path("store_config_unit");
+        assert!(!StoreConfig::load_store_config(&path).unwrap().is_enabled());
+
+        let config = StoreConfig::parse("title, body").unwrap();
+        assert!(config.is_stored("body"));
+        assert!(!config.is_stored("author"));
+        assert!(StoreConfig::all().is_stored("author"));
+        assert!(StoreConfig::parse("Title").is_err());
+
+        config.write_store_config(&path).unwrap();
+        assert_eq!(StoreConfig::load_store_config(&path).unwrap(), config);
+    }
+}
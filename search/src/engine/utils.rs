@@ -5,6 +5,29 @@ pub fn get_matching_prefix_len(s1: &str, s2: &str) -> usize {
         .count()
 }
 
+pub fn write_varint(output: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        output.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    output.push(n as u8);
+}
+
+pub fn read_varint(input: &[u8], i: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+
+    loop {
+        let b = input[*i];
+        *i += 1;
+        n |= ((b & 0x7f) as usize) << shift;
+        if b < 0x80 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
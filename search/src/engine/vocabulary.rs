@@ -1,7 +1,10 @@
-use std::cmp::min;
+use std::{cmp::min, ops::Range};
 
-use super::{utils, InMemory, VOCABULARY_ALPHA_EXTENSION};
-use crate::disk::{bits_reader::BitsReader, bits_writer::BitsWriter};
+use super::{levenshtein::LevenshteinAutomaton, utils, VOCABULARY_ALPHA_EXTENSION};
+use crate::{
+    disk::{bits_reader::BitsReader, bits_writer::BitsWriter},
+    Result,
+};
 use fxhash::FxHashMap;
 
 #[allow(dead_code)]
@@ -13,18 +16,16 @@ pub struct Vocabulary {
 }
 
 impl Vocabulary {
-    pub fn write_vocabulary(index: &InMemory, output_path: &str) {
+    pub fn write_terms(terms: &[(String, u32)], output_path: &str) -> Result<()> {
         let path = output_path.to_string() + VOCABULARY_ALPHA_EXTENSION;
-        let mut writer = BitsWriter::new(&path);
+        let mut writer = BitsWriter::new(&path)?;
 
-        let vocab = &index.term_index_map;
-
-        writer.write_vbyte(vocab.len() as u32);
+        writer.write_vbyte(terms.len() as u32);
 
         // write all terms with prefix compression
         let mut prev = "";
 
-        for s in vocab.keys() {
+        for (s, _) in terms {
             let p_len = utils::get_matching_prefix_len(prev, s);
             writer.write_gamma(p_len as u32);
             let remaining: String = s.chars().skip(p_len).collect();
@@ -34,18 +35,18 @@ impl Vocabulary {
         }
 
         // write all collection frequencies
-        index.postings.iter().for_each(|p| {
-            writer.write_vbyte(p.len() as u32);
+        terms.iter().for_each(|(_, f)| {
+            writer.write_vbyte(*f);
         });
 
-        writer.flush();
+        writer.flush()
     }
 
-    pub fn load_vocabulary(input_path: &str) -> Vocabulary {
+    pub fn load_vocabulary(input_path: &str) -> Result<Vocabulary> {
         let path = input_path.to_string() + VOCABULARY_ALPHA_EXTENSION;
-        let mut reader = BitsReader::new(&path);
+        let mut reader = BitsReader::new(&path)?;
 
-        let num_terms: u32 = reader.read_vbyte();
+        let num_terms: u32 = reader.read_vbyte()?;
 
         // read prefix compressed terms
         let mut prev = String::new();
@@ -54,19 +55,21 @@ impl Vocabulary {
 
         let term_to_index = (0..num_terms)
             .map(|i| {
-                let p_len = reader.read_gamma();
+                let p_len = reader.read_gamma()?;
                 let prefix: String = prev.chars().take(p_len as usize).collect();
-                let s = prefix + &reader.read_str();
+                let s = prefix + &reader.read_str()?;
                 prev = s.clone();
 
                 index_to_term.push(s.clone());
 
-                (s, i as usize)
+                Ok((s, i as usize))
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         // read frequencies
-        let frequencies = (0..num_terms).map(|_| reader.read_vbyte()).collect();
+        let frequencies = (0..num_terms)
+            .map(|_| reader.read_vbyte())
+            .collect::<Result<_>>()?;
 
         // build trigram index
         let mut trigram_index = FxHashMap::default();
@@ -88,12 +91,12 @@ impl Vocabulary {
             }
         }
 
-        Vocabulary {
+        Ok(Vocabulary {
             term_to_index,
             frequencies,
             index_to_term,
             trigram_index,
-        }
+        })
     }
 
     pub fn get_term_index(&self, term: &str) -> Option<usize> {
@@ -109,13 +112,166 @@ impl Vocabulary {
         }
     }
 
-    pub fn get_term_frequency(&self, term: &str) -> Option<u32> {
-        self.term_to_index.get(term).map(|i| self.frequencies[*i])
+    pub fn get_terms(&self) -> &[String] {
+        &self.index_to_term
+    }
+
+    // terms matching a pattern where `*` is any sequence of chars and `?` a single one,
+    // along with the number of documents holding them
+    pub fn get_wildcard_terms(&self, pattern: &str) -> Vec<(&str, u32)> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let literals: Vec<&[char]> = pattern
+            .split(|c| matches!(c, '*' | '?'))
+            .filter(|l| !l.is_empty())
+            .collect();
+        if literals.is_empty() {
+            return Vec::new();
+        }
+
+        // terms are sorted, the ones sharing the literal prefix are next to each other
+        let prefix: String = literals[0].iter().collect();
+        let range = match pattern[0] {
+            '*' | '?' => 0..self.index_to_term.len(),
+            _ => self.get_prefix_range(&prefix),
+        };
+
+        // leading wildcards are resolved with the trigrams of the literals,
+        // and the few candidates left are matched against the whole pattern
+        let trigrams: Vec<String> = literals
+            .iter()
+            .flat_map(|l| l.windows(3))
+            .map(|t| t.iter().collect())
+            .collect();
+        let candidates: Vec<usize> = match trigrams.is_empty() {
+            true => range.collect(),
+            false => self
+                .get_trigram_candidates(&trigrams)
+                .into_iter()
+                .filter(|i| range.contains(i))
+                .collect(),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|i| {
+                let term: Vec<char> = self.index_to_term[*i].chars().collect();
+                Self::matches_wildcard(&pattern, &term)
+            })
+            .map(|i| (self.index_to_term[i].as_str(), self.frequencies[i]))
+            .collect()
+    }
+
+    // terms within `max_distance` edits of a term, along with their distance
+    // and the number of documents holding them
+    pub fn get_fuzzy_terms(
+        &self,
+        term: &str,
+        max_distance: u32,
+        transpositions: bool,
+    ) -> Vec<(&str, u32, u32)> {
+        let automaton = LevenshteinAutomaton::new(term, max_distance, transpositions);
+
+        // states of the chars of the previous term are kept for the next one sharing them,
+        // and all the terms starting with a prefix no match starts with are skipped
+        let mut states = vec![automaton.start()];
+        let mut prev: Vec<char> = Vec::new();
+        let mut terms = Vec::new();
+        let mut i = 0;
+
+        while i < self.index_to_term.len() {
+            let chars: Vec<char> = self.index_to_term[i].chars().collect();
+            let shared = prev.iter().zip(&chars).take_while(|(a, b)| a == b).count();
+            states.truncate(shared + 1);
+
+            let mut dead_len = None;
+            for (k, c) in chars.iter().enumerate().skip(shared) {
+                let state = automaton.step(&states[k], *c);
+                if !automaton.can_match(&state) {
+                    dead_len = Some(k + 1);
+                    break;
+                }
+                states.push(state);
+            }
+
+            match dead_len {
+                Some(len) => {
+                    let prefix: String = chars[..len].iter().collect();
+                    i = self.get_prefix_range(&prefix).end;
+                    prev = chars[..len - 1].to_vec();
+                }
+                None => {
+                    if let Some(distance) = automaton.get_distance(&states[chars.len()]) {
+                        let term = self.index_to_term[i].as_str();
+                        terms.push((term, distance, self.frequencies[i]));
+                    }
+                    prev = chars;
+                    i += 1;
+                }
+            }
+        }
+
+        terms
+    }
+
+    fn get_prefix_range(&self, prefix: &str) -> Range<usize> {
+        let start = self.index_to_term.partition_point(|t| t.as_str() < prefix);
+        let len = self.index_to_term[start..].partition_point(|t| t.starts_with(prefix));
+        start..start + len
+    }
+
+    fn get_trigram_candidates(&self, trigrams: &[String]) -> Vec<usize> {
+        // lists of terms are sorted, the shortest is looked up in the others
+        let mut lists = Vec::with_capacity(trigrams.len());
+        for trigram in trigrams {
+            match self.trigram_index.get(trigram) {
+                Some(list) => lists.push(list),
+                None => return Vec::new(),
+            }
+        }
+        lists.sort_by_key(|l| l.len());
+
+        lists[0]
+            .iter()
+            .filter(|i| lists[1..].iter().all(|l| l.binary_search(i).is_ok()))
+            .copied()
+            .collect()
+    }
+
+    fn matches_wildcard(pattern: &[char], term: &[char]) -> bool {
+        // on a mismatch, the last `*` takes one more char of the term
+        let (mut p, mut t) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+
+        while t < term.len() {
+            match pattern.get(p) {
+                Some('*') => {
+                    star = Some((p, t));
+                    p += 1;
+                }
+                Some(c) if *c == '?' || *c == term[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                _ => match star {
+                    Some((star_p, star_t)) => {
+                        star = Some((star_p, star_t + 1));
+                        p = star_p + 1;
+                        t = star_t + 1;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        pattern[p..].iter().all(|c| *c == '*')
     }
 
     fn get_closest_index(&self, term: &str) -> Option<usize> {
-        let candidates = (0..term.len() - 2)
-            .map(|i| term[i..i + 3].to_string())
+        // trigrams are taken over chars, as terms are not only ascii
+        let term_chars: Vec<char> = term.chars().collect();
+        let candidates = term_chars
+            .windows(3)
+            .map(|t| t.iter().collect::<String>())
             .filter_map(|t| self.trigram_index.get(&t))
             .flat_map(|v| v.iter());
 
@@ -131,22 +287,20 @@ impl Vocabulary {
     }
 
     fn levenshtein_distance(s1: &str, s2: &str) -> usize {
-        if s1.len() > s2.len() {
+        let n = s1.chars().count() + 1;
+        let m = s2.chars().count() + 1;
+
+        if n > m {
             return Self::levenshtein_distance(s2, s1);
         }
 
-        let n = s1.len() + 1;
-        let m = s2.len() + 1;
-
         if n == 0 {
             return m;
         }
 
         let mut dp = vec![vec![0; m]; n];
 
-        for i in 0..m {
-            dp[0][i] = i;
-        }
+        dp[0] = (0..m).collect();
 
         for (i, c1) in s1.chars().enumerate() {
             dp[i][0] = i;
@@ -165,9 +319,7 @@ impl Vocabulary {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
-    use crate::{engine::postings::Posting, test_utils::utils::create_temporary_file_path};
+    use crate::test_utils::utils::create_temporary_file_path;
 
     use super::*;
 
@@ -175,23 +327,9 @@ mod tests {
     fn test_write_and_load() {
         let dir = create_temporary_file_path("vocab_unit");
 
-        let mut map = BTreeMap::new();
-        map.insert("hello".to_string(), 0);
-        map.insert("world".to_string(), 0);
-
-        let postings = vec![
-            vec![Posting::default()],
-            vec![Posting::default(), Posting::default()],
-        ];
-
-        let index = InMemory {
-            term_index_map: map,
-            postings,
-            documents: Vec::new(),
-        };
-
-        Vocabulary::write_vocabulary(&index, &dir);
-        let loaded_vocabulary = Vocabulary::load_vocabulary(&dir);
+        let terms = [("hello".to_string(), 1), ("world".to_string(), 2)];
+        Vocabulary::write_terms(&terms, &dir).unwrap();
+        let loaded_vocabulary = Vocabulary::load_vocabulary(&dir).unwrap();
 
         assert_eq!(loaded_vocabulary.index_to_term, ["hello", "world"]);
         assert_eq!(loaded_vocabulary.frequencies, [1, 2]);
@@ -205,6 +343,115 @@ mod tests {
         assert_eq!(loaded_vocabulary.spellcheck_term("he"), None);
     }
 
+    #[test]
+    fn test_wildcard_terms() {
+        let dir = create_temporary_file_path("vocab_wildcard_unit");
+
+        let terms: Vec<(String, u32)> = [
+            ("cat", 4),
+            ("cot", 1),
+            ("coat", 2),
+            ("comput", 9),
+            ("computer", 3),
+            ("dog", 5),
+            ("organization", 2),
+            ("realization", 1),
+        ]
+        .iter()
+        .map(|(t, f)| (t.to_string(), *f))
+        .collect();
+        let mut sorted = terms.clone();
+        sorted.sort();
+
+        Vocabulary::write_terms(&sorted, &dir).unwrap();
+        let vocabulary = Vocabulary::load_vocabulary(&dir).unwrap();
+        let get_terms = |pattern: &str| -> Vec<&str> {
+            let mut terms: Vec<&str> = vocabulary
+                .get_wildcard_terms(pattern)
+                .into_iter()
+                .map(|(t, _)| t)
+                .collect();
+            terms.sort();
+            terms
+        };
+
+        assert_eq!(get_terms("comput*"), ["comput", "computer"]);
+        assert_eq!(get_terms("*ization"), ["organization", "realization"]);
+        assert_eq!(get_terms("c?t"), ["cat", "cot"]);
+        assert_eq!(get_terms("c*t"), ["cat", "coat", "comput", "cot"]);
+        assert_eq!(
+            get_terms("*o*"),
+            [
+                "coat",
+                "comput",
+                "computer",
+                "cot",
+                "dog",
+                "organization",
+                "realization"
+            ]
+        );
+        assert_eq!(get_terms("?o?"), ["cot", "dog"]);
+        assert_eq!(get_terms("*zat*n"), ["organization", "realization"]);
+        assert!(get_terms("*xyz*").is_empty());
+        assert!(get_terms("**").is_empty());
+
+        assert_eq!(vocabulary.get_wildcard_terms("comput*")[0], ("comput", 9));
+    }
+
+    #[test]
+    fn test_fuzzy_terms() {
+        let dir = create_temporary_file_path("vocab_fuzzy_unit");
+
+        let mut terms: Vec<(String, u32)> = [
+            ("collar", 2),
+            ("color", 7),
+            ("colorful", 1),
+            ("colour", 3),
+            ("cool", 4),
+            ("dolor", 1),
+            ("lorem", 6),
+            ("oclor", 1),
+        ]
+        .iter()
+        .map(|(t, f)| (t.to_string(), *f))
+        .collect();
+        terms.sort();
+
+        Vocabulary::write_terms(&terms, &dir).unwrap();
+        let vocabulary = Vocabulary::load_vocabulary(&dir).unwrap();
+
+        assert_eq!(
+            vocabulary.get_fuzzy_terms("color", 1, false),
+            [("color", 0, 7), ("colour", 1, 3), ("dolor", 1, 1)]
+        );
+        assert_eq!(
+            vocabulary.get_fuzzy_terms("color", 2, false),
+            [
+                ("collar", 2, 2),
+                ("color", 0, 7),
+                ("colour", 1, 3),
+                ("cool", 2, 4),
+                ("dolor", 1, 1),
+                ("oclor", 2, 1)
+            ]
+        );
+        assert_eq!(
+            vocabulary.get_fuzzy_terms("color", 1, true),
+            [
+                ("color", 0, 7),
+                ("colour", 1, 3),
+                ("dolor", 1, 1),
+                ("oclor", 1, 1)
+            ]
+        );
+        assert_eq!(
+            vocabulary.get_fuzzy_terms("color", 0, true),
+            [("color", 0, 7)]
+        );
+        assert!(vocabulary.get_fuzzy_terms("xyz", 1, true).is_empty());
+    }
+
     #[test]
     fn test_levenshtein_distance() {
         assert_eq!(Vocabulary::levenshtein_distance("hello", "hello"), 0);
@@ -212,5 +459,6 @@ mod tests {
         assert_eq!(Vocabulary::levenshtein_distance("", ""), 0);
         assert_eq!(Vocabulary::levenshtein_distance("cat", "cats"), 1);
         assert_eq!(Vocabulary::levenshtein_distance("abc", "xyz"), 3);
+        assert_eq!(Vocabulary::levenshtein_distance("rivière", "riviere"), 1);
     }
 }
@@ -0,0 +1,237 @@
+use super::{
+    heap::FixedMinHeap,
+    postings::{BlockHeader, PostingsCursor},
+    scoring::{Scorer, TermStats},
+};
+use crate::Result;
+use std::{cmp::min, sync::Arc};
+
+// bounds are compared to scores with some slack, so that rounding
+// errors can never prune a document belonging to the top k
+const BOUND_SLACK: f64 = 1e-9;
+
+// how much a term in a field weighs in the score of a document
+#[derive(Clone)]
+pub struct TermWeight {
+    pub scorer: Arc<dyn Scorer>,
+    pub stats: TermStats,
+    pub field_weight: f64,
+    pub avg_field_len: f64,
+}
+
+// the postings of a query term in one field of one segment
+pub struct TermCursor {
+    pub term_id: usize,
+    pub field_id: usize,
+    base_doc_id: u32,
+    cursor: PostingsCursor,
+    weight: TermWeight,
+    max_score: f64,
+    // cursors on the same document are kept in query order,
+    // so that scores are always summed in the same order
+    order: usize,
+}
+
+pub struct TopDocuments {
+    pub documents: Vec<(u32, f64)>,
+    // documents scored, all of the matching ones when nothing was pruned
+    pub num_matches: usize,
+    pub exhaustive: bool,
+}
+
+impl TermWeight {
+    fn get_block_bound(&self, header: &BlockHeader) -> f64 {
+        // the score only grows with the frequency and decreases with the length,
+        // and a document is never shorter than the field holding the term
+        let min_length = header.min_length as f64;
+        let frequency = self.scorer.get_field_frequency(
+            header.max_frequency as f64,
+            min_length,
+            self.avg_field_len,
+        );
+
+        self.scorer
+            .get_term_score(self.field_weight * frequency, min_length, &self.stats)
+    }
+}
+
+impl TermCursor {
+    pub fn new(
+        term_id: usize,
+        field_id: usize,
+        base_doc_id: u32,
+        cursor: PostingsCursor,
+        weight: TermWeight,
+    ) -> TermCursor {
+        let max_score = cursor
+            .get_block_headers()
+            .iter()
+            .map(|h| weight.get_block_bound(h))
+            .fold(0.0, f64::max);
+
+        TermCursor {
+            term_id,
+            field_id,
+            base_doc_id,
+            cursor,
+            weight,
+            max_score,
+            order: 0,
+        }
+    }
+
+    // u32::MAX once the postings are over
+    pub fn get_document_id(&self) -> u32 {
+        self.cursor
+            .get_document_id()
+            .map_or(u32::MAX, |id| id + self.base_doc_id)
+    }
+
+    pub fn get_frequency(&self) -> u32 {
+        self.cursor.get_frequency()
+    }
+
+    pub fn get_positions(&mut self) -> Result<&[u32]> {
+        self.cursor.get_positions()
+    }
+
+    fn seek_document(&mut self, doc_id: u32) -> Result<()> {
+        self.cursor
+            .seek_document(doc_id.saturating_sub(self.base_doc_id))
+    }
+
+    fn get_block_bound(&self, doc_id: u32) -> Option<(u32, f64)> {
+        // the last document of the block and the best score in it
+        let header = self
+            .cursor
+            .get_block_header(doc_id.saturating_sub(self.base_doc_id))?;
+        Some((
+            header.last_document_id + self.base_doc_id,
+            self.weight.get_block_bound(header),
+        ))
+    }
+}
+
+// document at a time evaluation of the top k, skipping the documents whose
+// score bound can not beat the k-th best one (WAND), first on the maximum
+// score of every list, then on the maximum score of their blocks (Block-Max WAND)
+pub fn get_top_documents<F>(
+    mut cursors: Vec<TermCursor>,
+    num_terms: usize,
+    k: usize,
+    prune: bool,
+    proximity_weight: f64,
+    mut score: F,
+) -> Result<TopDocuments>
+where
+    F: FnMut(u32, &mut [TermCursor]) -> Result<Option<f64>>,
+{
+    cursors
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, c)| c.order = i);
+
+    let mut selector = FixedMinHeap::new(k);
+    let mut num_matches = 0;
+    let mut exhaustive = true;
+
+    // the window score is only sizeable for documents having every term
+    let get_bound = |seen: &[bool], relevance_bound: f64| {
+        let window = match seen.iter().all(|s| *s) {
+            true => 1,
+            false => u32::MAX,
+        };
+        (proximity_weight * (num_terms as f64 / window as f64) + relevance_bound)
+            * (1.0 + BOUND_SLACK)
+    };
+
+    loop {
+        cursors.sort_unstable_by_key(|c| (c.get_document_id(), c.order));
+
+        let first_doc_id = match cursors.first() {
+            Some(c) if c.get_document_id() != u32::MAX => c.get_document_id(),
+            _ => break,
+        };
+
+        // ties go to the lowest id, which is always an already scored document
+        let threshold = match prune {
+            true => selector.get_min_priority(),
+            false => None,
+        };
+
+        // the pivot is the first document whose bound beats the threshold
+        let pivot = match threshold {
+            None => Some(0),
+            Some(threshold) => {
+                let mut seen = vec![false; num_terms];
+                let mut relevance_bound = 0.0;
+                cursors.iter().position(|c| {
+                    seen[c.term_id] = true;
+                    relevance_bound += c.max_score;
+                    get_bound(&seen, relevance_bound) > threshold
+                })
+            }
+        };
+
+        let pivot_doc_id = match pivot {
+            Some(p) if cursors[p].get_document_id() != u32::MAX => cursors[p].get_document_id(),
+            _ => {
+                exhaustive = false;
+                break;
+            }
+        };
+        let end = cursors
+            .iter()
+            .position(|c| c.get_document_id() > pivot_doc_id)
+            .unwrap_or(cursors.len());
+
+        if let Some(threshold) = threshold {
+            // the blocks holding the pivot may still rule it out, along with
+            // every document up to the end of the first of those blocks
+            let mut seen = vec![false; num_terms];
+            let mut relevance_bound = 0.0;
+            let mut next_doc_id = cursors.get(end).map_or(u32::MAX, |c| c.get_document_id());
+            for c in &cursors[..end] {
+                if let Some((last_doc_id, bound)) = c.get_block_bound(pivot_doc_id) {
+                    seen[c.term_id] = true;
+                    relevance_bound += bound;
+                    next_doc_id = min(next_doc_id, last_doc_id.saturating_add(1));
+                }
+            }
+
+            if get_bound(&seen, relevance_bound) <= threshold {
+                exhaustive = false;
+                for c in &mut cursors[..end] {
+                    c.seek_document(next_doc_id)?;
+                }
+                continue;
+            }
+        }
+
+        if first_doc_id < pivot_doc_id {
+            // documents before the pivot can not make it to the top k
+            exhaustive = false;
+            for c in cursors
+                .iter_mut()
+                .take_while(|c| c.get_document_id() < pivot_doc_id)
+            {
+                c.seek_document(pivot_doc_id)?;
+            }
+            continue;
+        }
+
+        if let Some(s) = score(pivot_doc_id, &mut cursors[..end])? {
+            num_matches += 1;
+            selector.push(pivot_doc_id, s);
+        }
+        for c in &mut cursors[..end] {
+            c.seek_document(pivot_doc_id + 1)?;
+        }
+    }
+
+    Ok(TopDocuments {
+        documents: selector.get_sorted_id_priority_pairs(),
+        num_matches,
+        exhaustive,
+    })
+}
@@ -0,0 +1,54 @@
+use crate::engine::{IndexError, QueryError};
+use std::{fmt, io};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io { path: String, source: io::Error },
+    // the manifest does not match the files of the index
+    Index(IndexError),
+    // a file holds data that can not be decoded, such as a truncated bit stream
+    Corrupted(String),
+    InvalidQuery(QueryError),
+}
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn corrupted(message: &str) -> Error {
+        Error::Corrupted(message.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{path}: {source}"),
+            Error::Index(err) => err.fmt(f),
+            Error::Corrupted(message) => write!(f, "corrupted index, {message}"),
+            Error::InvalidQuery(err) => write!(f, "invalid query, {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Index(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IndexError> for Error {
+    fn from(err: IndexError) -> Error {
+        Error::Index(err)
+    }
+}
@@ -1,3 +1,6 @@
 pub mod disk;
 pub mod engine;
+mod error;
 mod test_utils;
+
+pub use error::{Error, Result};
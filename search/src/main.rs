@@ -1,16 +1,30 @@
 use indicatif::HumanDuration;
-use search::engine::{Engine, QueryResult};
-use std::cmp::min;
+use search::engine::{
+    AnalyzerConfig, Engine, IndexConfig, PostingsCodec, QueryResult, SourceConfig, StoreConfig,
+};
 use std::env;
 use std::io::{self, Write};
 use std::process::{exit, Command};
 use std::time::{Duration, Instant};
 
-const NUM_TOP_RESULTS: usize = 10;
-const NUM_RESULTS: usize = 100;
+const PAGE_SIZE: usize = 10;
+const NEXT_PAGE_COMMAND: &str = ":next";
+const PREV_PAGE_COMMAND: &str = ":prev";
+const DEFAULT_MEMORY_BUDGET_MB: usize = 1024;
 
-fn print_results(result: &QueryResult) {
+fn print_results(result: &QueryResult, offset: usize) {
     println!("Search tokens: {:?}", result.query);
+    if result.corrected {
+        println!("Corrected from: {:?}", result.original_query);
+    }
+    if !result.suggestions.is_empty() {
+        let suggestions: Vec<String> = result
+            .suggestions
+            .iter()
+            .map(|s| s.query.join(" "))
+            .collect();
+        println!("Did you mean: {}", suggestions.join(", "));
+    }
 
     if result.documents.is_empty() {
         println!("\nNo documents found\n");
@@ -18,12 +32,33 @@ fn print_results(result: &QueryResult) {
     }
 
     println!(
-        "\nTop {} results:\n",
-        min(result.documents.len(), NUM_TOP_RESULTS)
+        "\nResults {}-{} of {}{}:\n",
+        offset + 1,
+        offset + result.documents.len(),
+        if result.total_hits_exact {
+            ""
+        } else {
+            "about "
+        },
+        result.total_hits,
     );
 
-    for (i, doc) in result.documents.iter().take(NUM_TOP_RESULTS).enumerate() {
-        println!("{:2}. score: {:>5.3}, path: {}", i + 1, doc.score, doc.path);
+    for (i, doc) in result.documents.iter().enumerate() {
+        // records read from dumps are shown with their own id
+        match &doc.external_id {
+            Some(id) => println!(
+                "{:2}. score: {:>5.3}, id: {}",
+                offset + i + 1,
+                doc.score,
+                id
+            ),
+            None => println!(
+                "{:2}. score: {:>5.3}, path: {}",
+                offset + i + 1,
+                doc.score,
+                doc.path
+            ),
+        }
     }
 
     println!(
@@ -56,10 +91,17 @@ fn main() {
     println!("\x1B[1mSearch-rs\x1B[0m\n");
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 3 || args.len() > 5 {
-        println!("Usage: cargo run -r <base_path> <load_or_build> <min_freq (integer)> <max_frequency_perc (float)>
+    if args.len() < 3 || args.len() > 10 {
+        println!("Usage: cargo run -r <base_path> <load_build_update_or_compact> <min_freq (integer)> <max_frequency_perc (float)> [memory_budget_mb (integer)] [analyzer] [source] [stored_fields] [codec (gamma, bitpack, pfor or elias_fano)]
         \nExample:
         \n\t- cargo run -r path/to/docs build 10 0.90
+        \n\t- cargo run -r path/to/docs build 10 0.90 512
+        \n\t- cargo run -r path/to/docs build 10 0.90 512 whitespace,lowercase,ascii_folding,stop_words,stemmer
+        \n\t- cargo run -r path/to/dumps build 10 0.90 512 unicode,lowercase,stemmer jsonl:id:title,body
+        \n\t- cargo run -r path/to/dumps build 10 0.90 512 unicode,lowercase,stemmer jsonl:id:title,body title,body
+        \n\t- cargo run -r path/to/dumps build 10 0.90 512 unicode,lowercase,stemmer jsonl:id:title,body title,body pfor
+        \n\t- cargo run -r path/to/docs update
+        \n\t- cargo run -r path/to/docs compact
         \n\t- cargo run -r path/to/docs load");
         return;
     }
@@ -83,10 +125,85 @@ fn main() {
             return;
         };
 
+        let memory_budget_mb: Result<usize, _> = args
+            .get(5)
+            .map_or(Ok(DEFAULT_MEMORY_BUDGET_MB), |m| m.parse());
+        let Ok(memory_budget_mb) = memory_budget_mb else {
+            println!("Error: memory_budget_mb must be an integer.");
+            return;
+        };
+
+        let analyzer = args
+            .get(6)
+            .map_or(Ok(AnalyzerConfig::default()), |a| AnalyzerConfig::parse(a));
+        let analyzer = match analyzer {
+            Ok(analyzer) => analyzer,
+            Err(err) => {
+                println!("Error: invalid analyzer, {err}.");
+                return;
+            }
+        };
+
+        let source = args.get(7).map(|s| SourceConfig::parse(s)).transpose();
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                println!("Error: invalid source, {err}.");
+                return;
+            }
+        };
+
+        let store = args
+            .get(8)
+            .map_or(Ok(StoreConfig::default()), |s| StoreConfig::parse(s));
+        let store = match store {
+            Ok(store) => store,
+            Err(err) => {
+                println!("Error: invalid stored fields, {err}.");
+                return;
+            }
+        };
+
+        let codec = args.get(9).map_or(Some(PostingsCodec::default()), |c| {
+            PostingsCodec::from_name(c)
+        });
+        let Some(codec) = codec else {
+            println!("Error: codec must be one of gamma, bitpack, pfor or elias_fano.");
+            return;
+        };
+
+        let config = IndexConfig {
+            analyzer,
+            store,
+            codec,
+        };
+
         println!("Start build on directory [{base_path}]\n");
 
         let start_time = Instant::now();
-        Engine::build_engine(base_path, &index_path, max_frequency_perc, min_freq);
+        let built = match source {
+            Some(source) => Engine::build_engine_from_source(
+                base_path,
+                &index_path,
+                &source,
+                max_frequency_perc,
+                min_freq,
+                memory_budget_mb << 20,
+                &config,
+            ),
+            None => Engine::build_engine(
+                base_path,
+                &index_path,
+                max_frequency_perc,
+                min_freq,
+                memory_budget_mb << 20,
+                &config,
+            ),
+        };
+        if let Err(err) = built {
+            println!("Error: can not build index, {err}.");
+            exit(1);
+        }
         let elapsed_time = start_time.elapsed();
 
         println!(
@@ -97,21 +214,69 @@ fn main() {
         exit(0);
     }
 
-    let mut e = Engine::load_index(&index_path);
+    if action == "update" || action == "compact" {
+        println!("Start {action} on directory [{base_path}]\n");
+
+        let start_time = Instant::now();
+        let done = if action == "update" {
+            Engine::update_engine(base_path, &index_path)
+        } else {
+            Engine::compact_engine(&index_path)
+        };
+        if let Err(err) = done {
+            println!("Error: can not {action} index, {err}.");
+            exit(1);
+        }
+        let elapsed_time = start_time.elapsed();
+
+        println!(
+            "\nIndex {action}d in {}",
+            HumanDuration(Duration::from_secs(elapsed_time.as_secs()))
+        );
+
+        exit(0);
+    }
+
+    let e = match Engine::load_index(&index_path) {
+        Ok(e) => e,
+        Err(err) => {
+            println!("Error: can not load index, {err}.");
+            exit(1);
+        }
+    };
 
+    let manifest = e.get_manifest();
     println!(
-        "Loaded search engine for directory: [{base_path}]\n\nWrite a query and press enter.\n"
+        "Loaded search engine for directory: [{base_path}], {} documents and {} terms, {} postings\n\n\
+        Write a query and press enter, {NEXT_PAGE_COMMAND} and {PREV_PAGE_COMMAND} move through the results.\n",
+        manifest.num_documents, manifest.num_terms, manifest.codec,
     );
 
+    let mut query = String::new();
+    let mut offset = 0;
+
     loop {
-        let query = read_line("> ");
+        let input = read_line("> ");
+
+        match input.as_str() {
+            NEXT_PAGE_COMMAND => offset += PAGE_SIZE,
+            PREV_PAGE_COMMAND => offset = offset.saturating_sub(PAGE_SIZE),
+            _ => {
+                query = input;
+                offset = 0;
+            }
+        }
 
         let result = if query.starts_with("b: ") {
-            e.boolean_query(&query.replace("b: ", ""))
+            e.boolean_query(&query.replace("b: ", ""), offset, PAGE_SIZE)
         } else {
-            e.free_query(&query, NUM_RESULTS)
+            e.free_query(&query, offset, PAGE_SIZE)
         };
 
-        print_results(&result);
+        // a bad query is reported, the next one can still be run
+        match result {
+            Ok(result) => print_results(&result, offset),
+            Err(err) => println!("Error: {err}.\n"),
+        }
     }
 }
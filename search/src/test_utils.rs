@@ -1,5 +1,6 @@
 #[cfg(test)]
 pub mod utils {
+    use crate::engine::{Engine, IndexConfig, QueryResult, Record};
     use tempdir::TempDir;
 
     pub fn create_temporary_dir_path() -> String {
@@ -16,4 +17,40 @@ pub mod utils {
         let file_path = temp_dir.path().join(prefix);
         file_path.to_str().unwrap().to_string()
     }
+
+    // records named after their position, holding the fields given for it
+    pub fn create_records<F>(num_records: usize, get_fields: F) -> Vec<Record>
+    where
+        F: Fn(usize) -> Vec<(&'static str, String)>,
+    {
+        (0..num_records)
+            .map(|i| {
+                get_fields(i).iter().fold(
+                    Record::new(&format!("records/{i}")),
+                    |record, (name, text)| record.with_field(name, text),
+                )
+            })
+            .collect()
+    }
+
+    // an index of the records without frequency thresholds, built in memory
+    pub fn build_test_index(records: Vec<Record>, config: &IndexConfig) -> Engine {
+        let index_path = &create_temporary_dir_path();
+        Engine::build_engine_from_records(records, index_path, 1.0, 0, 1 << 20, config).unwrap();
+        Engine::load_index(index_path).unwrap()
+    }
+
+    // an index of the files of a test data folder, built as the records above
+    pub fn build_test_dir_index(input_path: &str, config: &IndexConfig) -> Engine {
+        let index_path = &create_temporary_dir_path();
+        Engine::build_engine(input_path, index_path, 1.0, 0, 1 << 20, config).unwrap();
+        Engine::load_index(index_path).unwrap()
+    }
+
+    // paths of the matching documents, whatever their rank
+    pub fn get_sorted_paths(result: QueryResult) -> Vec<String> {
+        let mut paths: Vec<String> = result.documents.into_iter().map(|d| d.path).collect();
+        paths.sort();
+        paths
+    }
 }
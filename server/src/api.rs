@@ -0,0 +1,301 @@
+use crate::{render_snippet, AppState};
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::info;
+use search::{
+    engine::{parse_scorer, Engine, Language, QueryOptions},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::task::spawn_blocking;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+// deeper pages score every preceding document, and are left to narrower queries
+pub const MAX_OFFSET: usize = 10_000;
+
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Free,
+    Boolean,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchParams {
+    q: Option<String>,
+    filter: Option<String>,
+    #[serde(default)]
+    mode: SearchMode,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    lang: Option<String>,
+    weights: Option<String>,
+    scorer: Option<String>,
+    proximity: Option<f64>,
+    ranked: Option<bool>,
+    transpositions: Option<bool>,
+    spellcheck: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    query: String,
+    original_query: String,
+    corrected_query: Option<String>,
+    corrected: bool,
+    suggestions: Vec<SearchSuggestion>,
+    tokens: Vec<String>,
+    offset: usize,
+    limit: usize,
+    total_hits: usize,
+    total_hits_exact: bool,
+    time_ms: u128,
+    documents: Vec<SearchDocument>,
+}
+
+#[derive(Serialize)]
+struct SearchSuggestion {
+    query: String,
+    hits: usize,
+}
+
+#[derive(Serialize)]
+struct SearchDocument {
+    id: u32,
+    path: String,
+    external_id: Option<String>,
+    score: f64,
+    snippet: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    position: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    status: u16,
+    message: String,
+    // where a malformed query went wrong, in chars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<usize>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            message: message.into(),
+            position: None,
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> ApiError {
+        // malformed queries are the client's fault, anything else is the index's
+        match err {
+            Error::InvalidQuery(err) => ApiError {
+                status: StatusCode::BAD_REQUEST,
+                message: err.message,
+                position: Some(err.position),
+            },
+            err => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error: ErrorBody {
+                status: self.status.as_u16(),
+                message: self.message,
+                position: self.position,
+            },
+        };
+
+        (self.status, Json(body)).into_response()
+    }
+}
+
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    params: Result<Query<SearchParams>, QueryRejection>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    let Query(params) =
+        params.map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.body_text()))?;
+
+    info!("API search request: {params:?}");
+
+    let query = params
+        .q
+        .filter(|q| !q.trim().is_empty())
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "missing query parameter `q`"))?;
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("`limit` must be between 1 and {MAX_LIMIT}"),
+        ));
+    }
+    let offset = params.offset.unwrap_or_default();
+    if offset > MAX_OFFSET {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("`offset` must be at most {MAX_OFFSET}"),
+        ));
+    }
+    let mode = params.mode;
+
+    // boolean queries are filters already
+    let filter = params.filter.filter(|f| !f.trim().is_empty());
+    if filter.is_some() && matches!(mode, SearchMode::Boolean) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "`filter` only applies to free queries",
+        ));
+    }
+
+    let language = params
+        .lang
+        .map(|l| {
+            Language::from_name(&l).ok_or_else(|| {
+                ApiError::new(StatusCode::BAD_REQUEST, format!("unknown language `{l}`"))
+            })
+        })
+        .transpose()?;
+    let field_weights = params
+        .weights
+        .as_deref()
+        .map(parse_field_weights)
+        .transpose()?
+        .unwrap_or_default();
+    let scorer = params
+        .scorer
+        .as_deref()
+        .map(|s| parse_scorer(s).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e)))
+        .transpose()?;
+    let proximity_weight = params
+        .proximity
+        .map(|p| match p.is_finite() && p >= 0.0 {
+            true => Ok(p),
+            false => Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "`proximity` must be a non negative number",
+            )),
+        })
+        .transpose()?;
+
+    let defaults = QueryOptions::default();
+    let options = QueryOptions {
+        language,
+        field_weights,
+        scorer: scorer.unwrap_or(defaults.scorer),
+        proximity_weight: proximity_weight.unwrap_or(defaults.proximity_weight),
+        ranked: params.ranked.unwrap_or(defaults.ranked),
+        transpositions: params.transpositions.unwrap_or(defaults.transpositions),
+        spellcheck: params.spellcheck.unwrap_or(defaults.spellcheck),
+    };
+
+    spawn_blocking(move || run_search(&state.engine, query, filter, mode, &options, offset, limit))
+        .await
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "could not run the query"))?
+        .map(Json)
+}
+
+fn parse_field_weights(weights: &str) -> Result<Vec<(String, f64)>, ApiError> {
+    // weights are comma separated `field:weight` pairs, as in `title:2,body:1`
+    weights
+        .split(',')
+        .map(|w| {
+            w.split_once(':')
+                .and_then(|(f, w)| Some((f.trim().to_string(), w.trim().parse().ok()?)))
+                .filter(|(_, w): &(String, f64)| w.is_finite() && *w >= 0.0)
+                .ok_or_else(|| {
+                    ApiError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("invalid field weight `{w}`"),
+                    )
+                })
+        })
+        .collect()
+}
+
+fn run_search(
+    engine: &Engine,
+    query: String,
+    filter: Option<String>,
+    mode: SearchMode,
+    options: &QueryOptions,
+    offset: usize,
+    limit: usize,
+) -> Result<SearchResponse, ApiError> {
+    let result = match (mode, &filter) {
+        (SearchMode::Free, Some(filter)) => {
+            engine.filtered_query_with_options(&query, filter, offset, limit, options)?
+        }
+        (SearchMode::Free, None) => {
+            engine.free_query_with_options(&query, offset, limit, options)?
+        }
+        (SearchMode::Boolean, _) => {
+            engine.boolean_query_with_options(&query, offset, limit, options)?
+        }
+    };
+
+    let corrected_query = result.corrected.then(|| result.query.join(" "));
+    let suggestions = result
+        .suggestions
+        .iter()
+        .map(|s| SearchSuggestion {
+            query: s.query.join(" "),
+            hits: s.hits,
+        })
+        .collect();
+
+    let documents = result
+        .documents
+        .iter()
+        .map(|d| {
+            Ok(SearchDocument {
+                id: d.id,
+                path: d.path.clone(),
+                external_id: d.external_id.clone(),
+                score: d.score,
+                snippet: render_snippet(engine, &result, d),
+                fields: engine.get_stored_fields(d.id)?.into_iter().collect(),
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(SearchResponse {
+        query,
+        original_query: result.original_query.join(" "),
+        corrected_query,
+        corrected: result.corrected,
+        suggestions,
+        tokens: result.query,
+        offset,
+        limit,
+        total_hits: result.total_hits,
+        total_hits_exact: result.total_hits_exact,
+        time_ms: result.time_ms,
+        documents,
+    })
+}
@@ -1,3 +1,5 @@
+mod api;
+
 use askama::Template;
 use axum::{
     debug_handler,
@@ -9,7 +11,7 @@ use axum::{
 };
 use log::info;
 use lru::LruCache;
-use search::engine::Engine;
+use search::engine::{DocumentResult, Engine, QueryResult};
 use serde::{Deserialize, Serialize};
 use std::{
     env,
@@ -17,13 +19,15 @@ use std::{
     num::NonZeroUsize,
     sync::{Arc, Mutex},
 };
+use tokio::task::spawn_blocking;
 
 const CACHE_SIZE: usize = 10;
+const PAGE_SIZE: usize = 20;
 
 struct AppState {
     index_path: String,
-    engine: Mutex<Engine>,
-    query_cache: Mutex<LruCache<String, QueryResponse>>,
+    engine: Engine,
+    query_cache: Mutex<LruCache<(String, usize), QueryResponse>>,
 }
 
 #[tokio::main]
@@ -42,15 +46,24 @@ async fn main() {
     let base_path = &args[1];
     let index_path = format!("{base_path}/.index/idx");
 
+    let engine = match Engine::load_index(&index_path) {
+        Ok(engine) => engine,
+        Err(err) => {
+            println!("Error: can not load index, {err}.");
+            return;
+        }
+    };
+
     let state = Arc::new(AppState {
         index_path: base_path.clone(),
-        engine: Mutex::new(Engine::load_index(&index_path)),
+        engine,
         query_cache: Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap())),
     });
 
     let app = Router::new()
         .route("/", get(root))
         .route("/query", post(post_query))
+        .route("/api/v1/search", get(api::search))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -95,14 +108,25 @@ async fn root(State(state): State<Arc<AppState>>) -> impl IntoResponse {
 #[derive(Deserialize, Debug)]
 struct QueryRequest {
     query: String,
+    #[serde(default)]
+    page: usize,
 }
 
 #[derive(Template, Clone)]
 #[template(path = "query.html")]
 struct QueryResponse {
+    query: String,
     tokens: Vec<String>,
+    original_tokens: Vec<String>,
+    corrected: bool,
+    suggestions: Vec<String>,
     time_ms: u128,
     documents: Vec<Document>,
+    total_hits: usize,
+    total_hits_exact: bool,
+    page: usize,
+    has_next_page: bool,
+    error: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
@@ -110,7 +134,7 @@ struct Document {
     id: u32,
     score: f64,
     path: String,
-    content: String,
+    snippet: String,
 }
 
 #[debug_handler]
@@ -118,21 +142,75 @@ async fn post_query(
     State(state): State<Arc<AppState>>,
     Form(payload): Form<QueryRequest>,
 ) -> impl IntoResponse {
-    info!("Query request: {}", payload.query);
+    info!("Query request: {}, page {}", payload.query, payload.page);
 
-    let mut query_cache = state.query_cache.lock().unwrap();
+    let cache_key = (payload.query.clone(), payload.page);
 
-    if let Some(cached_result) = query_cache.get(&payload.query) {
+    if let Some(cached_result) = state.query_cache.lock().unwrap().get(&cache_key) {
         info!("Cache hit for query: {}", payload.query);
         return HtmlTemplate(cached_result.clone());
     }
 
-    let mut engine = state.engine.lock().unwrap();
+    // queries are blocking work, run them on the blocking pool so
+    // that concurrent requests do not starve the async workers
+    let query_state = state.clone();
+    let query = payload.query.clone();
+    let page = payload.page;
+    let response = spawn_blocking(move || run_query(&query_state.engine, query, page))
+        .await
+        .unwrap_or_else(|_| {
+            QueryResponse::error(payload.query.clone(), page, "could not run the query")
+        });
+
+    // failed queries are not cached, the index may be fixed meanwhile
+    if response.error.is_none() {
+        info!("Caching query: {}", payload.query);
+        state
+            .query_cache
+            .lock()
+            .unwrap()
+            .put(cache_key, response.clone());
+    }
+
+    HtmlTemplate(response)
+}
+
+impl QueryResponse {
+    fn error(query: String, page: usize, message: impl Into<String>) -> QueryResponse {
+        QueryResponse {
+            query,
+            tokens: Vec::new(),
+            original_tokens: Vec::new(),
+            corrected: false,
+            suggestions: Vec::new(),
+            documents: Vec::new(),
+            time_ms: 0,
+            total_hits: 0,
+            total_hits_exact: true,
+            page,
+            has_next_page: false,
+            error: Some(message.into()),
+        }
+    }
+}
 
-    let query_result = if payload.query.starts_with("b: ") {
-        engine.boolean_query(&payload.query.replace("b: ", ""))
+fn run_query(engine: &Engine, query: String, page: usize) -> QueryResponse {
+    // pages go as deep as the API offsets
+    let Some(offset) = page
+        .checked_mul(PAGE_SIZE)
+        .filter(|offset| *offset <= api::MAX_OFFSET)
+    else {
+        return QueryResponse::error(query, page, format!("page {page} is out of range"));
+    };
+
+    let query_result = if query.starts_with("b: ") {
+        engine.boolean_query(&query.replace("b: ", ""), offset, PAGE_SIZE)
     } else {
-        engine.free_query(&payload.query, 100)
+        engine.free_query(&query, offset, PAGE_SIZE)
+    };
+    let query_result = match query_result {
+        Ok(result) => result,
+        Err(err) => return QueryResponse::error(query, page, err.to_string()),
     };
 
     let documents = query_result
@@ -141,23 +219,65 @@ async fn post_query(
         .map(|r| Document {
             id: r.id,
             score: r.score,
-            path: r.path.clone(),
-            content: read_file_content(r.path.clone()),
+            // records read from dumps are shown with their own id
+            path: r.external_id.clone().unwrap_or_else(|| r.path.clone()),
+            snippet: render_snippet(engine, &query_result, r).unwrap_or_default(),
         })
         .collect();
 
-    let response = QueryResponse {
+    let suggestions = query_result
+        .suggestions
+        .iter()
+        .map(|s| s.query.join(" "))
+        .collect();
+
+    QueryResponse {
+        query,
         tokens: query_result.query,
+        original_tokens: query_result.original_query,
+        corrected: query_result.corrected,
+        suggestions,
         documents,
         time_ms: query_result.time_ms,
+        total_hits: query_result.total_hits,
+        total_hits_exact: query_result.total_hits_exact,
+        page,
+        has_next_page: offset.saturating_add(PAGE_SIZE) < query_result.total_hits,
+        error: None,
+    }
+}
+
+fn render_snippet(
+    engine: &Engine,
+    query: &QueryResult,
+    document: &DocumentResult,
+) -> Option<String> {
+    // the stored text is used when the index keeps it, so that the source files are not needed
+    let text = match engine.get_stored_text(document.id).ok().flatten() {
+        Some(text) => text,
+        None => read_to_string(&document.path).ok()?,
     };
 
-    info!("Caching query: {}", payload.query);
-    query_cache.put(payload.query.clone(), response.clone());
+    let html = engine
+        .get_snippet(&text, query, document)
+        .get_parts()
+        .iter()
+        .map(|(part, highlighted)| {
+            if *highlighted {
+                format!("<mark>{}</mark>", escape_html(part))
+            } else {
+                escape_html(part)
+            }
+        })
+        .collect();
 
-    HtmlTemplate(response)
+    Some(html)
 }
 
-fn read_file_content(path: String) -> String {
-    read_to_string(path).expect("error while reading file")
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
 }